@@ -52,8 +52,14 @@ fn main() {
         let chk = hf2::info(&device);
         println!("{:?}", chk);
 
-        // let chk = hf2::dmesg(&device);
-        // println!("{:?}", chk);
+        // reset counters, then the crash report from the last reset if the device has crashed
+        match hf2::dmesg(&device) {
+            Ok(report) => match split_crash_report(&report.logs) {
+                (resets, Some(crash)) => println!("{}crash report:\n{}", resets, crash),
+                (resets, None) => println!("{}no crash report", resets),
+            },
+            Err(e) => println!("{:?}", e),
+        }
 
         // let chk = hf2::reset_into_app(&device);
         // println!("{:?}", chk);
@@ -70,7 +76,14 @@ fn main() {
         for path in paths {
             let name = path.as_ref().unwrap().file_name();
 
-            if name.to_str().unwrap_or_default().ends_with(".paw") {
+            // .paw images, .evo species definitions, .map tile maps, .fnt fonts and .sng songs
+            let name_str = name.to_str().unwrap_or_default();
+            if name_str.ends_with(".paw")
+                || name_str.ends_with(".evo")
+                || name_str.ends_with(".map")
+                || name_str.ends_with(".fnt")
+                || name_str.ends_with(".sng")
+            {
                 let path = path.unwrap().path();
                 let f = fs::File::open(path.clone()).unwrap();
                 let size = f.metadata().unwrap().len();
@@ -98,3 +111,15 @@ fn main() {
         }
     }
 }
+
+// dmesg text starts with the reset counters, a crash report starts with a panic or hard fault line
+fn split_crash_report(logs: &str) -> (&str, Option<&str>) {
+    let mut offset = 0;
+    for line in logs.split_inclusive('\n') {
+        if line.starts_with("panic at ") || line.starts_with("hard fault") {
+            return (&logs[..offset], Some(&logs[offset..]));
+        }
+        offset += line.len();
+    }
+    return (logs, None);
+}
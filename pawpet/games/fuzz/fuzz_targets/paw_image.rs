@@ -0,0 +1,47 @@
+#![no_main]
+
+// cargo +nightly fuzz run paw_image
+// parses arbitrary bytes as a .paw file and decodes every frame of the ones that are accepted
+
+use games::image::{PawImage, PawImageHeader};
+use libfuzzer_sys::fuzz_target;
+use pawdevicetraits::{DisplayDevice, DisplayUpdateStats};
+
+struct CheckedDisplay;
+
+impl DisplayDevice for CheckedDisplay {
+    fn draw_pixel(&mut self, x: u8, y: u8, _color: bool) {
+        assert!(x < 64 && y < 64);
+    }
+
+    fn update(&mut self) -> bool {
+        return true;
+    }
+
+    fn set_rotation(&mut self, _rotation: u8) {}
+
+    fn update_stats(&self) -> DisplayUpdateStats {
+        return DisplayUpdateStats::default();
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let header = match PawImageHeader::parse(data) {
+        Ok(header) => header,
+        Err(_) => return,
+    };
+
+    // images only keep static data, the image is dropped before the input
+    let data: &'static [u8] = unsafe { core::mem::transmute(data) };
+
+    let mut image = PawImage::new(Some(data));
+    assert_eq!(image.tile_count(), header.tile_count);
+
+    let mut display = CheckedDisplay;
+    for frame in 0..=header.tile_count {
+        image.set_frame(frame);
+        image.set_colors(true, false, Some(true));
+        image.draw(&mut display, -4, 60);
+        image.draw(&mut display, 0, 0);
+    }
+});
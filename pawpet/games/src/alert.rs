@@ -0,0 +1,213 @@
+/*!
+ * Attention alerts, sounds while asleep when a need drops below the threshold.
+ * Re-alerts with an increasing backoff until acknowledged by a button press. An alert that
+ * comes due during quiet hours waits for them to end, the hours come from the settings.
+ */
+
+use crate::pet::Need;
+use crate::pet::PetNeeds;
+use crate::sound::Sfx;
+use crate::{SECONDS_PER_DAY, SECONDS_PER_HOUR};
+
+use pawdevicetraits::SysTimerDevice as SysTimer;
+use pawdevicetraits::ToneDevice as Tone;
+
+pub const NEED_ALERT_THRESHOLD: u8 = 25;
+pub const NEEDS_CHECK_INTERVAL: u32 = 15 * 60;
+
+const ALERT_BACKOFF_MIN: u32 = 5 * 60;
+const ALERT_BACKOFF_MAX: u32 = 2 * 60 * 60;
+
+pub struct AlertState {
+    active: Option<Need>,
+    next_alert: u32,
+    backoff: u32,
+    quiet_hours: Option<(u8, u8)>, // start hour, end hour
+}
+
+impl AlertState {
+    pub fn new() -> Self {
+        Self {
+            active: None,
+            next_alert: 0,
+            backoff: ALERT_BACKOFF_MIN,
+            quiet_hours: None,
+        }
+    }
+
+    pub fn set_quiet_hours(&mut self, quiet_hours: Option<(u8, u8)>) {
+        self.quiet_hours = quiet_hours;
+    }
+
+    pub fn is_active(&self) -> bool {
+        return self.active.is_some();
+    }
+
+    // returns the need to alert for if an alert is due now
+    pub fn update(&mut self, needs: &PetNeeds, now: u32) -> Option<Need> {
+        let need = needs.most_urgent(NEED_ALERT_THRESHOLD);
+
+        if need.is_none() {
+            self.active = None;
+            self.backoff = ALERT_BACKOFF_MIN;
+            return None;
+        }
+
+        // newly crossed threshold, alert right away
+        if self.active != need {
+            self.active = need;
+            self.next_alert = now;
+            self.backoff = ALERT_BACKOFF_MIN;
+        }
+
+        if self.is_quiet(now) {
+            return None;
+        }
+
+        if now >= self.next_alert {
+            self.next_alert = now + self.backoff;
+            self.backoff = (self.backoff * 2).min(ALERT_BACKOFF_MAX);
+            return self.active;
+        }
+
+        return None;
+    }
+
+    // user responded, reset backoff and hold off on alerting again
+    pub fn acknowledge(&mut self, now: u32) {
+        if self.active.is_some() {
+            self.next_alert = now + ALERT_BACKOFF_MAX;
+            self.backoff = ALERT_BACKOFF_MIN;
+        }
+    }
+
+    // clock was set, keep the pending alert the same distance away
+    pub fn shift_time(&mut self, delta: i32) {
+        self.next_alert = self.next_alert.wrapping_add(delta as u32);
+    }
+
+    // rtc time of the next needs check
+    pub fn next_check(&self, now: u32) -> u32 {
+        let mut at = now + NEEDS_CHECK_INTERVAL;
+        if self.active.is_some() {
+            let mut alert = self.next_alert.max(now + 1);
+            if self.is_quiet(now) {
+                alert = alert.max(self.quiet_end(now));
+            }
+            at = at.min(alert);
+        }
+        return at;
+    }
+
+    // rtc time the current quiet hours end
+    fn quiet_end(&self, now: u32) -> u32 {
+        if let Some((_, end)) = self.quiet_hours {
+            let mut at = now - now % SECONDS_PER_DAY + end as u32 * SECONDS_PER_HOUR;
+            if at <= now {
+                at += SECONDS_PER_DAY;
+            }
+            return at;
+        }
+        return now;
+    }
+
+    pub fn is_quiet(&self, now: u32) -> bool {
+        if let Some((start, end)) = self.quiet_hours {
+            let hour = ((now % SECONDS_PER_DAY) / SECONDS_PER_HOUR) as u8;
+            if start <= end {
+                return hour >= start && hour < end;
+            }
+            // wraps past midnight
+            return hour >= start || hour < end;
+        }
+        return false;
+    }
+
+    // blocking, only played from a wake event while the frame loop is asleep
+    pub fn play(&self, tone: &impl Tone, timer: &mut impl SysTimer) {
+        Sfx::Alert.play_blocking(tone, timer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u32 = 3600;
+
+    // hungry, the other needs full
+    fn hungry(now: u32) -> PetNeeds {
+        let mut needs = PetNeeds::new();
+        needs.update(now);
+        needs.update(now + 90 * 600);
+        return needs;
+    }
+
+    #[test]
+    fn backoff_doubles_until_acknowledged() {
+        let now = 12 * HOUR;
+        let needs = hungry(now);
+        let mut alerts = AlertState::new();
+
+        assert_eq!(alerts.update(&needs, now), Some(Need::Hunger));
+        assert_eq!(alerts.update(&needs, now + 1), None);
+        assert_eq!(alerts.next_check(now), now + ALERT_BACKOFF_MIN);
+
+        assert_eq!(alerts.update(&needs, now + ALERT_BACKOFF_MIN), Some(Need::Hunger));
+        let next = now + 3 * ALERT_BACKOFF_MIN;
+        assert_eq!(alerts.update(&needs, next - 1), None);
+        assert_eq!(alerts.update(&needs, next), Some(Need::Hunger));
+
+        alerts.acknowledge(next);
+        assert_eq!(alerts.update(&needs, next + ALERT_BACKOFF_MAX - 1), None);
+        assert_eq!(alerts.update(&needs, next + ALERT_BACKOFF_MAX), Some(Need::Hunger));
+    }
+
+    #[test]
+    fn recovered_need_clears_alert() {
+        let now = 12 * HOUR;
+        let mut needs = hungry(now);
+        let mut alerts = AlertState::new();
+
+        assert_eq!(alerts.update(&needs, now), Some(Need::Hunger));
+        needs.satisfy(Need::Hunger, 50);
+        assert_eq!(alerts.update(&needs, now + HOUR), None);
+        assert!(!alerts.is_active());
+        assert_eq!(alerts.next_check(now + HOUR), now + HOUR + NEEDS_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn quiet_hours_hold_the_alert() {
+        let now = 23 * HOUR;
+        let needs = hungry(now);
+        let mut alerts = AlertState::new();
+        alerts.set_quiet_hours(Some((22, 7)));
+
+        assert!(alerts.is_quiet(now));
+        assert_eq!(alerts.update(&needs, now), None);
+        assert!(alerts.is_active());
+        assert_eq!(alerts.update(&needs, now + 5 * HOUR), None);
+
+        // the next check is not before quiet hours end, at 07:00
+        let morning = now + 8 * HOUR;
+        assert_eq!(alerts.next_check(now), now + NEEDS_CHECK_INTERVAL);
+        assert_eq!(alerts.next_check(morning - 600), morning);
+
+        // not pushed back or backed off by the quiet night
+        assert!(!alerts.is_quiet(morning));
+        assert_eq!(alerts.update(&needs, morning), Some(Need::Hunger));
+        assert_eq!(alerts.next_check(morning), morning + ALERT_BACKOFF_MIN);
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let mut alerts = AlertState::new();
+        alerts.set_quiet_hours(Some((13, 15)));
+        assert!(!alerts.is_quiet(12 * HOUR));
+        assert!(alerts.is_quiet(13 * HOUR));
+        assert!(!alerts.is_quiet(15 * HOUR));
+
+        alerts.set_quiet_hours(None);
+        assert!(!alerts.is_quiet(23 * HOUR));
+    }
+}
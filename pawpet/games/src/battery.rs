@@ -0,0 +1,325 @@
+/*!
+ * Fuel gauge for 2 cells in series, voltages are in hundredths of a volt as returned by
+ * BatteryMonitorDevice::read (0 -> 400).
+ *
+ * Discharge curves are non-linear, alkaline drops steadily while NiMH spends most of its
+ * capacity on a flat plateau around 2.4v. Each profile maps voltage under load to remaining
+ * capacity, linear interpolation between points.
+ *
+ * 2.0v is the device cutoff, the display stops working at 1.9v so every profile is empty at 2.0v.
+ */
+
+use crate::settings::BatteryChemistry;
+
+// low battery policy thresholds, filtered centivolts
+pub const BATTERY_WARN: u16 = 212;
+pub const BATTERY_WARN_CLEAR: u16 = 216; // a cell hovering at the warning level warns once
+pub const BATTERY_CRITICAL: u16 = 204; // leaves margin above 2.0v to draw the replace screen
+pub const BATTERY_RECOVER: u16 = 212; // needed to leave the critical lockout (and to boot)
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BatteryLevel {
+    Ok,
+    Low,
+    Critical,
+}
+
+pub struct DischargeProfile {
+    // (centivolts, percent) ordered from full to empty
+    pub points: &'static [(u16, u8)],
+    // runtime from a full charge at typical awake/sleep usage
+    pub runtime_hours: u16,
+}
+
+pub static ALKALINE_PROFILE: DischargeProfile = DischargeProfile {
+    points: &[
+        (300, 100),
+        (290, 90),
+        (276, 75),
+        (262, 55),
+        (250, 40),
+        (238, 25),
+        (226, 12),
+        (212, 4),
+        (200, 0),
+    ],
+    runtime_hours: 700,
+};
+
+pub static NIMH_PROFILE: DischargeProfile = DischargeProfile {
+    points: &[
+        (276, 100),
+        (266, 92),
+        (254, 80),
+        (248, 60),
+        (244, 40),
+        (238, 22),
+        (230, 10),
+        (218, 3),
+        (200, 0),
+    ],
+    runtime_hours: 550,
+};
+
+pub fn profile(chemistry: BatteryChemistry) -> &'static DischargeProfile {
+    match chemistry {
+        BatteryChemistry::Alkaline => &ALKALINE_PROFILE,
+        BatteryChemistry::NiMH => &NIMH_PROFILE,
+    }
+}
+
+impl DischargeProfile {
+    pub fn percent(&self, centivolts: u16) -> u8 {
+        let points = self.points;
+
+        if centivolts >= points[0].0 {
+            return points[0].1;
+        }
+
+        for i in 1..points.len() {
+            let (v_hi, p_hi) = points[i - 1];
+            let (v_lo, p_lo) = points[i];
+
+            if centivolts >= v_lo {
+                let span = (v_hi - v_lo) as u32;
+                let above = (centivolts - v_lo) as u32;
+                return p_lo + ((p_hi - p_lo) as u32 * above / span) as u8;
+            }
+        }
+
+        return 0;
+    }
+}
+
+// icon frames, full to empty, with the percent needed to reach each frame
+const FRAME_PERCENT: [u8; 4] = [75, 50, 25, 5];
+pub const EMPTY_FRAME: u8 = 4;
+// percent the estimate has to move past a frame boundary before the frame changes
+const FRAME_HYSTERESIS: u8 = 3;
+
+// exponential moving average, new samples are weighted 1/FILTER_WEIGHT
+const FILTER_SHIFT: u32 = 4; // fixed point fraction bits
+const FILTER_WEIGHT: u32 = 16;
+
+pub struct FuelGauge {
+    profile: &'static DischargeProfile,
+    filtered: Option<u32>, // centivolts << FILTER_SHIFT
+    frame: u8,
+}
+
+impl FuelGauge {
+    pub fn new(chemistry: BatteryChemistry) -> Self {
+        Self {
+            profile: profile(chemistry),
+            filtered: None,
+            frame: 0,
+        }
+    }
+
+    pub fn set_chemistry(&mut self, chemistry: BatteryChemistry) {
+        self.profile = profile(chemistry);
+        // re-evaluate the frame without hysteresis against the new curve
+        self.frame = self.frame_for(self.percent());
+    }
+
+    pub fn update(&mut self, centivolts: u16) {
+        let sample = (centivolts as u32) << FILTER_SHIFT;
+
+        let filtered = match self.filtered {
+            Some(f) => f + sample / FILTER_WEIGHT - f / FILTER_WEIGHT,
+            // first reading seeds the filter so boot does not ramp up from 0
+            None => {
+                self.filtered = Some(sample);
+                self.frame = self.frame_for(self.profile.percent(centivolts));
+                return;
+            }
+        };
+        self.filtered = Some(filtered);
+
+        let percent = self.percent();
+        let frame = self.frame_for(percent);
+
+        if frame > self.frame {
+            // draining, move down once clearly below the boundary of the current frame
+            let boundary = FRAME_PERCENT[self.frame as usize];
+            if percent + FRAME_HYSTERESIS < boundary || frame > self.frame + 1 {
+                self.frame = frame;
+            }
+        } else if frame < self.frame {
+            // recovering (battery swapped or load removed), move up once clearly above
+            let boundary = FRAME_PERCENT[frame as usize];
+            if percent >= boundary + FRAME_HYSTERESIS || frame + 1 < self.frame {
+                self.frame = frame;
+            }
+        }
+    }
+
+    fn frame_for(&self, percent: u8) -> u8 {
+        for (frame, min) in FRAME_PERCENT.iter().enumerate() {
+            if percent >= *min {
+                return frame as u8;
+            }
+        }
+        return EMPTY_FRAME;
+    }
+
+    // filtered voltage in centivolts
+    pub fn voltage(&self) -> u16 {
+        return (self.filtered.unwrap_or(0) >> FILTER_SHIFT) as u16;
+    }
+
+    pub fn percent(&self) -> u8 {
+        return self.profile.percent(self.voltage());
+    }
+
+    pub fn runtime_hours(&self) -> u32 {
+        return self.profile.runtime_hours as u32 * self.percent() as u32 / 100;
+    }
+
+    pub fn frame(&self) -> u8 {
+        return self.frame;
+    }
+
+    // once critical the voltage has to recover past the warning level, a sagging battery
+    // bounces back a little when the load is removed
+    pub fn level(&self, critical: bool) -> BatteryLevel {
+        let voltage = self.voltage();
+
+        if voltage < BATTERY_CRITICAL || (critical && voltage < BATTERY_RECOVER) {
+            return BatteryLevel::Critical;
+        }
+        if voltage < BATTERY_WARN {
+            return BatteryLevel::Low;
+        }
+        return BatteryLevel::Ok;
+    }
+
+    // low battery warning can be given again once the voltage is clearly above the warning level
+    pub fn warning_cleared(&self) -> bool {
+        return self.voltage() >= BATTERY_WARN_CLEAR;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // typical 2 cell discharge under a light constant load, one sample per 5% of runtime
+    const ALKALINE_CURVE: [u16; 21] = [
+        310, 298, 292, 287, 281, 276, 271, 266, 262, 258, 254, 250, 246, 242, 238, 234, 229, 224,
+        218, 210, 199,
+    ];
+    const NIMH_CURVE: [u16; 21] = [
+        280, 270, 264, 258, 254, 252, 250, 249, 248, 247, 246, 245, 244, 243, 241, 239, 236, 232,
+        226, 216, 199,
+    ];
+
+    // adc reads jitter by a few counts between samples
+    const NOISE: [i16; 8] = [0, 3, -2, 4, -4, 1, -3, 2];
+
+    fn run_curve(chemistry: BatteryChemistry, curve: &[u16]) -> ([u8; 21], [u8; 21]) {
+        let mut gauge = FuelGauge::new(chemistry);
+        let mut percents = [0; 21];
+        let mut frames = [0; 21];
+
+        for (i, v) in curve.iter().enumerate() {
+            // many noisy reads per curve sample, runner reads every frame
+            for n in 0..64 {
+                let noisy = (*v as i16 + NOISE[n % NOISE.len()]) as u16;
+                gauge.update(noisy);
+            }
+            percents[i] = gauge.percent();
+            frames[i] = gauge.frame();
+        }
+        return (percents, frames);
+    }
+
+    fn check_curve(chemistry: BatteryChemistry, curve: &[u16]) {
+        let (percents, frames) = run_curve(chemistry, curve);
+
+        assert!(percents[0] >= 95, "starts full {:?}", percents);
+        assert_eq!(percents[20], 0, "empty at cutoff {:?}", percents);
+        assert_eq!(frames[20], EMPTY_FRAME);
+
+        for i in 1..percents.len() {
+            assert!(percents[i] <= percents[i - 1], "percent rises {:?}", percents);
+            assert!(frames[i] >= frames[i - 1], "frame flickers {:?}", frames);
+        }
+
+        // estimate tracks elapsed runtime to within 20%
+        for i in 0..percents.len() {
+            let expected = 100 - i as i32 * 5;
+            let error = (percents[i] as i32 - expected).abs();
+            assert!(error <= 20, "sample {} expected {} got {:?}", i, expected, percents);
+        }
+    }
+
+    #[test]
+    fn alkaline_curve() {
+        check_curve(BatteryChemistry::Alkaline, &ALKALINE_CURVE);
+    }
+
+    #[test]
+    fn nimh_curve() {
+        check_curve(BatteryChemistry::NiMH, &NIMH_CURVE);
+    }
+
+    #[test]
+    fn frame_holds_at_boundary() {
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+
+        // hover right at the 75% boundary
+        gauge.update(276);
+        let frame = gauge.frame();
+        for n in 0..512 {
+            gauge.update((276 + NOISE[n % NOISE.len()]) as u16);
+            assert_eq!(gauge.frame(), frame);
+        }
+    }
+
+    #[test]
+    fn critical_needs_recovery() {
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+        gauge.update(BATTERY_CRITICAL - 2);
+        assert_eq!(gauge.level(false), BatteryLevel::Critical);
+
+        // rebound after the load is removed is not enough to leave the lockout
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+        gauge.update(BATTERY_CRITICAL + 2);
+        assert_eq!(gauge.level(false), BatteryLevel::Low);
+        assert_eq!(gauge.level(true), BatteryLevel::Critical);
+
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+        gauge.update(BATTERY_RECOVER);
+        assert_eq!(gauge.level(true), BatteryLevel::Ok);
+    }
+
+    #[test]
+    fn warning_clears_above_warn_level() {
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+        gauge.update(BATTERY_WARN);
+        assert_eq!(gauge.level(false), BatteryLevel::Ok);
+        assert!(!gauge.warning_cleared());
+
+        // adc noise around the warning level
+        for n in 0..512 {
+            gauge.update((BATTERY_WARN as i16 + NOISE[n % NOISE.len()]) as u16);
+            assert!(!gauge.warning_cleared());
+        }
+
+        let mut gauge = FuelGauge::new(BatteryChemistry::Alkaline);
+        gauge.update(BATTERY_WARN_CLEAR);
+        assert!(gauge.warning_cleared());
+    }
+
+    #[test]
+    fn runtime_scales_with_percent() {
+        let mut gauge = FuelGauge::new(BatteryChemistry::NiMH);
+        gauge.update(300);
+        assert_eq!(gauge.runtime_hours(), NIMH_PROFILE.runtime_hours as u32);
+
+        let mut gauge = FuelGauge::new(BatteryChemistry::NiMH);
+        gauge.update(190);
+        assert_eq!(gauge.runtime_hours(), 0);
+    }
+}
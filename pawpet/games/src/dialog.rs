@@ -0,0 +1,228 @@
+/*!
+ * Modal message box drawn over the current screen. The state that opens it passes input to it
+ * until it returns a result, and draws it after its own content.
+ *
+ *  self.dialog = Some(MessageBox::prompt("Release pet?"));
+ *  ...
+ *  match self.dialog.as_mut().and_then(|d| d.tick(buttons)) {
+ *      Some(DialogResult::Ok) => ...
+ *
+ * Widgets share the same buttons, A is back (cancel) and P confirms. Up/Down page through long
+ * text and P turns the page until the last one. Two button dialogs pick a button with
+ * Left/Right, P confirms the selected one.
+ */
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use heapless::String;
+use pawdevicetraits::Buttons;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+
+use crate::font::Font;
+use crate::font::TextBox;
+use crate::font::TextStyle;
+
+pub const MESSAGE_SIZE: usize = 160;
+pub const DIALOG_AREA: Rectangle = Rectangle::new(Point::new(2, 10), Size::new(60, 52));
+const PADDING: i32 = 3; // frame to text
+const FOOTER_HEIGHT: u32 = 10;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DialogResult {
+    Ok,     // also yes
+    Cancel, // also no, or back pressed
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DialogButtons {
+    Ok,
+    OkCancel,
+    YesNo,
+}
+
+impl DialogButtons {
+    fn labels(&self) -> (&'static str, Option<&'static str>) {
+        match self {
+            DialogButtons::Ok => ("OK", None),
+            DialogButtons::OkCancel => ("OK", Some("Cancel")),
+            DialogButtons::YesNo => ("Yes", Some("No")),
+        }
+    }
+}
+
+// border with the inside cleared, the area must be on screen
+pub(crate) fn draw_frame(disp: &mut impl Display, area: Rectangle) {
+    let x = area.top_left.x as u8;
+    let y = area.top_left.y as u8;
+    let width = area.size.width as u8;
+    let height = area.size.height as u8;
+
+    disp.fill_rect(x, y, width, height, true);
+    disp.fill_rect(x + 1, y + 1, width - 2, height - 2, false);
+}
+
+pub struct MessageBox {
+    text: String<MESSAGE_SIZE>,
+    buttons: DialogButtons,
+    selection: DialogResult,
+    font: Font,
+    body: TextBox,
+    area: Rectangle,
+}
+
+impl MessageBox {
+    pub fn new(text: &str, buttons: DialogButtons) -> Self {
+        let font = Font::default_font();
+        let mut message = Self {
+            text: String::new(),
+            buttons,
+            selection: DialogResult::Ok,
+            font,
+            body: TextBox::new(font, MessageBox::body_area(DIALOG_AREA)),
+            area: DIALOG_AREA,
+        };
+        message.set_text(text);
+        return message;
+    }
+
+    pub fn prompt(text: &str) -> Self {
+        return MessageBox::new(text, DialogButtons::YesNo);
+    }
+
+    fn body_area(area: Rectangle) -> Rectangle {
+        let width = area.size.width.saturating_sub(2 * PADDING as u32);
+        let height = area.size.height.saturating_sub(2 * PADDING as u32 + FOOTER_HEIGHT);
+        return Rectangle::new(area.top_left + Point::new(PADDING, PADDING), Size::new(width, height));
+    }
+
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+        self.body.set_font(font);
+    }
+
+    pub fn set_area(&mut self, area: Rectangle) {
+        self.area = area;
+        self.body.set_area(MessageBox::body_area(area));
+    }
+
+    // text longer than MESSAGE_SIZE bytes is cut at a character boundary
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        for c in text.chars() {
+            if self.text.push(c).is_err() {
+                break;
+            }
+        }
+        self.body.first_page();
+        self.selection = DialogResult::Ok;
+    }
+
+    pub fn text(&self) -> &str {
+        return &self.text;
+    }
+
+    // None until the dialog is closed
+    pub fn tick(&mut self, buttons: &mut impl Input) -> Option<DialogResult> {
+        if buttons.is_pressed(Buttons::A) {
+            return Some(DialogResult::Cancel);
+        } else if buttons.is_pressed(Buttons::Up) {
+            self.body.previous_page();
+        } else if buttons.is_pressed(Buttons::Down) {
+            self.body.next_page(&self.text);
+        } else if buttons.is_pressed(Buttons::Left) || buttons.is_pressed(Buttons::Right) {
+            if self.buttons != DialogButtons::Ok {
+                self.selection = match self.selection {
+                    DialogResult::Ok => DialogResult::Cancel,
+                    DialogResult::Cancel => DialogResult::Ok,
+                };
+            }
+        } else if buttons.is_pressed(Buttons::P) {
+            if self.body.next_page(&self.text) {
+                return None;
+            }
+            return Some(self.selection);
+        }
+        return None;
+    }
+
+    pub fn draw(&self, disp: &mut impl Display) {
+        draw_frame(disp, self.area);
+        self.body.draw(disp, &self.text);
+
+        let (first, second) = self.buttons.labels();
+        let y = self.area.top_left.y + self.area.size.height as i32 - PADDING - FOOTER_HEIGHT as i32 + 1;
+
+        match second {
+            None => {
+                let center = self.area.top_left.x + self.area.size.width as i32 / 2;
+                self.draw_button(disp, first, center, y, true);
+            }
+            Some(second) => {
+                let quarter = self.area.size.width as i32 / 4;
+                let left = self.area.top_left.x + quarter;
+                let right = left + 2 * quarter;
+                self.draw_button(disp, first, left, y, self.selection == DialogResult::Ok);
+                self.draw_button(disp, second, right, y, self.selection == DialogResult::Cancel);
+            }
+        }
+    }
+
+    // centered on x, the highlight gets a column of padding on the left to balance the spacing
+    fn draw_button(&self, disp: &mut impl Display, label: &str, center: i32, y: i32, selected: bool) {
+        let x = center - self.font.text_width(label) as i32 / 2;
+        if selected {
+            disp.fill_rect((x - 1) as u8, y as u8, 1, self.font.line_height(), true);
+            self.font.draw(disp, label, x, y, TextStyle::HIGHLIGHT);
+        } else {
+            self.font.draw(disp, label, x, y, TextStyle::NORMAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::Keys;
+
+    #[test]
+    fn p_turns_pages_before_confirming() {
+        let text = "a message long enough that it does not fit in the dialog and has to be paged";
+        let mut message = MessageBox::new(text, DialogButtons::Ok);
+        let pages = message.body.page_count(message.text());
+        assert!(pages > 1);
+
+        for _ in 1..pages {
+            assert_eq!(message.tick(&mut Keys::press(Buttons::P)), None);
+        }
+        // a single button can't be deselected
+        assert_eq!(message.tick(&mut Keys::press(Buttons::Right)), None);
+        assert_eq!(message.tick(&mut Keys::press(Buttons::P)), Some(DialogResult::Ok));
+
+        message.set_text(text);
+        assert_eq!(message.body.page(), 0);
+        assert_eq!(message.tick(&mut Keys::press(Buttons::A)), Some(DialogResult::Cancel));
+    }
+
+    #[test]
+    fn prompt_confirms_the_selected_button() {
+        let mut prompt = MessageBox::prompt("Release pet?");
+        assert_eq!(prompt.tick(&mut Keys::press(Buttons::Right)), None);
+        assert_eq!(prompt.tick(&mut Keys::press(Buttons::P)), Some(DialogResult::Cancel));
+
+        prompt.tick(&mut Keys::press(Buttons::Left));
+        assert_eq!(prompt.tick(&mut Keys::press(Buttons::P)), Some(DialogResult::Ok));
+    }
+
+    #[test]
+    fn long_text_is_cut_at_a_character_boundary() {
+        let mut text = std::string::String::from("a");
+        text.extend(core::iter::repeat('é').take(100));
+
+        let message = MessageBox::new(&text, DialogButtons::OkCancel);
+        assert_eq!(message.text().len(), MESSAGE_SIZE - 1);
+        assert!(message.text().ends_with('é'));
+    }
+}
@@ -1,7 +1,4 @@
-use embedded_graphics::mono_font::MonoTextStyle;
-use embedded_graphics::mono_font::MonoTextStyleBuilder;
 use embedded_graphics::primitives::PrimitiveStyle;
-use heapless::String;
 
 use pawdevicetraits::*;
 
@@ -9,38 +6,56 @@ use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
 use pawdevicetraits::StorageDevice as Storage;
-use pawdevicetraits::ToneDevice as Tone;
 use crate::FramerateMs;
 use crate::image::PawAnimation;
 use crate::image::PawImage;
+use crate::scene::NodeId;
+use crate::scene::NodeKind;
+use crate::scene::Scene;
 
 use embedded_graphics::{
-    mono_font::ascii::FONT_6X10,
     pixelcolor::BinaryColor,
     prelude::*,
-    primitives::{Circle, Line, PrimitiveStyleBuilder, Rectangle},
-    text::Text,
-    Drawable,
+    primitives::{Line, Rectangle},
 };
 
-use crate::include_bytes_align_as;
-
+use crate::pet::Pet;
+use crate::settings::Settings;
+use crate::sound::Sound;
+use crate::species::AnimTag;
+use crate::species::Species;
+use crate::species::SPECIES_TICK_MS;
 use crate::GameState;
 use crate::StateKind;
 
+const EGG_FRAME_MS: u16 = 8 * SPECIES_TICK_MS;
+
 pub struct EggState {
-    egg: PawAnimation,
-    creature: PawAnimation,
-    bg: PawImage,
-    tick_to_hatch: u16,
+    scene: Scene,
+    creature: NodeId,
+    bg: NodeId,
 }
 impl EggState {
     pub fn new() -> Self {
+        let mut scene = Scene::new();
+
+        // a new scene has room for these
+        let bg = scene
+            .add_image(PawImage::new_no_data(), Point::new(2, 8), 0)
+            .unwrap();
+        let floor = NodeKind::Line(
+            Line::new(Point::new(0, 40), Point::new(64, 40)),
+            PrimitiveStyle::with_stroke(BinaryColor::On, 1),
+        );
+        scene.add(floor, Point::zero(), 0).unwrap();
+        let creature = scene
+            .add_animation(PawAnimation::new((0, 4), EGG_FRAME_MS), Point::new(16, 28), 1)
+            .unwrap();
+
         Self {
-            egg: PawAnimation::new((0, 4), 8),
-            creature: PawAnimation::new((0, 2), 8),
-            bg: PawImage::new_no_data(),
-            tick_to_hatch: 200,
+            scene,
+            creature,
+            bg,
         }
     }
 }
@@ -48,18 +63,20 @@ impl GameState for EggState {
     fn tick(
         &mut self,
         buttons: &mut impl Input,
-        _tone: &impl Tone,
+        _sound: &mut Sound,
         _battery: &mut impl BatteryMonitor,
+        pet: &mut Pet,
+        _settings: &mut Settings,
     ) -> StateKind {
-        self.egg.tick();
-        self.creature.tick();
+        self.scene.advance(Self::get_fps() as u32);
 
-        if buttons.is_pressed(Buttons::A) {
-            return StateKind::Menu;
+        // hatched
+        if pet.stage() > 0 {
+            return StateKind::Home;
         }
 
-        if self.tick_to_hatch > 0 {
-            self.tick_to_hatch -= 1;
+        if buttons.is_pressed(Buttons::A) {
+            return StateKind::Menu;
         }
 
         return StateKind::Egg;
@@ -70,26 +87,27 @@ impl GameState for EggState {
         return FramerateMs::Fps30;
     }
 
-    fn load(&mut self, storage: &mut impl Storage) {
-        self.egg.set_image(storage.load_image("egg_wobble"));
-        self.creature.set_image(storage.load_image("pet1_idle"));
-        self.bg.set_image(storage.load_image("window"));
-    }
-
-    fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
-        display.clear(BinaryColor::Off).ok();
+    // stage sprites come from the species definition, state is reloaded when the pet evolves
+    fn load(&mut self, storage: &mut impl Storage, pet: &Pet, species: &Species) {
+        if let Some(stage) = species.stage(pet.stage()) {
+            let idle = stage.tag(AnimTag::Idle);
+            let data = storage.load_image(stage.key);
+            self.scene.update_animation(self.creature, |creature| {
+                creature.set_image(data);
+                creature.set_loop((idle.start, idle.end), idle.frame_ms());
+            });
+        }
 
-        self.bg.draw(display, 2, 8);
-        // self.bg.draw(display, 0, 0);
+        let data = storage.load_image("window");
+        self.scene.update_image(self.bg, |bg| bg.set_image(data));
+    }
 
-        Line::new(Point::new(0, 40), Point::new(64, 40))
-            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
-            .draw(display).ok();
+    fn invalidate(&mut self, area: Rectangle) {
+        self.scene.invalidate(area);
+    }
 
-        if self.tick_to_hatch == 0 {
-            self.creature.draw(display, 16, 28);
-        } else {
-            self.egg.draw(display, 16, 28);
-        }
+    // only what changed since the last frame is repainted
+    fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        self.scene.draw(display);
     }
 }
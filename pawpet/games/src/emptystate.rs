@@ -9,7 +9,6 @@ use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
 use pawdevicetraits::StorageDevice as Storage;
-use pawdevicetraits::ToneDevice as Tone;
 use crate::FramerateMs;
 use crate::image::PawAnimation;
 use crate::image::PawImage;
@@ -25,6 +24,10 @@ use embedded_graphics::{
 
 use crate::include_bytes_align_as;
 
+use crate::pet::Pet;
+use crate::species::Species;
+use crate::settings::Settings;
+use crate::sound::Sound;
 use crate::GameState;
 use crate::StateKind;
 
@@ -40,8 +43,10 @@ impl GameState for EmptyState {
     fn tick(
         &mut self,
         buttons: &mut impl Input,
-        _tone: &impl Tone,
+        _sound: &mut Sound,
         _battery: &mut impl BatteryMonitor,
+        _pet: &mut Pet,
+        _settings: &mut Settings,
     ) -> StateKind {
 
 
@@ -57,7 +62,7 @@ impl GameState for EmptyState {
         return FramerateMs::Fps30;
     }
 
-    fn load(&mut self, storage: &mut impl Storage) {
+    fn load(&mut self, storage: &mut impl Storage, _pet: &Pet, _species: &Species) {
 
     }
 
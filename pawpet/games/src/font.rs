@@ -0,0 +1,677 @@
+/*!
+ * Proportional pixel font, stored in flash as a file so glyphs can be added without a firmware
+ * rebuild. Compiled on host from a BDF font by png2c/font.py, the built in default is the
+ * misc-fixed 5x8 font with ASCII, Latin-1 and a few symbols.
+ *
+ * little endian
+ * [header 10 bytes]
+ *      magic "PFNT", version u8, height u8 (pixel rows per glyph), line height u8, reserved u8,
+ *      glyph count u16
+ * [glyph 6 bytes] * glyph count, sorted by code point
+ *      code point u16, width u8, advance u8, bitmap offset u16 (from the end of the glyph table)
+ * [bitmaps]
+ *      height rows of (width + 7) / 8 bytes, bit 0 is the leftmost pixel
+ *
+ * Characters missing from the font draw as '?'.
+ */
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use pawdevicetraits::DisplayDevice;
+use pawdevicetraits::StorageDevice;
+use rtt_target::debug_rprintln;
+
+use crate::include_bytes_align_as;
+
+pub const FONT_FILE: &str = "font";
+
+const MAGIC: &[u8; 4] = b"PFNT";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 10;
+const GLYPH_SIZE: usize = 6;
+const MAX_HEIGHT: u8 = 32;
+const MAX_GLYPH_WIDTH: u8 = 32;
+const FALLBACK: char = '?';
+const SCREEN: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(64, 64));
+// space kept free on the right of a text box for the more pages marker
+const MARKER_WIDTH: u32 = 4;
+
+static DEFAULT_FONT: &'static [u8] = include_bytes_align_as!(u32, "../../../sprites/font.fnt");
+
+#[derive(Debug, PartialEq)]
+pub enum FontError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion,
+    BadHeight,
+    NoGlyphs,
+    UnsortedGlyphs,
+    BadGlyph,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TextStyle {
+    pub color: bool,
+    pub background: Option<bool>, // None leaves the pixels between glyph strokes alone
+}
+
+impl TextStyle {
+    pub const NORMAL: Self = Self {
+        color: true,
+        background: None,
+    };
+
+    // inverted, for selections and titles
+    pub const HIGHLIGHT: Self = Self {
+        color: false,
+        background: Some(true),
+    };
+}
+
+#[derive(Copy, Clone)]
+pub struct Glyph {
+    pub width: u8,
+    pub advance: u8,
+    bitmap: &'static [u8],
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    return u16::from_le_bytes([data[at], data[at + 1]]);
+}
+
+#[derive(Copy, Clone)]
+pub struct Font {
+    glyphs: &'static [u8],
+    bitmaps: &'static [u8],
+    height: u8,
+    line_height: u8,
+}
+
+impl Font {
+    pub fn default_font() -> Self {
+        return Font::parse(DEFAULT_FONT).ok().unwrap();
+    }
+
+    // the font file pushed to storage, or the built in font if there is none
+    pub fn load(storage: &mut impl StorageDevice) -> Self {
+        if let Some(data) = storage.load_image(FONT_FILE) {
+            match Font::parse(data) {
+                Ok(font) => return font,
+                Err(error) => debug_rprintln!("bad font file {:?}", error),
+            }
+        }
+        return Font::default_font();
+    }
+
+    /**
+     * Checks the glyph table is sorted and every bitmap is inside the data, lookups and drawing
+     * don't check again.
+     */
+    pub fn parse(data: &'static [u8]) -> Result<Self, FontError> {
+        if data.len() < HEADER_SIZE {
+            return Err(FontError::Truncated);
+        }
+        if &data[0..4] != MAGIC {
+            return Err(FontError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(FontError::UnsupportedVersion);
+        }
+
+        let height = data[5];
+        let line_height = data[6];
+        if height == 0 || height > MAX_HEIGHT || line_height < height {
+            return Err(FontError::BadHeight);
+        }
+
+        let glyph_count = read_u16(data, 8) as usize;
+        if glyph_count == 0 {
+            return Err(FontError::NoGlyphs);
+        }
+
+        let table_end = HEADER_SIZE + glyph_count * GLYPH_SIZE;
+        if data.len() < table_end {
+            return Err(FontError::Truncated);
+        }
+
+        let glyphs = &data[HEADER_SIZE..table_end];
+        let bitmaps = &data[table_end..];
+
+        for i in 0..glyph_count {
+            let record = &glyphs[i * GLYPH_SIZE..(i + 1) * GLYPH_SIZE];
+            if i > 0 && read_u16(glyphs, (i - 1) * GLYPH_SIZE) >= read_u16(record, 0) {
+                return Err(FontError::UnsortedGlyphs);
+            }
+
+            let width = record[2];
+            let offset = read_u16(record, 4) as usize;
+            let length = height as usize * (width as usize).div_ceil(8);
+            if width > MAX_GLYPH_WIDTH || offset + length > bitmaps.len() {
+                return Err(FontError::BadGlyph);
+            }
+        }
+
+        return Ok(Self {
+            glyphs,
+            bitmaps,
+            height,
+            line_height,
+        });
+    }
+
+    pub fn height(&self) -> u8 {
+        return self.height;
+    }
+
+    // distance between the tops of two lines
+    pub fn line_height(&self) -> u8 {
+        return self.line_height;
+    }
+
+    fn glyph_count(&self) -> usize {
+        return self.glyphs.len() / GLYPH_SIZE;
+    }
+
+    fn glyph_at(&self, index: usize) -> Glyph {
+        let record = &self.glyphs[index * GLYPH_SIZE..(index + 1) * GLYPH_SIZE];
+        let width = record[2];
+        let offset = read_u16(record, 4) as usize;
+        let length = self.height as usize * (width as usize).div_ceil(8);
+
+        return Glyph {
+            width,
+            advance: record[3],
+            bitmap: &self.bitmaps[offset..offset + length],
+        };
+    }
+
+    // binary search of the glyph table
+    pub fn glyph(&self, c: char) -> Option<Glyph> {
+        let code = c as u32;
+        if code > u16::MAX as u32 {
+            return None;
+        }
+
+        let mut low = 0;
+        let mut high = self.glyph_count();
+        while low < high {
+            let mid = (low + high) / 2;
+            let point = read_u16(self.glyphs, mid * GLYPH_SIZE) as u32;
+
+            if point == code {
+                return Some(self.glyph_at(mid));
+            } else if point < code {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        return None;
+    }
+
+    // missing characters draw as '?', or nothing if the font has no '?' either
+    fn glyph_or_fallback(&self, c: char) -> Option<Glyph> {
+        return self.glyph(c).or_else(|| self.glyph(FALLBACK));
+    }
+
+    pub fn advance(&self, c: char) -> u8 {
+        return self.glyph_or_fallback(c).map(|g| g.advance).unwrap_or(0);
+    }
+
+    // includes the spacing after the last character
+    pub fn text_width(&self, text: &str) -> u32 {
+        return text.chars().map(|c| self.advance(c) as u32).sum();
+    }
+
+    // single line with the top left at x, y, returns the x after the last character
+    pub fn draw(&self, disp: &mut impl DisplayDevice, text: &str, x: i32, y: i32, style: TextStyle) -> i32 {
+        return self.draw_clipped(disp, text, x, y, style, SCREEN);
+    }
+
+    pub fn draw_clipped(
+        &self,
+        disp: &mut impl DisplayDevice,
+        text: &str,
+        x: i32,
+        y: i32,
+        style: TextStyle,
+        clip: Rectangle,
+    ) -> i32 {
+        let clip = clip.intersection(&SCREEN);
+        let clip = (
+            clip.top_left.x,
+            clip.top_left.y,
+            clip.top_left.x + clip.size.width as i32,
+            clip.top_left.y + clip.size.height as i32,
+        );
+
+        let mut x = x;
+        for c in text.chars() {
+            let glyph = match self.glyph_or_fallback(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+
+            if let Some(background) = style.background {
+                let cell = (x, y, x + glyph.advance as i32, y + self.line_height as i32);
+                fill_clipped(disp, cell, clip, background);
+            }
+            self.draw_glyph(disp, &glyph, x, y, style.color, clip);
+            x += glyph.advance as i32;
+        }
+        return x;
+    }
+
+    // runs of set bits in each row become spans
+    fn draw_glyph(
+        &self,
+        disp: &mut impl DisplayDevice,
+        glyph: &Glyph,
+        x: i32,
+        y: i32,
+        color: bool,
+        clip: (i32, i32, i32, i32),
+    ) {
+        let (cx0, cy0, cx1, cy1) = clip;
+        let row_bytes = (glyph.width as usize).div_ceil(8);
+
+        for row in 0..self.height as usize {
+            let py = y + row as i32;
+            if py < cy0 || py >= cy1 {
+                continue;
+            }
+
+            let bits = &glyph.bitmap[row * row_bytes..(row + 1) * row_bytes];
+            let set = |i: u8| (bits[i as usize / 8] >> (i % 8)) & 1 > 0;
+
+            let mut i = 0;
+            while i < glyph.width {
+                if !set(i) {
+                    i += 1;
+                    continue;
+                }
+
+                let start = i;
+                while i < glyph.width && set(i) {
+                    i += 1;
+                }
+
+                let x0 = (x + start as i32).max(cx0);
+                let x1 = (x + i as i32).min(cx1);
+                if x0 < x1 {
+                    disp.draw_span(x0 as u8, py as u8, (x1 - x0) as u8, color);
+                }
+            }
+        }
+    }
+}
+
+// area and clip are x0, y0, x1, y1 exclusive, clip is already inside the screen
+fn fill_clipped(
+    disp: &mut impl DisplayDevice,
+    area: (i32, i32, i32, i32),
+    clip: (i32, i32, i32, i32),
+    color: bool,
+) {
+    let x0 = area.0.max(clip.0);
+    let y0 = area.1.max(clip.1);
+    let x1 = area.2.min(clip.2);
+    let y1 = area.3.min(clip.3);
+
+    if x0 < x1 && y0 < y1 {
+        disp.fill_rect(x0 as u8, y0 as u8, (x1 - x0) as u8, (y1 - y0) as u8, color);
+    }
+}
+
+/**
+ * Splits text into lines no wider than `width`, breaking at spaces and newlines. Words longer
+ * than a line are broken between characters, the space a line was broken at is dropped.
+ */
+pub struct Lines<'a> {
+    font: Font,
+    text: &'a str,
+    width: u32,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(font: Font, text: &'a str, width: u32) -> Self {
+        return Self { font, text, width };
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.text.is_empty() {
+            return None;
+        }
+
+        let mut width = 0;
+        let mut space = None; // last space the line can be broken at
+        for (i, c) in self.text.char_indices() {
+            if c == '\n' {
+                let line = &self.text[..i];
+                self.text = &self.text[i + 1..];
+                return Some(line);
+            }
+
+            let glyph = self.font.glyph_or_fallback(c);
+            let glyph_width = glyph.map(|g| g.width).unwrap_or(0) as u32;
+            let advance = glyph.map(|g| g.advance).unwrap_or(0) as u32;
+
+            if c == ' ' {
+                if i > 0 {
+                    space = Some(i);
+                }
+            } else if i > 0 && width + glyph_width > self.width {
+                let end = space.unwrap_or(i);
+                let line = self.text[..end].trim_end_matches(' ');
+                self.text = self.text[end..].trim_start_matches(' ');
+                return Some(line);
+            }
+            width += advance;
+        }
+
+        let line = self.text;
+        self.text = "";
+        return Some(line);
+    }
+}
+
+/**
+ * Word wrapped text in a fixed area, text that doesn't fit is split into pages and a marker in
+ * the bottom right corner shows there are more. The text isn't stored, the caller passes the same
+ * text to paging and drawing.
+ */
+pub struct TextBox {
+    font: Font,
+    area: Rectangle,
+    style: TextStyle,
+    page: u8,
+}
+
+impl TextBox {
+    pub fn new(font: Font, area: Rectangle) -> Self {
+        Self {
+            font,
+            area,
+            style: TextStyle::NORMAL,
+            page: 0,
+        }
+    }
+
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+        self.page = 0;
+    }
+
+    pub fn set_area(&mut self, area: Rectangle) {
+        self.area = area;
+        self.page = 0;
+    }
+
+    pub fn area(&self) -> Rectangle {
+        return self.area;
+    }
+
+    // a background fills the whole area
+    pub fn set_style(&mut self, style: TextStyle) {
+        self.style = style;
+    }
+
+    pub fn lines<'a>(&self, text: &'a str) -> Lines<'a> {
+        let width = self.area.size.width.saturating_sub(MARKER_WIDTH);
+        return Lines::new(self.font, text, width);
+    }
+
+    pub fn lines_per_page(&self) -> usize {
+        return (self.area.size.height / self.font.line_height as u32).max(1) as usize;
+    }
+
+    // at least one, an empty text is an empty page
+    pub fn page_count(&self, text: &str) -> u8 {
+        let lines = self.lines(text).count();
+        let pages = lines.div_ceil(self.lines_per_page());
+        return pages.clamp(1, u8::MAX as usize) as u8;
+    }
+
+    pub fn page(&self) -> u8 {
+        return self.page;
+    }
+
+    pub fn has_more(&self, text: &str) -> bool {
+        return self.page + 1 < self.page_count(text);
+    }
+
+    // false if already on the last page
+    pub fn next_page(&mut self, text: &str) -> bool {
+        if !self.has_more(text) {
+            return false;
+        }
+        self.page += 1;
+        return true;
+    }
+
+    pub fn previous_page(&mut self) -> bool {
+        if self.page == 0 {
+            return false;
+        }
+        self.page -= 1;
+        return true;
+    }
+
+    pub fn first_page(&mut self) {
+        self.page = 0;
+    }
+
+    pub fn draw(&self, disp: &mut impl DisplayDevice, text: &str) {
+        let area = self.area.intersection(&SCREEN);
+        let bounds = (
+            area.top_left.x,
+            area.top_left.y,
+            area.top_left.x + area.size.width as i32,
+            area.top_left.y + area.size.height as i32,
+        );
+
+        if let Some(background) = self.style.background {
+            fill_clipped(disp, bounds, bounds, background);
+        }
+
+        // the background is already filled
+        let style = TextStyle {
+            color: self.style.color,
+            background: None,
+        };
+
+        let per_page = self.lines_per_page();
+        let first = self.page as usize * per_page;
+        let lines = self.lines(text).skip(first).take(per_page);
+
+        for (row, line) in lines.enumerate() {
+            let y = self.area.top_left.y + (row * self.font.line_height as usize) as i32;
+            self.font
+                .draw_clipped(disp, line, self.area.top_left.x, y, style, self.area);
+        }
+
+        // down pointing triangle, 3 pixels wide
+        if self.has_more(text) {
+            let x = bounds.2 - 3;
+            let y = bounds.3 - 2;
+            fill_clipped(disp, (x, y, x + 3, y + 1), bounds, self.style.color);
+            fill_clipped(disp, (x + 1, y + 1, x + 2, y + 2), bounds, self.style.color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::Grid;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    // 3 rows tall, line height 4
+    //  ' ' blank, advance 2
+    //  '?' 1 wide  '#' '.' '#'
+    //  'A' 2 wide  '##' '#.' '##'
+    //  'é' 10 wide, wider than a byte
+    fn test_font() -> &'static [u8] {
+        let glyphs: [(u16, u8, u8, &[u8]); 4] = [
+            (' ' as u16, 0, 2, &[]),
+            ('?' as u16, 1, 2, &[0b1, 0b0, 0b1]),
+            ('A' as u16, 2, 3, &[0b11, 0b01, 0b11]),
+            ('é' as u16, 10, 11, &[0xFF, 0b11, 0x01, 0b00, 0xFF, 0b11]),
+        ];
+
+        let mut data = Vec::from(*MAGIC);
+        data.extend_from_slice(&[VERSION, 3, 4, 0]);
+        data.extend_from_slice(&(glyphs.len() as u16).to_le_bytes());
+
+        let mut bitmaps = Vec::new();
+        for (code, width, advance, bitmap) in glyphs.iter() {
+            data.extend_from_slice(&code.to_le_bytes());
+            data.extend_from_slice(&[*width, *advance]);
+            data.extend_from_slice(&(bitmaps.len() as u16).to_le_bytes());
+            bitmaps.extend_from_slice(bitmap);
+        }
+        data.extend_from_slice(&bitmaps);
+        return Box::leak(data.into_boxed_slice());
+    }
+
+    fn font() -> Font {
+        return Font::parse(test_font()).ok().unwrap();
+    }
+
+    fn patched(at: usize, value: u8) -> &'static [u8] {
+        let mut data = test_font().to_vec();
+        data[at] = value;
+        return Box::leak(data.into_boxed_slice());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let data = test_font();
+        assert_eq!(Font::parse(&data[..9]).err(), Some(FontError::Truncated));
+        assert_eq!(Font::parse(&data[..HEADER_SIZE + 6]).err(), Some(FontError::Truncated));
+        assert_eq!(Font::parse(&data[..data.len() - 1]).err(), Some(FontError::BadGlyph));
+        assert_eq!(Font::parse(patched(0, b'X')).err(), Some(FontError::BadMagic));
+        assert_eq!(Font::parse(patched(4, 2)).err(), Some(FontError::UnsupportedVersion));
+        assert_eq!(Font::parse(patched(6, 2)).err(), Some(FontError::BadHeight));
+        assert_eq!(Font::parse(patched(8, 0)).err(), Some(FontError::NoGlyphs));
+        // 'A' moved before '?'
+        assert_eq!(Font::parse(patched(HEADER_SIZE + 12, b'0')).err(), Some(FontError::UnsortedGlyphs));
+        assert_eq!(Font::parse(patched(HEADER_SIZE + 14, 40)).err(), Some(FontError::BadGlyph));
+    }
+
+    #[test]
+    fn default_font_has_glyphs_beyond_ascii() {
+        let font = Font::default_font();
+        assert_eq!(font.height(), 8);
+
+        for c in (' '..='~').chain("éüñß♥♪←→…".chars()) {
+            assert!(font.glyph(c).is_some(), "{}", c);
+        }
+        assert!(font.glyph('あ').is_none());
+        assert!(font.glyph('😀').is_none());
+
+        // proportional
+        assert!(font.advance('i') < font.advance('m'));
+        assert_eq!(font.advance('あ'), font.advance('?'));
+    }
+
+    #[test]
+    fn draws_proportional_text() {
+        let font = font();
+        let mut grid = Grid::new();
+
+        assert_eq!(font.text_width("A Aé"), 3 + 2 + 3 + 11);
+        let end = font.draw(&mut grid, "A✓é", 1, 1, TextStyle::NORMAL);
+        assert_eq!(end, 1 + 3 + 2 + 11);
+
+        assert_eq!(
+            grid.rows(1, 1, 16, 3),
+            [
+                "## # ########## ",
+                "#    #          ",
+                "## # ########## ",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlight_fills_behind_the_glyphs() {
+        let font = font();
+        let mut grid = Grid::new();
+
+        let end = font.draw(&mut grid, "A A", 61, 2, TextStyle::HIGHLIGHT);
+        assert_eq!(end, 61 + 8);
+
+        // clipped to the screen, background covers the whole line height
+        assert_eq!(grid.rows(60, 1, 4, 6), [
+            "    ",
+            " ..#",
+            " .##",
+            " ..#",
+            " ###",
+            "    ",
+        ]);
+    }
+
+    #[test]
+    fn wraps_words_and_breaks_long_ones() {
+        let font = font();
+        let lines = |text, width| Lines::new(font, text, width).collect::<Vec<_>>();
+
+        assert_eq!(lines("AA AA AAA", 13), ["AA AA", "AAA"]);
+        assert_eq!(lines("AA AA AAA", 12), ["AA", "AA", "AAA"]);
+        assert_eq!(lines("AAAAAAA", 8), ["AAA", "AAA", "A"]);
+        assert_eq!(lines("A\n\nA  AAA", 8), ["A", "", "A", "AAA"]);
+        assert!(lines("", 8).is_empty());
+    }
+
+    #[test]
+    fn text_box_pages() {
+        let text = "A A A A A A A";
+        // 2 lines of AAA per page
+        let area = Rectangle::new(Point::new(2, 2), Size::new(12 + MARKER_WIDTH, 9));
+        let mut text_box = TextBox::new(font(), area);
+        text_box.set_style(TextStyle::HIGHLIGHT);
+
+        assert_eq!(text_box.lines_per_page(), 2);
+        assert_eq!(text_box.page_count(text), 2);
+        assert_eq!(TextBox::new(font(), area).page_count(""), 1);
+
+        let mut grid = Grid::new();
+        text_box.draw(&mut grid, text);
+        assert_eq!(
+            grid.rows(1, 1, 18, 11),
+            [
+                "                  ",
+                " ..###..###..#### ",
+                " .####.####.##### ",
+                " ..###..###..#### ",
+                " ################ ",
+                " ..###..###..#### ",
+                " .####.####.##### ",
+                " ..###..###..#### ",
+                " #############... ",
+                " ##############.# ",
+                "                  ",
+            ]
+        );
+
+        assert!(text_box.next_page(text));
+        assert!(!text_box.next_page(text));
+        assert!(!text_box.has_more(text));
+
+        let mut grid = Grid::new();
+        text_box.draw(&mut grid, text);
+        assert_eq!(grid.rows(2, 2, 4, 4), ["..##", ".###", "..##", "####"]);
+
+        assert!(text_box.previous_page());
+        assert_eq!(text_box.page(), 0);
+    }
+}
@@ -8,7 +8,6 @@ use pawdevicetraits::*;
 use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
-use pawdevicetraits::ToneDevice as Tone;
 use pawdevicetraits::StorageDevice as Storage;
 
 use crate::image::PawImage;
@@ -24,6 +23,9 @@ use embedded_graphics::{
 
 use crate::include_bytes_align_as;
 
+use crate::pet::Pet;
+use crate::settings::Settings;
+use crate::sound::Sound;
 use crate::GameState;
 use crate::StateKind;
 
@@ -38,8 +40,10 @@ impl GameState for PawGame1 {
     fn tick(
         &mut self,
         _buttons: &mut impl Input,
-        _tone: &impl Tone,
+        _sound: &mut Sound,
         _battery: &mut impl BatteryMonitor,
+        _pet: &mut Pet,
+        _settings: &mut Settings,
     ) -> StateKind {
         return StateKind::Game1;
     }
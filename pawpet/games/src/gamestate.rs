@@ -3,22 +3,28 @@
 use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
-use pawdevicetraits::ToneDevice as Tone;
 use pawdevicetraits::StorageDevice as Storage;
 
 use embedded_graphics::{
     pixelcolor::BinaryColor,
     prelude::DrawTarget,
+    primitives::Rectangle,
 };
 
+use crate::pet::Pet;
+use crate::species::Species;
+use crate::settings::Settings;
+use crate::sound::Sound;
 use crate::FramerateMs;
 
 pub trait GameState {
     fn tick(
         &mut self,
         buttons: &mut impl Input,
-        tone: &impl Tone,
+        sound: &mut Sound,
         battery: &mut impl BatteryMonitor,
+        pet: &mut Pet,
+        settings: &mut Settings,
     ) -> StateKind;
 
     fn draw(
@@ -26,7 +32,14 @@ pub trait GameState {
         display: &mut (impl Display + DrawTarget<Color = BinaryColor>),
     );
 
-    fn load(&mut self, storage: &mut impl Storage) {}
+    // species is parsed once by the runner
+    fn load(&mut self, storage: &mut impl Storage, pet: &Pet, species: &Species) {}
+
+    // area drawn over by the runner, only needed by states that don't redraw everything each frame
+    fn invalidate(&mut self, _area: Rectangle) {}
+
+    // transition into the state has finished, called before the first tick
+    fn entered(&mut self) {}
 
     fn need_redraw( &mut self) -> bool
     {
@@ -39,12 +52,15 @@ pub trait GameState {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum StateKind {
     Main,
     Menu,
     Game1,
     Egg,
     Empty,
+    Memorial,
+    Home,
+    Settings,
 }
 
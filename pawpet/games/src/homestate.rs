@@ -0,0 +1,263 @@
+/*!
+ * Home screen, the pet picks behaviors from a weighted table that shifts with its needs.
+ * Top 8 rows are left for the status bar drawn by the runner.
+ */
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::mono_font::MonoTextStyleBuilder;
+use embedded_graphics::primitives::PrimitiveStyle;
+
+use pawdevicetraits::*;
+
+use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+use pawdevicetraits::StorageDevice as Storage;
+use crate::FramerateMs;
+use crate::image::PawAnimation;
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_5X8,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Line,
+    text::Text,
+    Drawable,
+};
+
+use crate::pet::Need;
+use crate::pet::Pet;
+use crate::settings::Settings;
+use crate::sound::Sfx;
+use crate::sound::Sound;
+use crate::species::AnimTag;
+use crate::species::Species;
+use crate::species::Stage;
+use crate::species::SPECIES_TICK_MS;
+use crate::GameState;
+use crate::StateKind;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Behavior {
+    Idle = 0,
+    Wander = 1,
+    Sit = 2,
+    Sleep = 3,
+}
+
+const BEHAVIOR_COUNT: usize = 4;
+const BEHAVIORS: [Behavior; BEHAVIOR_COUNT] =
+    [Behavior::Idle, Behavior::Wander, Behavior::Sit, Behavior::Sleep];
+
+// (min, max) seconds a behavior runs before picking the next one
+const BEHAVIOR_SECONDS: [(u32, u32); BEHAVIOR_COUNT] = [(3, 8), (4, 10), (5, 12), (10, 30)];
+
+const TICKS_PER_SECOND: u32 = 1000 / FramerateMs::Fps15 as u32;
+const WANDER_TICKS_PER_PIXEL: u8 = 3;
+const DEFAULT_FRAME_MS: u16 = 8 * SPECIES_TICK_MS;
+
+const FLOOR_Y: i32 = 60;
+const CREATURE_Y: u8 = 28;
+const SCREEN_WIDTH: u8 = 64;
+
+// care shortcut amounts, same as the care menu
+pub const FEED_AMOUNT: u8 = 25;
+pub const CLEAN_AMOUNT: u8 = 40;
+
+pub struct HomeState {
+    creature: PawAnimation,
+    stage: Option<Stage>, // current stage definition from the species
+
+    behavior: Behavior,
+    behavior_ticks: u32,
+    x: u8,
+    target_x: u8,
+    move_tick: u8,
+    seed: u32,
+}
+
+impl HomeState {
+    pub fn new() -> Self {
+        Self {
+            creature: PawAnimation::new((0, 1), DEFAULT_FRAME_MS),
+            stage: None,
+            behavior: Behavior::Idle,
+            behavior_ticks: 0,
+            x: 16,
+            target_x: 16,
+            move_tick: 0,
+            seed: 0x2545_F491,
+        }
+    }
+
+    // xorshift32, only needs to look random to the player
+    fn random(&mut self) -> u32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        return x;
+    }
+
+    fn random_range(&mut self, min: u32, max: u32) -> u32 {
+        return min + self.random() % (max - min + 1);
+    }
+
+    // unhappy pets mope instead of wandering, hungry pets sit and wait, sick pets mostly sleep
+    fn weights(&self, pet: &Pet) -> [u32; BEHAVIOR_COUNT] {
+        let hunger = pet.needs.level(Need::Hunger) as u32;
+        let happiness = pet.needs.level(Need::Happiness) as u32;
+
+        if pet.is_sick() {
+            return [20, 0, 10, 70];
+        }
+
+        return [30, 10 + happiness / 2, 10 + (100 - hunger) / 3, 10];
+    }
+
+    fn pick_behavior(&mut self, pet: &Pet) {
+        let weights = self.weights(pet);
+        let total: u32 = weights.iter().sum();
+
+        let mut roll = self.random() % total;
+        let mut next = Behavior::Idle;
+        for i in 0..BEHAVIOR_COUNT {
+            if roll < weights[i] {
+                next = BEHAVIORS[i];
+                break;
+            }
+            roll -= weights[i];
+        }
+
+        self.set_behavior(next);
+    }
+
+    fn set_behavior(&mut self, behavior: Behavior) {
+        self.behavior = behavior;
+
+        let (min, max) = BEHAVIOR_SECONDS[behavior as usize];
+        self.behavior_ticks = self.random_range(min, max) * TICKS_PER_SECOND;
+
+        let tag = match behavior {
+            Behavior::Idle => AnimTag::Idle,
+            Behavior::Wander => AnimTag::Walk,
+            Behavior::Sit => AnimTag::Sit,
+            Behavior::Sleep => AnimTag::Sleep,
+        };
+
+        if let Some(stage) = &self.stage {
+            let range = stage.tag(tag);
+            self.creature
+                .set_loop((range.start, range.end), range.frame_ms());
+        }
+
+        if behavior == Behavior::Wander {
+            let max_x = SCREEN_WIDTH.saturating_sub(self.creature.width()) as u32;
+            self.target_x = self.random_range(0, max_x) as u8;
+
+            // sprites face right, keeps facing the last direction once it stops
+            if self.target_x != self.x {
+                self.creature.set_flip(self.target_x < self.x, false);
+            }
+        }
+    }
+}
+
+impl GameState for HomeState {
+    fn tick(
+        &mut self,
+        buttons: &mut impl Input,
+        sound: &mut Sound,
+        _battery: &mut impl BatteryMonitor,
+        pet: &mut Pet,
+        _settings: &mut Settings,
+    ) -> StateKind {
+        // hatching is shown by the egg state
+        if pet.stage() == 0 {
+            return StateKind::Egg;
+        }
+
+        if buttons.is_pressed(Buttons::P) {
+            sound.play(Sfx::Click);
+            return StateKind::Menu;
+        }
+
+        // care shortcuts, pet reacts by looking at the player
+        if buttons.is_pressed(Buttons::B) {
+            pet.care(Need::Hunger, FEED_AMOUNT);
+            sound.play(Sfx::Eat);
+            self.set_behavior(Behavior::Idle);
+        } else if buttons.is_pressed(Buttons::C) {
+            pet.care(Need::Hygiene, CLEAN_AMOUNT);
+            sound.play(Sfx::Happy);
+            self.set_behavior(Behavior::Idle);
+        }
+
+        self.creature.advance(Self::get_fps() as u32);
+
+        if self.behavior == Behavior::Wander && self.x != self.target_x {
+            self.move_tick += 1;
+            if self.move_tick >= WANDER_TICKS_PER_PIXEL {
+                self.move_tick = 0;
+                if self.x < self.target_x {
+                    self.x += 1;
+                } else {
+                    self.x -= 1;
+                }
+            }
+        }
+
+        if self.behavior_ticks == 0 {
+            self.pick_behavior(pet);
+        } else {
+            self.behavior_ticks -= 1;
+        }
+
+        return StateKind::Home;
+    }
+
+    fn get_fps() -> FramerateMs {
+        return FramerateMs::Fps15;
+    }
+
+    fn load(&mut self, storage: &mut impl Storage, pet: &Pet, species: &Species) {
+        self.stage = species.stage(pet.stage());
+
+        if let Some(stage) = &self.stage {
+            self.creature.set_image(storage.load_image(stage.key));
+        }
+
+        self.seed ^= pet.needs.average() as u32 + ((pet.stage() as u32) << 8);
+        self.x = SCREEN_WIDTH.saturating_sub(self.creature.width()) / 2;
+        self.target_x = self.x;
+        self.set_behavior(Behavior::Idle);
+    }
+
+    fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        display.clear(BinaryColor::Off).ok();
+
+        self.creature.draw(display, self.x as i32, CREATURE_Y as i32);
+
+        if self.behavior == Behavior::Sleep {
+            let text: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
+                .font(&FONT_5X8)
+                .text_color(BinaryColor::On)
+                .build();
+
+            Text::new("z", Point::new(self.x as i32 + self.creature.width() as i32, 26), text)
+                .draw(display)
+                .ok();
+        }
+
+        // status bar separator and floor
+        Line::new(Point::new(0, 8), Point::new(63, 8))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(display)
+            .ok();
+        Line::new(Point::new(0, FLOOR_Y), Point::new(63, FLOOR_Y))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(display)
+            .ok();
+    }
+}
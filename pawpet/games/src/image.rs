@@ -1,27 +1,191 @@
-use core::{mem::size_of, ptr::null};
+use embedded_graphics::image::ImageDrawable;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
 use pawdevicetraits::DisplayDevice;
 use rtt_target::debug_rprintln;
 
-#[repr(C)]
-#[repr(packed)]
+use crate::lz;
+use crate::lz::LzBytes;
+
+const HEADER_SIZE: usize = 8;
+// high bit of the encoding field, a u16 duration in ms per frame follows the frame offsets
+const FRAME_DURATIONS_FLAG: u16 = 0x8000;
+
+#[derive(Debug, PartialEq)]
+pub enum PawImageError {
+    Truncated,
+    BadEncoding,
+    BadSize,
+    BadTileCount,
+    BadFrameOffset,
+    FrameTruncated,
+}
+
+/**
+ * .paw header, all fields are little endian u16 in the file:
+ * width, height, encoding, tile count, then an array of u16 frame offsets if there is more
+ * than one tile and optionally an array of u16 frame durations.
+ *
+ * Encoding 0 bitmap binary, 1 bitmap alpha, 2 span array binary, 3 span array alpha,
+ * 4 LZ compressed bitmap binary, 5 LZ compressed bitmap alpha.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PawImageHeader {
-    width: u16,
-    height: u16,
-    encoding: u16, // 2 - span array binary, 3 span array alhpa, 0 bitmap binary, 1 bitmap alpha
-    tile_count: u16,
-    // array of tile offsets u16
+    pub width: u8,
+    pub height: u8,
+    pub encoding: u8,
+    pub tile_count: u8,
+    pub frame_durations: bool,
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    return u16::from_le_bytes([data[at], data[at + 1]]);
+}
+
+impl PawImageHeader {
+    pub const EMPTY: Self = Self {
+        width: 0,
+        height: 0,
+        encoding: 0,
+        tile_count: 0,
+        frame_durations: false,
+    };
+
+    /**
+     * Validates the header, the frame offset table and that every frame has enough pixel data,
+     * after this the decoders can't read past the end of the slice.
+     */
+    pub fn parse(data: &[u8]) -> Result<Self, PawImageError> {
+        if data.len() < HEADER_SIZE {
+            return Err(PawImageError::Truncated);
+        }
+
+        let width = read_u16(data, 0);
+        let height = read_u16(data, 2);
+        let encoding = read_u16(data, 4);
+        let tile_count = read_u16(data, 6);
+
+        let frame_durations = encoding & FRAME_DURATIONS_FLAG != 0;
+        let encoding = encoding & !FRAME_DURATIONS_FLAG;
+
+        if encoding > 5 {
+            return Err(PawImageError::BadEncoding);
+        }
+        if width == 0 || height == 0 || width > u8::MAX as u16 || height > u8::MAX as u16 {
+            return Err(PawImageError::BadSize);
+        }
+        if tile_count == 0 || tile_count > u8::MAX as u16 {
+            return Err(PawImageError::BadTileCount);
+        }
+
+        let header = Self {
+            width: width as u8,
+            height: height as u8,
+            encoding: encoding as u8,
+            tile_count: tile_count as u8,
+            frame_durations,
+        };
+
+        if data.len() < HEADER_SIZE + header.offsets_length() + header.durations_length() {
+            return Err(PawImageError::Truncated);
+        }
+
+        for frame in 0..header.tile_count {
+            let start = header.frame_start(data, frame);
+            if start >= data.len() {
+                return Err(PawImageError::BadFrameOffset);
+            }
+            header.check_frame(&data[start..])?;
+        }
+
+        return Ok(header);
+    }
+
+    // png2c only writes the table for more than one tile
+    fn offsets_length(&self) -> usize {
+        if self.tile_count > 1 {
+            return self.tile_count as usize * 2;
+        }
+        return 0;
+    }
+
+    fn durations_length(&self) -> usize {
+        if self.frame_durations {
+            return self.tile_count as usize * 2;
+        }
+        return 0;
+    }
+
+    // index of the first pixel byte of a frame, the offset table must be in bounds
+    fn frame_start(&self, data: &[u8], frame: u8) -> usize {
+        let pixels_start = HEADER_SIZE + self.offsets_length() + self.durations_length();
+
+        // sprite map/animation
+        if self.tile_count > 1 {
+            return pixels_start + read_u16(data, HEADER_SIZE + frame as usize * 2) as usize;
+        }
+        return pixels_start;
+    }
+
+    // None if the file has no durations, the animation uses its own frame time
+    fn frame_duration_ms(&self, data: &[u8], frame: u8) -> Option<u16> {
+        if !self.frame_durations {
+            return None;
+        }
+        return Some(read_u16(data, HEADER_SIZE + self.offsets_length() + frame as usize * 2));
+    }
+
+    // pixel data from the frame start to the end of the file covers the whole frame
+    fn check_frame(&self, data: &[u8]) -> Result<(), PawImageError> {
+        let pixels = self.width as usize * self.height as usize;
+
+        let length_mask = match self.encoding {
+            0 => return Self::check_length(data, pixels.div_ceil(8)),
+            1 => return Self::check_length(data, (pixels * 2).div_ceil(8)),
+            2 => 0x7F,
+            3 => 0x3F,
+            4 => return Self::check_lz(data, pixels.div_ceil(8)),
+            _ => return Self::check_lz(data, (pixels * 2).div_ceil(8)),
+        };
+
+        let mut covered = 0;
+        for byte in data {
+            covered += (byte & length_mask) as usize;
+            if covered >= pixels {
+                return Ok(());
+            }
+        }
+        return Err(PawImageError::FrameTruncated);
+    }
+
+    fn check_lz(data: &[u8], length: usize) -> Result<(), PawImageError> {
+        if !lz::check(data, length) {
+            return Err(PawImageError::FrameTruncated);
+        }
+        return Ok(());
+    }
+
+    fn check_length(data: &[u8], length: usize) -> Result<(), PawImageError> {
+        if data.len() < length {
+            return Err(PawImageError::FrameTruncated);
+        }
+        return Ok(());
+    }
 }
 
 pub struct PawImage {
     on_color: bool,
     off_color: bool,
     alpha_color: Option<bool>,
+    flip_x: bool,
+    flip_y: bool,
+    inverted: bool,
+    clip: Option<Rectangle>,
     data: Option<&'static [u8]>,
     frame: u8,
     image_ptr_offset: usize,
-    encoding: u8,
-    width: u8,
-    height: u8,
+    header: PawImageHeader,
 }
 
 impl PawImage {
@@ -30,12 +194,14 @@ impl PawImage {
             off_color: false,
             on_color: true,
             alpha_color: None,
+            flip_x: false,
+            flip_y: false,
+            inverted: false,
+            clip: None,
             data,
             frame: 0,
             image_ptr_offset: 0,
-            encoding: 0,
-            width: 0,
-            height: 0,
+            header: PawImageHeader::EMPTY,
         };
         s.set_image(data);
         return s;
@@ -46,18 +212,41 @@ impl PawImage {
             off_color: false,
             on_color: true,
             alpha_color: None,
+            flip_x: false,
+            flip_y: false,
+            inverted: false,
+            clip: None,
             data: None,
             frame: 0,
             image_ptr_offset: 0,
-            encoding: 0,
-            width: 0,
-            height: 0,
+            header: PawImageHeader::EMPTY,
         }
     }
 
+    // an image that fails to parse is dropped, nothing is drawn until a valid one is set
     pub fn set_image(&mut self, data: Option<&'static [u8]>) {
-        self.data = data;
+        self.data = None;
+
+        let data = match data {
+            Some(data) => data,
+            None => return,
+        };
+
+        match PawImageHeader::parse(data) {
+            Ok(header) => {
+                self.data = Some(data);
+                self.header = header;
+            }
+            Err(error) => {
+                debug_rprintln!("bad image {:?}", error);
+                self.header = PawImageHeader::EMPTY;
+                return;
+            }
+        }
 
+        if self.frame >= self.header.tile_count {
+            self.frame = 0;
+        }
         self.update_image_data_offset();
     }
 
@@ -67,274 +256,968 @@ impl PawImage {
         self.alpha_color = alpha_color;
     }
 
-    pub fn update_image_data_offset(&mut self) {
-        if self.data.is_none() {
-            return;
-        }
-
-        let data = self.data.unwrap();
+    // mirrored drawing, one sprite sheet serves both walking directions
+    pub fn set_flip(&mut self, flip_x: bool, flip_y: bool) {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+    }
 
-        let meta_ptr: *const PawImageHeader =
-            unsafe { core::mem::transmute(data[0..size_of::<PawImageHeader>()].as_ptr()) };
+    // swaps on and off colors, transparent pixels stay transparent
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
+    }
 
-        let meta: &PawImageHeader = unsafe { &*meta_ptr };
+    // only the part of the image inside `clip` is drawn, in image coordinates after flipping
+    pub fn set_clip(&mut self, clip: Option<Rectangle>) {
+        self.clip = clip;
+    }
 
-        self.image_ptr_offset = size_of::<PawImageHeader>();
+    pub fn update_image_data_offset(&mut self) {
+        if let Some(data) = self.data {
+            self.image_ptr_offset = self.header.frame_start(data, self.frame);
+        }
+    }
 
+    pub fn width(&self) -> u8 {
+        return self.header.width;
+    }
 
-        // todo: fix image encoder
-        if meta.encoding == 2 || meta.encoding == 3
-        {
-            // its adding tile array offset for files that don't need it
-            // or make it consistent for bitmaps
-            self.image_ptr_offset = size_of::<PawImageHeader>() + 2;
-        }
-        
-        // sprite map/animation
-        if meta.tile_count > 1 {
-            let frame_offsets_list = &data[size_of::<PawImageHeader>()..data.len()];
-            let frame_offsets_length = meta.tile_count * 2;
+    pub fn height(&self) -> u8 {
+        return self.header.height;
+    }
 
-            let frame = (self.frame as usize) * 2;
-            let frame_offset_bytes = [frame_offsets_list[frame], frame_offsets_list[frame + 1]];
-            let frame_offset = u16::from_le_bytes(frame_offset_bytes) as usize;
+    pub fn tile_count(&self) -> u8 {
+        return self.header.tile_count;
+    }
 
-            self.image_ptr_offset =
-                size_of::<PawImageHeader>() + (frame_offsets_length as usize) + frame_offset;
+    // duration of a frame stored in the image, if it has any
+    pub fn frame_duration_ms(&self, frame: u8) -> Option<u16> {
+        match self.data {
+            Some(data) if frame < self.header.tile_count => {
+                return self.header.frame_duration_ms(data, frame);
+            }
+            _ => return None,
         }
-
-        self.width = meta.width as u8;
-        self.height = meta.height as u8;
-        self.encoding = meta.encoding as u8;
     }
 
+    // frames past the end of the sprite map are ignored
     pub fn set_frame(&mut self, frame: u8) {
+        if self.data.is_some() && frame >= self.header.tile_count {
+            return;
+        }
         self.frame = frame;
 
         self.update_image_data_offset();
     }
 
-    pub fn draw(&self, disp: &mut impl DisplayDevice, x: u8, y: u8) {
+    // native display fast path, sprites can be placed partially or fully off screen
+    pub fn draw(&self, disp: &mut impl DisplayDevice, x: i32, y: i32) {
+        self.decode(&mut NativeTarget { disp, dx: x, dy: y });
+    }
+
+    fn decode(&self, target: &mut impl SpanTarget) {
         if self.data.is_none() {
             return;
         }
 
         let data = self.data.unwrap();
 
-        let image_ptr =
-            unsafe { core::mem::transmute(data[self.image_ptr_offset..data.len()].as_ptr()) };
+        let frame_data = &data[self.image_ptr_offset..];
+
+        let (mut x0, mut y0, mut x1, mut y1) = (0, 0, self.header.width as i32, self.header.height as i32);
+        if let Some(clip) = self.clip {
+            x0 = clip.top_left.x.max(0);
+            y0 = clip.top_left.y.max(0);
+            x1 = (clip.top_left.x + clip.size.width as i32).min(x1);
+            y1 = (clip.top_left.y + clip.size.height as i32).min(y1);
+        }
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let target = &mut TransformTarget {
+            target,
+            width: self.header.width,
+            height: self.header.height,
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+            inverted: self.inverted,
+            clip: (x0, y0, x1, y1),
+        };
 
-        match self.encoding {
+        match self.header.encoding {
             0 => {
                 //bitmap no alpha
-                self.draw_bitmap(disp, image_ptr, x, y);
+                self.decode_bitmap(target, frame_data.iter().copied());
             }
             1 => {
                 //bitmap with alpha
-                self.draw_bitmap_alpha(disp, image_ptr, x, y);
+                self.decode_bitmap_alpha(target, frame_data.iter().copied());
             }
             2 => {
                 //span
-                self.draw_span(disp, image_ptr, x, y);
+                self.decode_span(target, frame_data);
             }
             3 => {
                 // span with alpha
-                self.draw_span_alpha(disp, image_ptr, x, y);
+                self.decode_span_alpha(target, frame_data);
+            }
+            4 => {
+                // compressed bitmap, decoded while drawing
+                self.decode_bitmap(target, LzBytes::new(frame_data));
+            }
+            5 => {
+                // compressed bitmap with alpha
+                self.decode_bitmap_alpha(target, LzBytes::new(frame_data));
             }
             _ => {}
         }
     }
 
-    pub fn draw_span(&self, disp: &mut impl DisplayDevice, data: *const u8, dx: u8, dy: u8) {
-        let dyt = dy + self.height as u8;
-        let dxt = dx + self.width as u8;
-
+    // runs are written as spans, a run can continue on the next row
+    fn decode_span(&self, target: &mut impl SpanTarget, data: &[u8]) {
         let mut curr_byte = 0;
-        let mut pixel_data = unsafe { *data.offset(curr_byte) };
+        let mut pixel_data = data[curr_byte];
         let mut length = pixel_data & 0x7F;
 
-        for y in dy..dyt {
-            for x in dx..dxt {
+        for y in 0..self.header.height {
+            let mut x = 0;
+            while x < self.header.width {
                 if length == 0 {
                     curr_byte += 1;
-                    pixel_data = unsafe { *data.offset(curr_byte) };
+                    pixel_data = data[curr_byte];
                     length = pixel_data & 0x7F;
+                    continue;
                 }
 
-                if (pixel_data >> 7) & 0x1 > 0 {
-                    disp.draw_pixel(x, y, self.on_color);
+                let count = length.min(self.header.width - x);
+                let color = if (pixel_data >> 7) & 0x1 > 0 {
+                    self.on_color
                 } else {
-                    disp.draw_pixel(x, y, self.off_color);
-                }
+                    self.off_color
+                };
+                target.span(x, y, count, color);
 
-                length -= 1;
+                x += count;
+                length -= count;
             }
         }
     }
-    pub fn draw_span_alpha(
-        &self,
-        disp: &mut impl DisplayDevice,
-        data: *const u8,
-        dx: u8,
-        dy: u8,
-    ) {
+
+    fn decode_span_alpha(&self, target: &mut impl SpanTarget, data: &[u8]) {
         let mut curr_byte = 0;
-        let mut pixel_data = unsafe { *data.offset(curr_byte) };
+        let mut pixel_data = data[curr_byte];
 
         let mut length = pixel_data & 0x3F;
         let mut color = (pixel_data >> 6) & 0x3;
 
-        let dyt = dy + self.height as u8;
-        let dxt = dx + self.width as u8;
-
-        for y in dy..dyt {
-            for x in dx..dxt {
+        for y in 0..self.header.height {
+            let mut x = 0;
+            while x < self.header.width {
                 if length == 0 {
                     curr_byte += 1;
-                    pixel_data = unsafe { *data.offset(curr_byte) };
+                    pixel_data = data[curr_byte];
                     length = pixel_data & 0x3F;
                     color = (pixel_data >> 6) & 0x3;
+                    continue;
                 }
 
-                match color {
-                    0 => {
-                        disp.draw_pixel(x, y, self.off_color);
-                    }
-                    1 => {
-                        disp.draw_pixel(x, y, self.on_color);
-                    }
-                    2 => {
-                        if self.alpha_color.is_some() {
-                            disp.draw_pixel(x, y, self.alpha_color.unwrap());
-                        }
-                    }
-                    _ => {}
+                let count = length.min(self.header.width - x);
+                if let Some(color) = self.pixel_color(color) {
+                    target.span(x, y, count, color);
                 }
-                length -= 1;
+
+                x += count;
+                length -= count;
             }
         }
     }
 
-    pub fn draw_bitmap(&self, disp: &mut impl DisplayDevice, data: *const u8, dx: u8, dy: u8) {
-        let mut pack: u8 = 0;
-        let mut bit_index: u8 = 0;
-        let mut packed_u8_index: isize = 0;
+    // 2 bit pixel value to a color, None is transparent
+    fn pixel_color(&self, pixel: u8) -> Option<bool> {
+        match pixel {
+            0 => return Some(self.off_color),
+            1 => return Some(self.on_color),
+            2 => return self.alpha_color,
+            _ => return None,
+        }
+    }
 
-        let dyt = dy + self.height as u8;
-        let dxt = dx + self.width as u8;
+    // bits are packed across rows, whole bytes that fall inside a row are blitted
+    fn decode_bitmap(&self, target: &mut impl SpanTarget, mut bytes: impl Iterator<Item = u8>) {
+        let mut bit: usize = 0;
+        let mut pack = 0;
 
-        for y in dy..dyt {
-            for x in dx..dxt {
-                // shift saved byte to next bit
-                if bit_index > 0 {
-                    pack >>= 1;
-                    bit_index -= 1;
+        for y in 0..self.header.height {
+            let mut x = 0;
+            while x < self.header.width {
+                if bit % 8 == 0 {
+                    // frame length was checked by the parser
+                    pack = bytes.next().unwrap_or(0);
                 }
-                // read next byte
-                else {
-                    bit_index = 7;
-                    pack = unsafe { *data.offset(packed_u8_index) };
-                    packed_u8_index += 1;
+
+                if bit % 8 == 0 && self.header.width - x >= 8 {
+                    target.blit8(x, y, pack, self.on_color, self.off_color);
+                    x += 8;
+                    bit += 8;
+                    continue;
                 }
 
-                if pack & 0x1 > 0 {
-                    disp.draw_pixel(x, y, self.on_color);
+                let color = if (pack >> (bit % 8)) & 0x1 > 0 {
+                    self.on_color
                 } else {
-                    disp.draw_pixel(x, y, self.off_color);
-                }
+                    self.off_color
+                };
+                target.span(x, y, 1, color);
+                x += 1;
+                bit += 1;
             }
         }
     }
-    pub fn draw_bitmap_alpha(
-        &self,
-        disp: &mut impl DisplayDevice,
-        data: *const u8,
-        dx: u8,
-        dy: u8,
-    ) {
-        let mut pack: u8 = 0;
-        let mut bit_index: u8 = 0;
-        let mut packed_u8_index: isize = 0;
-        let dyt = dy + self.height as u8;
-        let dxt = dx + self.width as u8;
-
-        for y in dy..dyt {
-            for x in dx..dxt {
-                // shift saved byte to next bit
-                if bit_index > 0 {
-                    pack >>= 2;
-                    bit_index -= 1;
-                }
-                // read next byte
-                else {
-                    bit_index = 3;
-                    pack = unsafe { *data.offset(packed_u8_index) };
-                    packed_u8_index += 1;
+
+    // runs of equal pixels within a row are written as spans
+    fn decode_bitmap_alpha(&self, target: &mut impl SpanTarget, mut bytes: impl Iterator<Item = u8>) {
+        let mut index: usize = 0;
+        let mut pack = 0;
+
+        for y in 0..self.header.height {
+            let mut run_start = 0;
+            let mut run_pixel = None;
+
+            for x in 0..self.header.width {
+                if index % 4 == 0 {
+                    pack = bytes.next().unwrap_or(0);
                 }
+                let pixel = (pack >> ((index % 4) * 2)) & 0x3;
+                index += 1;
 
-                let pixel = pack & 0x3;
-                match pixel {
-                    0 => {
-                        disp.draw_pixel(x, y, self.off_color);
-                    }
-                    1 => {
-                        disp.draw_pixel(x, y, self.on_color);
-                    }
-                    2 => {
-                        if self.alpha_color.is_some() {
-                            disp.draw_pixel(x, y, self.alpha_color.unwrap());
-                        }
+                if run_pixel != Some(pixel) {
+                    if let Some(color) = run_pixel.and_then(|p| self.pixel_color(p)) {
+                        target.span(run_start, y, x - run_start, color);
                     }
-                    _ => {}
+                    run_start = x;
+                    run_pixel = Some(pixel);
                 }
             }
+
+            if let Some(color) = run_pixel.and_then(|p| self.pixel_color(p)) {
+                target.span(run_start, y, self.header.width - run_start, color);
+            }
+        }
+    }
+}
+
+impl OriginDimensions for PawImage {
+    fn size(&self) -> Size {
+        return Size::new(self.header.width as u32, self.header.height as u32);
+    }
+}
+
+// current frame as an embedded-graphics image, for any draw target:
+// Image::new(&sprite, Point::new(-4, 10)).draw(target)
+impl ImageDrawable for PawImage {
+    type Color = BinaryColor;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let mut target = GraphicsTarget {
+            target,
+            result: Ok(()),
+        };
+        self.decode(&mut target);
+        return target.result;
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let mut translated = target.translated(-area.top_left);
+        let mut clipped = translated.clipped(&Rectangle::new(Point::zero(), area.size));
+        return ImageDrawable::draw(self, &mut clipped);
+    }
+}
+
+/**
+ * Receives decoded pixels as horizontal runs, positions are relative to the image origin.
+ * Runs never cross a row.
+ */
+trait SpanTarget {
+    fn span(&mut self, x: u8, y: u8, length: u8, color: bool);
+    // 8 pixels of a bitmap byte, bit 0 is the leftmost pixel
+    fn blit8(&mut self, x: u8, y: u8, bits: u8, on_color: bool, off_color: bool);
+}
+
+/**
+ * Applies flips, inversion and the clip rectangle to decoded runs before they reach the target,
+ * the decoders stay the same for every encoding.
+ */
+struct TransformTarget<'a, T: SpanTarget> {
+    target: &'a mut T,
+    width: u8,
+    height: u8,
+    flip_x: bool,
+    flip_y: bool,
+    inverted: bool,
+    clip: (i32, i32, i32, i32), // x0, y0, x1, y1 exclusive
+}
+
+impl<'a, T: SpanTarget> TransformTarget<'a, T> {
+    fn row(&self, y: u8) -> u8 {
+        if self.flip_y {
+            return self.height - 1 - y;
+        }
+        return y;
+    }
+
+    // run already in transformed coordinates
+    fn clipped_span(&mut self, x: i32, y: u8, length: i32, color: bool) {
+        let (cx0, cy0, cx1, cy1) = self.clip;
+        let x0 = x.max(cx0);
+        let x1 = (x + length).min(cx1);
+
+        if (y as i32) < cy0 || (y as i32) >= cy1 || x0 >= x1 {
+            return;
+        }
+        self.target.span(x0 as u8, y, (x1 - x0) as u8, color);
+    }
+}
+
+impl<'a, T: SpanTarget> SpanTarget for TransformTarget<'a, T> {
+    fn span(&mut self, x: u8, y: u8, length: u8, color: bool) {
+        let x = if self.flip_x {
+            self.width - x - length
+        } else {
+            x
+        };
+        let y = self.row(y);
+        self.clipped_span(x as i32, y, length as i32, color != self.inverted);
+    }
+
+    fn blit8(&mut self, x: u8, y: u8, bits: u8, on_color: bool, off_color: bool) {
+        let (bits, x) = if self.flip_x {
+            (bits.reverse_bits(), self.width - x - 8)
+        } else {
+            (bits, x)
+        };
+        let y = self.row(y);
+        let on_color = on_color != self.inverted;
+        let off_color = off_color != self.inverted;
+
+        let (cx0, cy0, cx1, cy1) = self.clip;
+        if x as i32 >= cx0 && x as i32 + 8 <= cx1 && y as i32 >= cy0 && (y as i32) < cy1 {
+            self.target.blit8(x, y, bits, on_color, off_color);
+            return;
+        }
+
+        for i in 0..8 {
+            let color = if (bits >> i) & 1 > 0 { on_color } else { off_color };
+            self.clipped_span(x as i32 + i, y, 1, color);
         }
     }
 }
 
+struct NativeTarget<'a, D: DisplayDevice> {
+    disp: &'a mut D,
+    dx: i32,
+    dy: i32,
+}
+
+impl<'a, D: DisplayDevice> SpanTarget for NativeTarget<'a, D> {
+    fn span(&mut self, x: u8, y: u8, length: u8, color: bool) {
+        let y = self.dy + y as i32;
+        let x0 = (self.dx + x as i32).max(0);
+        let x1 = (self.dx + x as i32 + length as i32).min(64);
+
+        if y < 0 || y >= 64 || x0 >= x1 {
+            return;
+        }
+        self.disp.draw_span(x0 as u8, y as u8, (x1 - x0) as u8, color);
+    }
+
+    fn blit8(&mut self, x: u8, y: u8, bits: u8, on_color: bool, off_color: bool) {
+        let px = self.dx + x as i32;
+        let py = self.dy + y as i32;
+
+        if px >= 0 && px + 8 <= 64 && py >= 0 && py < 64 {
+            self.disp.blit8(px as u8, py as u8, bits, on_color, off_color);
+            return;
+        }
+
+        // partially visible, clipped per pixel
+        for i in 0..8 {
+            let color = if (bits >> i) & 1 > 0 { on_color } else { off_color };
+            self.span(x + i, y, 1, color);
+        }
+    }
+}
+
+struct GraphicsTarget<'a, D: DrawTarget<Color = BinaryColor>> {
+    target: &'a mut D,
+    result: Result<(), D::Error>, // first error, remaining spans are skipped
+}
+
+impl<'a, D: DrawTarget<Color = BinaryColor>> SpanTarget for GraphicsTarget<'a, D> {
+    fn span(&mut self, x: u8, y: u8, length: u8, color: bool) {
+        if self.result.is_err() {
+            return;
+        }
+
+        // the target clips, the native display writes whole bytes for solid fills
+        let area = Rectangle::new(Point::new(x as i32, y as i32), Size::new(length as u32, 1));
+        self.result = self.target.fill_solid(&area, BinaryColor::from(color));
+    }
+
+    fn blit8(&mut self, x: u8, y: u8, bits: u8, on_color: bool, off_color: bool) {
+        let area = Rectangle::new(Point::new(x as i32, y as i32), Size::new(8, 1));
+        let colors = (0..8).map(|i| {
+            let on = (bits >> i) & 1 > 0;
+            BinaryColor::from(if on { on_color } else { off_color })
+        });
+
+        if self.result.is_ok() {
+            self.result = self.target.fill_contiguous(&area, colors);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PlayMode {
+    Forward,
+    Reverse,
+    PingPong,
+    Once, // forward, then holds the last frame
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AnimationEvent {
+    Looped,   // back at the first frame of the cycle
+    Finished, // one-shot reached its last frame
+}
+
+/**
+ * Plays the frames start..end of a sprite map. Time advances in milliseconds so playback speed
+ * does not depend on the framerate of the state. Frame durations stored in the image take
+ * priority over `frame_ms`.
+ */
 pub struct PawAnimation {
     image: PawImage,
-    ticks_per_frame: u16,
-    tick: u16,
-    loop_bounds: (u8, u8), // dir: pinpong/forward
+    mode: PlayMode,
+    bounds: (u8, u8), // start, end exclusive
+    frame_ms: u16,
+    elapsed_ms: u32,
+    frame: u8,
+    backwards: bool, // ping-pong direction
+    finished: bool,
+    event: Option<AnimationEvent>,
 }
 
 impl PawAnimation {
-    pub fn new(bounds: (u8, u8), ticks_per_frame: u16) -> Self {
+    pub fn new(bounds: (u8, u8), frame_ms: u16) -> Self {
         Self {
             image: PawImage::new(None),
-            ticks_per_frame: ticks_per_frame,
-            tick: 0,
-            loop_bounds: bounds,
+            mode: PlayMode::Forward,
+            bounds,
+            frame_ms,
+            elapsed_ms: 0,
+            frame: bounds.0,
+            backwards: false,
+            finished: false,
+            event: None,
         }
     }
 
     pub fn set_image(&mut self, data: Option<&'static [u8]>) {
         self.image.set_image(data);
+        self.image.set_frame(self.frame);
+    }
+
+    // restarts playback of frames bounds.0..bounds.1
+    pub fn play(&mut self, mode: PlayMode, bounds: (u8, u8), frame_ms: u16) {
+        self.mode = mode;
+        self.bounds = bounds;
+        self.frame_ms = frame_ms;
+        self.restart();
+    }
+
+    pub fn set_loop(&mut self, bounds: (u8, u8), frame_ms: u16) {
+        self.play(PlayMode::Forward, bounds, frame_ms);
     }
 
-    pub fn tick(&mut self) {
-        self.tick = self.tick + 1;
+    pub fn restart(&mut self) {
+        let (start, end) = self.range();
+        self.elapsed_ms = 0;
+        self.backwards = false;
+        self.finished = false;
+        self.event = None;
+        self.show(if self.mode == PlayMode::Reverse { end - 1 } else { start });
+    }
+
+    // call once per state tick with the frame time
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        if self.finished {
+            return;
+        }
+        self.elapsed_ms += elapsed_ms;
+
+        loop {
+            let duration = self.duration_ms() as u32;
+            if self.elapsed_ms < duration {
+                return;
+            }
+            self.elapsed_ms -= duration;
+            self.step();
 
-        if self.tick > self.ticks_per_frame {
-            self.tick = 0;
-            if self.image.frame + 1 >= self.loop_bounds.1 {
-                self.image.set_frame(0);
-            } else {
-                self.image.set_frame(self.image.frame + 1);
+            if self.finished {
+                self.elapsed_ms = 0;
+                return;
             }
         }
     }
 
+    // latest event since the last call
+    pub fn take_event(&mut self) -> Option<AnimationEvent> {
+        return self.event.take();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.finished;
+    }
+
+    pub fn frame(&self) -> u8 {
+        return self.frame;
+    }
+
+    // bounds limited to the frames of the image, never empty
+    fn range(&self) -> (u8, u8) {
+        let mut end = self.bounds.1;
+        if self.image.tile_count() > 0 {
+            end = end.min(self.image.tile_count());
+        }
+        let start = self.bounds.0.min(end.saturating_sub(1));
+        return (start, end.max(start + 1));
+    }
+
+    fn duration_ms(&self) -> u16 {
+        let duration = self.image.frame_duration_ms(self.frame).unwrap_or(self.frame_ms);
+        // a zero duration would never let time pass
+        return duration.max(1);
+    }
+
+    fn step(&mut self) {
+        let (start, end) = self.range();
+        let last = end - 1;
+        let frame = self.frame.clamp(start, last);
+
+        let next = match self.mode {
+            PlayMode::Forward => {
+                if frame >= last {
+                    self.event = Some(AnimationEvent::Looped);
+                    start
+                } else {
+                    frame + 1
+                }
+            }
+            PlayMode::Reverse => {
+                if frame <= start {
+                    self.event = Some(AnimationEvent::Looped);
+                    last
+                } else {
+                    frame - 1
+                }
+            }
+            PlayMode::PingPong => {
+                if (self.backwards && frame <= start) || (!self.backwards && frame >= last) {
+                    self.backwards = !self.backwards;
+                }
+                if self.backwards {
+                    let next = frame.saturating_sub(1).max(start);
+                    if next == start {
+                        self.event = Some(AnimationEvent::Looped);
+                    }
+                    next
+                } else {
+                    (frame + 1).min(last)
+                }
+            }
+            PlayMode::Once => {
+                // the last frame is shown for its full duration before finishing
+                if frame >= last {
+                    self.finished = true;
+                    self.event = Some(AnimationEvent::Finished);
+                    last
+                } else {
+                    frame + 1
+                }
+            }
+        };
+        self.show(next);
+    }
+
+    fn show(&mut self, frame: u8) {
+        self.frame = frame;
+        self.image.set_frame(frame);
+    }
+
     pub fn set_colors(&mut self, on_color: bool, off_color: bool, alpha_color: Option<bool>) {
         self.image.set_colors(on_color, off_color, alpha_color);
     }
 
     pub fn set_frame(&mut self, frame: u8) {
-        self.image.set_frame(frame);
+        self.show(frame);
+    }
+
+    pub fn set_flip(&mut self, flip_x: bool, flip_y: bool) {
+        self.image.set_flip(flip_x, flip_y);
+    }
+
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.image.set_inverted(inverted);
+    }
+
+    pub fn set_clip(&mut self, clip: Option<Rectangle>) {
+        self.image.set_clip(clip);
+    }
+
+    pub fn width(&self) -> u8 {
+        return self.image.width();
+    }
+
+    pub fn tile_count(&self) -> u8 {
+        return self.image.tile_count();
     }
 
-    pub fn draw(&self, disp: &mut impl DisplayDevice, dx: u8, dy: u8) {
+    // current frame, for drawing into other targets with Image
+    pub fn image(&self) -> &PawImage {
+        return &self.image;
+    }
+
+    pub fn draw(&self, disp: &mut impl DisplayDevice, dx: i32, dy: i32) {
         self.image.draw(disp, dx, dy);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::Grid;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    // '#' on, '.' off, ' ' transparent
+    const BINARY: [&str; 3] = ["###......#", "#...##....", "..#......."];
+    const ALPHA: [&str; 3] = ["##  #...  ", " .#. ##   ", "#  ..  . #"];
+
+    // flip_x, flip_y, inverted and the expected rows
+    const BINARY_GOLDEN: [(bool, bool, bool, [&str; 3]); 8] = [
+        (false, false, false, ["###......#", "#...##....", "..#......."]),
+        (false, false, true, ["...######.", ".###..####", "##.#######"]),
+        (false, true, false, ["..#.......", "#...##....", "###......#"]),
+        (false, true, true, ["##.#######", ".###..####", "...######."]),
+        (true, false, false, ["#......###", "....##...#", ".......#.."]),
+        (true, false, true, [".######...", "####..###.", "#######.##"]),
+        (true, true, false, [".......#..", "....##...#", "#......###"]),
+        (true, true, true, ["#######.##", "####..###.", ".######..."]),
+    ];
+    const ALPHA_GOLDEN: [(bool, bool, bool, [&str; 3]); 8] = [
+        (false, false, false, ["##  #...  ", " .#. ##   ", "#  ..  . #"]),
+        (false, false, true, ["..  .###  ", " #.# ..   ", ".  ##  # ."]),
+        (false, true, false, ["#  ..  . #", " .#. ##   ", "##  #...  "]),
+        (false, true, true, [".  ##  # .", " #.# ..   ", "..  .###  "]),
+        (true, false, false, ["  ...#  ##", "   ## .#. ", "# .  ..  #"]),
+        (true, false, true, ["  ###.  ..", "   .. #.# ", ". #  ##  ."]),
+        (true, true, false, ["# .  ..  #", "   ## .#. ", "  ...#  ##"]),
+        (true, true, true, [". #  ##  .", "   .. #.# ", "  ###.  .."]),
+    ];
+
+    fn pixel_value(c: u8) -> u8 {
+        match c {
+            b'#' => return 1,
+            b'.' => return 0,
+            _ => return 3,
+        }
+    }
+
+    // single tile .paw file of the pattern in the given encoding
+    fn encode(rows: &[&str; 3], encoding: u8) -> &'static [u8] {
+        let width = rows[0].len();
+        let pixels: Vec<u8> = rows.iter().flat_map(|r| r.bytes()).map(pixel_value).collect();
+
+        let mut data = Vec::new();
+        for value in [width as u16, rows.len() as u16, encoding as u16, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        match encoding {
+            0 | 1 => {
+                let bits = if encoding == 0 { 1 } else { 2 };
+                let mut packed = std::vec![0u8; (pixels.len() * bits).div_ceil(8)];
+                for (i, pixel) in pixels.iter().enumerate() {
+                    let bit = i * bits;
+                    packed[bit / 8] |= (pixel & ((1 << bits) - 1)) << (bit % 8);
+                }
+                data.extend_from_slice(&packed);
+            }
+            _ => {
+                let (shift, max) = if encoding == 2 { (7, 0x7F) } else { (6, 0x3F) };
+                let mut i = 0;
+                while i < pixels.len() {
+                    let mut length = 1;
+                    while i + length < pixels.len() && pixels[i + length] == pixels[i] && length < max {
+                        length += 1;
+                    }
+                    data.push((pixels[i] << shift) | length as u8);
+                    i += length;
+                }
+            }
+        }
+        return Box::leak(data.into_boxed_slice());
+    }
+
+    // files written by png2c's packPixels, lzCompress and toBytes
+    // BINARY then BINARY flipped in x, a 2 tile lz sprite map
+    const PNG2C_LZ_BINARY: [u8; 22] = [
+        10, 0, 3, 0, 4, 0, 2, 0, 0, 0, 5, 0, 3, 7, 198, 64, 0, 3, 129, 195, 8, 8,
+    ];
+    const PNG2C_LZ_ALPHA: [u8; 17] = [10, 0, 3, 0, 5, 0, 1, 0, 7, 165, 1, 42, 97, 169, 41, 40, 6];
+    // BINARY from a sprite map with a single tile, no offset table
+    const PNG2C_SINGLE_TILE: [u8; 12] = [10, 0, 3, 0, 0, 0, 1, 0, 7, 198, 64, 0];
+
+    // draws at (1, 1) and reads back the image area, untouched pixels are ' '
+    fn render(image: &PawImage) -> [std::string::String; 3] {
+        let mut grid = Grid::new();
+        image.draw(&mut grid, 1, 1);
+
+        return core::array::from_fn(|y| {
+            std::string::String::from_utf8(grid.pixels[y + 1][1..11].to_vec()).unwrap()
+        });
+    }
+
+    fn check_golden(data: &'static [u8], golden: &[(bool, bool, bool, [&str; 3]); 8]) {
+        let encoding = data[4];
+        let mut image = PawImage::new(Some(data));
+
+        for (flip_x, flip_y, inverted, expected) in golden {
+            image.set_flip(*flip_x, *flip_y);
+            image.set_inverted(*inverted);
+            assert_eq!(
+                render(&image),
+                *expected,
+                "encoding {} flip {} {} inverted {}",
+                encoding,
+                flip_x,
+                flip_y,
+                inverted
+            );
+        }
+    }
+
+    #[test]
+    fn bitmap_transforms() {
+        check_golden(encode(&BINARY, 0), &BINARY_GOLDEN);
+    }
+
+    #[test]
+    fn bitmap_alpha_transforms() {
+        check_golden(encode(&ALPHA, 1), &ALPHA_GOLDEN);
+    }
+
+    #[test]
+    fn span_transforms() {
+        check_golden(encode(&BINARY, 2), &BINARY_GOLDEN);
+    }
+
+    #[test]
+    fn span_alpha_transforms() {
+        check_golden(encode(&ALPHA, 3), &ALPHA_GOLDEN);
+    }
+
+    #[test]
+    fn lz_transforms() {
+        check_golden(&PNG2C_LZ_BINARY, &BINARY_GOLDEN);
+        check_golden(&PNG2C_LZ_ALPHA, &ALPHA_GOLDEN);
+    }
+
+    #[test]
+    fn png2c_files_parse() {
+        let mut image = PawImage::new(Some(&PNG2C_LZ_BINARY));
+        image.set_frame(1);
+        assert_eq!(render(&image), BINARY_GOLDEN[4].3);
+
+        assert_eq!(render(&PawImage::new(Some(&PNG2C_SINGLE_TILE))), BINARY);
+
+        let mut truncated = PNG2C_LZ_ALPHA.to_vec();
+        truncated.pop();
+        assert_eq!(PawImageHeader::parse(&truncated), Err(PawImageError::FrameTruncated));
+    }
+
+    // header fields as written, followed by `rest`
+    fn header(width: u16, height: u16, encoding: u16, tile_count: u16, rest: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [width, height, encoding, tile_count] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(rest);
+        return data;
+    }
+
+    #[test]
+    fn header_errors() {
+        let mut short_header = header(8, 1, 0, 1, &[]);
+        short_header.pop();
+        let durations = FRAME_DURATIONS_FLAG;
+
+        // 8x1 binary bitmap, one byte of pixels per frame
+        let cases = [
+            ("short header", short_header, PawImageError::Truncated),
+            ("short offset table", header(8, 1, 0, 2, &[0, 0]), PawImageError::Truncated),
+            ("short durations", header(8, 1, durations, 1, &[100]), PawImageError::Truncated),
+            ("encoding", header(8, 1, 6, 1, &[0xFF]), PawImageError::BadEncoding),
+            ("flagged encoding", header(8, 1, 6 | durations, 1, &[100, 0, 0xFF]), PawImageError::BadEncoding),
+            ("no width", header(0, 1, 0, 1, &[0xFF]), PawImageError::BadSize),
+            ("no height", header(8, 0, 0, 1, &[0xFF]), PawImageError::BadSize),
+            ("too wide", header(256, 1, 0, 1, &[0xFF; 32]), PawImageError::BadSize),
+            ("no tiles", header(8, 1, 0, 0, &[0xFF]), PawImageError::BadTileCount),
+            ("too many tiles", header(8, 1, 0, 256, &[0xFF; 768]), PawImageError::BadTileCount),
+            // second frame starts past the end of the file
+            ("frame offset", header(8, 1, 0, 2, &[0, 0, 2, 0, 0xFF, 0xFF]), PawImageError::BadFrameOffset),
+        ];
+
+        let valid = header(8, 1, 0, 2, &[0, 0, 1, 0, 0xFF, 0xFF]);
+        assert_eq!(PawImageHeader::parse(&valid).map(|h| h.tile_count), Ok(2));
+        for (name, data, error) in cases {
+            assert_eq!(PawImageHeader::parse(&data), Err(error), "{}", name);
+        }
+    }
+
+    #[test]
+    fn clip_after_flip() {
+        let clip = Rectangle::new(Point::new(2, 1), Size::new(5, 2));
+        let binary = ["          ", "  ..##.   ", "  .....   "];
+        let alpha = ["          ", "   ## .   ", "  .  ..   "];
+
+        for (data, expected) in [
+            (encode(&BINARY, 0), &binary),
+            (encode(&ALPHA, 1), &alpha),
+            (encode(&BINARY, 2), &binary),
+            (encode(&ALPHA, 3), &alpha),
+            (&PNG2C_LZ_BINARY[..], &binary),
+            (&PNG2C_LZ_ALPHA[..], &alpha),
+        ] {
+            let mut image = PawImage::new(Some(data));
+            image.set_flip(true, false);
+            image.set_clip(Some(clip));
+            assert_eq!(render(&image), *expected, "encoding {}", data[4]);
+        }
+    }
+
+    #[test]
+    fn clip_outside_draws_nothing() {
+        let mut image = PawImage::new(Some(encode(&BINARY, 0)));
+        image.set_clip(Some(Rectangle::new(Point::new(10, 0), Size::new(4, 4))));
+        assert_eq!(render(&image), ["          "; 3]);
+    }
+
+    // 1x1 sprite map of `count` frames, optionally with frame durations
+    fn frames(count: u16, durations: Option<&[u16]>) -> &'static [u8] {
+        let encoding = if durations.is_some() { FRAME_DURATIONS_FLAG } else { 0 };
+
+        let mut data = Vec::new();
+        for value in [1, 1, encoding, count] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for frame in 0..count {
+            data.extend_from_slice(&frame.to_le_bytes());
+        }
+        for duration in durations.unwrap_or(&[]) {
+            data.extend_from_slice(&duration.to_le_bytes());
+        }
+        data.extend((0..count).map(|_| 0u8));
+        return Box::leak(data.into_boxed_slice());
+    }
+
+    // frame shown after each advance of `step_ms`
+    fn play(animation: &mut PawAnimation, steps: usize, step_ms: u32) -> Vec<u8> {
+        return (0..steps)
+            .map(|_| {
+                animation.advance(step_ms);
+                animation.frame()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn play_modes_within_bounds() {
+        let mut animation = PawAnimation::new((0, 1), 100);
+        animation.set_image(Some(frames(6, None)));
+
+        animation.play(PlayMode::Forward, (1, 4), 100);
+        assert_eq!(animation.frame(), 1);
+        assert_eq!(play(&mut animation, 4, 100), [2, 3, 1, 2]);
+
+        animation.play(PlayMode::Reverse, (1, 4), 100);
+        assert_eq!(animation.frame(), 3);
+        assert_eq!(play(&mut animation, 4, 100), [2, 1, 3, 2]);
+
+        animation.play(PlayMode::PingPong, (1, 4), 100);
+        assert_eq!(play(&mut animation, 6, 100), [2, 3, 2, 1, 2, 3]);
+
+        // end past the last tile is limited to the image
+        animation.play(PlayMode::Forward, (4, 10), 100);
+        assert_eq!(play(&mut animation, 3, 100), [5, 4, 5]);
+    }
+
+    #[test]
+    fn events() {
+        let mut animation = PawAnimation::new((0, 3), 100);
+        animation.set_image(Some(frames(3, None)));
+
+        assert_eq!(play(&mut animation, 2, 100), [1, 2]);
+        assert_eq!(animation.take_event(), None);
+        animation.advance(100);
+        assert_eq!(animation.take_event(), Some(AnimationEvent::Looped));
+        assert_eq!(animation.take_event(), None);
+
+        animation.play(PlayMode::Once, (0, 3), 100);
+        assert_eq!(play(&mut animation, 2, 100), [1, 2]);
+        assert!(!animation.is_finished());
+
+        // the last frame plays for its duration, then holds
+        animation.advance(100);
+        assert!(animation.is_finished());
+        assert_eq!(animation.take_event(), Some(AnimationEvent::Finished));
+        assert_eq!(play(&mut animation, 2, 100), [2, 2]);
+    }
+
+    #[test]
+    fn time_based_advance() {
+        let mut animation = PawAnimation::new((0, 4), 100);
+        animation.set_image(Some(frames(4, None)));
+
+        // same playback speed at 30 and 15 fps
+        assert_eq!(play(&mut animation, 6, 33)[5], 1);
+        animation.restart();
+        assert_eq!(play(&mut animation, 3, 66)[2], 1);
+
+        // long frames skip ahead
+        animation.restart();
+        animation.advance(250);
+        assert_eq!(animation.frame(), 2);
+    }
+
+    #[test]
+    fn frame_durations_from_image() {
+        let data = frames(3, Some(&[50, 200, 100]));
+        assert!(PawImageHeader::parse(data).unwrap().frame_durations);
+
+        let mut animation = PawAnimation::new((0, 3), 1000);
+        animation.set_image(Some(data));
+        assert_eq!(animation.image().frame_duration_ms(1), Some(200));
+
+        assert_eq!(play(&mut animation, 8, 50), [1, 1, 1, 1, 2, 2, 0, 1]);
+    }
+}
@@ -0,0 +1,279 @@
+/*!
+ * Full screen on-screen keyboard for short text such as a pet name. The D-pad moves over a grid
+ * of keys, P types the selected key and A goes back without keeping the text.
+ *
+ *  [label          ]
+ *  [text_          ]
+ *   A B C D E F G H I
+ *   J K L M N O P Q R
+ *   S T U V W X Y Z -
+ *   ab  [space] ← OK
+ *
+ * The layer key cycles upper case, lower case and digits/symbols. OK confirms, the text is read
+ * with text().
+ */
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use heapless::String;
+use pawdevicetraits::Buttons;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+
+use crate::dialog::draw_frame;
+use crate::dialog::DialogResult;
+use crate::font::Font;
+use crate::font::TextStyle;
+
+const COLUMNS: usize = 9;
+const CHAR_ROWS: usize = 3;
+const ROWS: usize = CHAR_ROWS + 1;
+const CELL_WIDTH: i32 = 7;
+const CELL_HEIGHT: i32 = 10;
+const GRID_Y: i32 = 23;
+const TEXT_AREA: Rectangle = Rectangle::new(Point::new(0, 9), Size::new(64, 12));
+const CURSOR_WIDTH: u8 = 4;
+
+#[rustfmt::skip]
+static LAYERS: [[&str; CHAR_ROWS]; 3] = [
+    ["ABCDEFGHI", "JKLMNOPQR", "STUVWXYZ-"],
+    ["abcdefghi", "jklmnopqr", "stuvwxyz-"],
+    ["123456789", "0.,!?'&+:", "éèàäöüñç♥"],
+];
+// label of the layer key, names the layer it switches to
+static LAYER_LABELS: [&str; 3] = ["ab", "12", "AB"];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Key {
+    Char(char),
+    Layer,
+    Space,
+    Delete,
+    Done,
+}
+
+// bottom row, keys wider than a cell repeat
+static BOTTOM_ROW: [Key; COLUMNS] = [
+    Key::Layer,
+    Key::Layer,
+    Key::Space,
+    Key::Space,
+    Key::Space,
+    Key::Space,
+    Key::Delete,
+    Key::Done,
+    Key::Done,
+];
+
+pub struct Keyboard<const N: usize> {
+    text: String<N>,
+    label: &'static str,
+    layer: usize,
+    row: usize,
+    column: usize,
+    font: Font,
+}
+
+impl<const N: usize> Keyboard<N> {
+    pub fn new(label: &'static str, text: &str) -> Self {
+        let mut keyboard = Self {
+            text: String::new(),
+            label,
+            layer: 0,
+            row: 0,
+            column: 0,
+            font: Font::default_font(),
+        };
+        for c in text.chars() {
+            if keyboard.text.push(c).is_err() {
+                break;
+            }
+        }
+        return keyboard;
+    }
+
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+
+    pub fn text(&self) -> &String<N> {
+        return &self.text;
+    }
+
+    fn key(&self, row: usize, column: usize) -> Key {
+        if row < CHAR_ROWS {
+            let c = LAYERS[self.layer][row].chars().nth(column).unwrap_or(' ');
+            return Key::Char(c);
+        }
+        return BOTTOM_ROW[column];
+    }
+
+    fn selected(&self) -> Key {
+        return self.key(self.row, self.column);
+    }
+
+    // wide keys are skipped over in one press
+    fn move_column(&mut self, right: bool) {
+        let start = self.selected();
+        loop {
+            self.column = if right {
+                (self.column + 1) % COLUMNS
+            } else {
+                (self.column + COLUMNS - 1) % COLUMNS
+            };
+            if self.row < CHAR_ROWS || self.selected() != start {
+                break;
+            }
+        }
+    }
+
+    // None while typing
+    pub fn tick(&mut self, buttons: &mut impl Input) -> Option<DialogResult> {
+        if buttons.is_pressed(Buttons::A) {
+            return Some(DialogResult::Cancel);
+        } else if buttons.is_pressed(Buttons::Up) {
+            self.row = (self.row + ROWS - 1) % ROWS;
+        } else if buttons.is_pressed(Buttons::Down) {
+            self.row = (self.row + 1) % ROWS;
+        } else if buttons.is_pressed(Buttons::Left) {
+            self.move_column(false);
+        } else if buttons.is_pressed(Buttons::Right) {
+            self.move_column(true);
+        } else if buttons.is_pressed(Buttons::P) {
+            match self.selected() {
+                Key::Char(c) => {
+                    self.text.push(c).ok();
+                }
+                Key::Space => {
+                    self.text.push(' ').ok();
+                }
+                Key::Delete => {
+                    self.text.pop();
+                }
+                Key::Layer => self.layer = (self.layer + 1) % LAYERS.len(),
+                Key::Done => return Some(DialogResult::Ok),
+            }
+        }
+        return None;
+    }
+
+    pub fn draw(&self, disp: &mut impl Display) {
+        disp.fill_rect(0, 0, 64, 64, false);
+        self.font.draw(disp, self.label, 1, 0, TextStyle::NORMAL);
+
+        // the end of the text stays visible when it is wider than the box
+        draw_frame(disp, TEXT_AREA);
+        let inside = Rectangle::new(TEXT_AREA.top_left + Point::new(2, 2), TEXT_AREA.size - Size::new(4, 4));
+        let width = self.font.text_width(&self.text) as i32;
+        let room = inside.size.width as i32 - CURSOR_WIDTH as i32;
+        let x = inside.top_left.x + (room - width).min(0);
+        let end = self
+            .font
+            .draw_clipped(disp, &self.text, x, inside.top_left.y, TextStyle::NORMAL, inside);
+        let cursor_y = inside.top_left.y + self.font.height() as i32 - 1;
+        disp.draw_span(end as u8, cursor_y as u8, CURSOR_WIDTH, true);
+
+        for row in 0..ROWS {
+            let mut column = 0;
+            while column < COLUMNS {
+                // wide keys span the cells with the same key
+                let key = self.key(row, column);
+                let mut span = 1;
+                while row >= CHAR_ROWS && column + span < COLUMNS && self.key(row, column + span) == key {
+                    span += 1;
+                }
+
+                let mut buffer = [0u8; 4];
+                let label: &str = match key {
+                    Key::Char(c) => c.encode_utf8(&mut buffer),
+                    Key::Layer => LAYER_LABELS[self.layer],
+                    Key::Space => "___",
+                    Key::Delete => "←",
+                    Key::Done => "OK",
+                };
+
+                let cell_x = column as i32 * CELL_WIDTH;
+                let cell_y = GRID_Y + row as i32 * CELL_HEIGHT;
+                let cell_width = span as i32 * CELL_WIDTH;
+                let x = cell_x + (cell_width - self.font.text_width(label) as i32 + 1) / 2;
+
+                if row == self.row && column <= self.column && self.column < column + span {
+                    disp.fill_rect(cell_x as u8, cell_y as u8, cell_width as u8, CELL_HEIGHT as u8, true);
+                    self.font.draw(disp, label, x, cell_y + 1, TextStyle::HIGHLIGHT);
+                } else {
+                    self.font.draw(disp, label, x, cell_y + 1, TextStyle::NORMAL);
+                }
+
+                column += span;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Grid;
+    use crate::test_util::Keys;
+
+    fn type_keys<const N: usize>(
+        keyboard: &mut Keyboard<N>,
+        keys: impl IntoIterator<Item = Buttons>,
+    ) -> Option<DialogResult> {
+        let mut result = None;
+        for key in keys {
+            result = keyboard.tick(&mut Keys::press(key));
+        }
+        return result;
+    }
+
+    #[test]
+    fn types_across_layers() {
+        use Buttons::*;
+        let mut keyboard: Keyboard<8> = Keyboard::new("Name", "");
+
+        // B, lower case layer, a
+        assert_eq!(type_keys(&mut keyboard, [Right, P, Up, P, Down, Left, P]), None);
+        assert_eq!(keyboard.text().as_str(), "Ba");
+
+        // space is 4 cells wide, one press moves over it
+        type_keys(&mut keyboard, [Up, Right, P, Right, P]);
+        assert_eq!(keyboard.text().as_str(), "Ba");
+        type_keys(&mut keyboard, [Right, Right]);
+        assert_eq!(keyboard.selected(), Key::Layer);
+
+        // symbols layer
+        type_keys(&mut keyboard, [P, Up, P]);
+        assert_eq!(keyboard.text().as_str(), "Baé");
+
+        assert_eq!(type_keys(&mut keyboard, [Down, Left, P]), Some(DialogResult::Ok));
+        assert_eq!(type_keys(&mut keyboard, [A]), Some(DialogResult::Cancel));
+    }
+
+    #[test]
+    fn text_is_limited_to_the_capacity() {
+        let mut keyboard: Keyboard<3> = Keyboard::new("", "abcdef");
+        assert_eq!(keyboard.text().as_str(), "abc");
+
+        type_keys(&mut keyboard, [Buttons::P]);
+        assert_eq!(keyboard.text().as_str(), "abc");
+    }
+
+    #[test]
+    fn long_text_draws_inside_the_screen() {
+        let mut keyboard: Keyboard<32> = Keyboard::new("Name", "ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        let mut grid = Grid::new();
+        keyboard.draw(&mut grid);
+
+        // selected key is filled, the others are not
+        assert!(grid.is_on(0, GRID_Y as usize));
+        assert!(!grid.is_on(CELL_WIDTH as usize, GRID_Y as usize));
+
+        // moved onto the wide space key
+        type_keys(&mut keyboard, [Buttons::Up, Buttons::Right]);
+        keyboard.draw(&mut grid);
+        let bottom = (GRID_Y + 3 * CELL_HEIGHT) as usize;
+        assert!((2 * CELL_WIDTH..6 * CELL_WIDTH).all(|x| grid.is_on(x as usize, bottom)));
+        assert!(!grid.is_on(0, bottom));
+    }
+}
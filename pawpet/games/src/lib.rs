@@ -1,17 +1,62 @@
 #![no_std]
 #![allow(dead_code)]
 
+pub mod image;
 pub mod menustate;
+pub mod resets;
 
+mod alert;
+mod battery;
+mod dialog;
+mod font;
 mod gamestate;
-mod image;
+mod keyboard;
+mod lz;
+mod menu;
+mod music;
+mod pet;
+mod save;
+mod scene;
+mod settings;
+mod sound;
+mod spinner;
+mod species;
+mod tilemap;
+mod transition;
+
+#[cfg(test)]
+mod test_util;
+
+use crate::alert::AlertState;
+use crate::alert::NEED_ALERT_THRESHOLD;
+use crate::battery::BatteryLevel;
+use crate::battery::FuelGauge;
+use crate::battery::EMPTY_FRAME;
 use crate::image::PawImage;
+use crate::music::Song;
+use crate::music::STARTUP_SONG_FILE;
+use crate::pet::Pet;
+use crate::pet::PetEvent;
+use crate::resets::ResetStats;
+use crate::save::SaveReader;
+use crate::save::SaveWriter;
+use crate::save::SAVE_BUFFER_SIZE;
+use crate::save::SAVE_VERSION;
+use crate::settings::BatteryChemistry;
+use crate::settings::Settings;
+use crate::sound::Sfx;
+use crate::sound::Sound;
+use crate::species::Species;
+use crate::species::SPECIES_FILE;
+use crate::transition::Transition;
 use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
+use pawdevicetraits::RtcDevice as Rtc;
 use pawdevicetraits::StorageDevice as Storage;
 use pawdevicetraits::SysTimerDevice as SysTimer;
 use pawdevicetraits::ToneDevice as Tone;
+use pawdevicetraits::WakeSchedulerDevice as WakeScheduler;
 use pawdevicetraits::WatchdogDevice as Watchdog;
 
 use embedded_graphics::{pixelcolor::BinaryColor, prelude::DrawTarget};
@@ -20,7 +65,9 @@ use core::fmt::Write;
 use core::mem::ManuallyDrop;
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::mono_font::MonoTextStyleBuilder;
+use embedded_graphics::primitives::Rectangle;
 use heapless::String;
+use rtt_target::debug_rprintln;
 
 use pawdevicetraits::*;
 
@@ -28,7 +75,7 @@ use gamestate::*;
 
 use embedded_graphics::{
     mono_font::ascii::FONT_5X8,
-    // mono_font::ascii::FONT_6X10,
+    mono_font::ascii::FONT_6X10,
     prelude::*,
     // primitives::{Circle, PrimitiveStyleBuilder, Rectangle, Triangle},
     text::Text,
@@ -45,23 +92,139 @@ pub enum FramerateMs {
     Fps5 = 200,
 }
 
+// drawn over the state every frame, the status bar holds the clock, battery and alert icons
+const STATUS_BAR: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(64, 8));
+const FULL_SCREEN: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(64, 64));
+
 // ticks per update, arbitrary tick rate that should scale across fps
 // leave overhead for 60 fps mode if possible
 
 mod eggstate;
 mod emptystate;
 mod game1;
+mod homestate;
+mod memorialstate;
+mod settingsstate;
 
 use eggstate::EggState;
 use emptystate::EmptyState;
 use game1::PawGame1;
+use homestate::HomeState;
+use memorialstate::MemorialState;
 use menustate::MenuState;
+use settingsstate::SettingsState;
 
 pub union StateUnion {
     menu: ManuallyDrop<MenuState>,
     game1: ManuallyDrop<PawGame1>,
     egg: ManuallyDrop<EggState>,
     empty: ManuallyDrop<EmptyState>,
+    memorial: ManuallyDrop<MemorialState>,
+    home: ManuallyDrop<HomeState>,
+    settings: ManuallyDrop<SettingsState>,
+}
+
+// the active object is picked by the runner's StateKind, Main has none
+impl StateUnion {
+    unsafe fn draw(&mut self, kind: StateKind, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        match kind {
+            StateKind::Main => {}
+            StateKind::Menu => (*self.menu).draw(display),
+            StateKind::Game1 => (*self.game1).draw(display),
+            StateKind::Egg => (*self.egg).draw(display),
+            StateKind::Empty => (*self.empty).draw(display),
+            StateKind::Memorial => (*self.memorial).draw(display),
+            StateKind::Home => (*self.home).draw(display),
+            StateKind::Settings => (*self.settings).draw(display),
+        }
+    }
+
+    unsafe fn invalidate(&mut self, kind: StateKind, area: Rectangle) {
+        match kind {
+            StateKind::Main => {}
+            StateKind::Menu => (*self.menu).invalidate(area),
+            StateKind::Game1 => (*self.game1).invalidate(area),
+            StateKind::Egg => (*self.egg).invalidate(area),
+            StateKind::Empty => (*self.empty).invalidate(area),
+            StateKind::Memorial => (*self.memorial).invalidate(area),
+            StateKind::Home => (*self.home).invalidate(area),
+            StateKind::Settings => (*self.settings).invalidate(area),
+        }
+    }
+
+    unsafe fn entered(&mut self, kind: StateKind) {
+        match kind {
+            StateKind::Main => {}
+            StateKind::Menu => (*self.menu).entered(),
+            StateKind::Game1 => (*self.game1).entered(),
+            StateKind::Egg => (*self.egg).entered(),
+            StateKind::Empty => (*self.empty).entered(),
+            StateKind::Memorial => (*self.memorial).entered(),
+            StateKind::Home => (*self.home).entered(),
+            StateKind::Settings => (*self.settings).entered(),
+        }
+    }
+
+    unsafe fn drop_state(&mut self, kind: StateKind) {
+        match kind {
+            StateKind::Main => {}
+            StateKind::Menu => ManuallyDrop::drop(&mut self.menu),
+            StateKind::Game1 => ManuallyDrop::drop(&mut self.game1),
+            StateKind::Egg => ManuallyDrop::drop(&mut self.egg),
+            StateKind::Empty => ManuallyDrop::drop(&mut self.empty),
+            StateKind::Memorial => ManuallyDrop::drop(&mut self.memorial),
+            StateKind::Home => ManuallyDrop::drop(&mut self.home),
+            StateKind::Settings => ManuallyDrop::drop(&mut self.settings),
+        }
+    }
+
+    // constructs and loads the state, returns its frame rate
+    unsafe fn load(
+        &mut self,
+        kind: StateKind,
+        storage: &mut impl Storage,
+        pet: &Pet,
+        species: &Species,
+    ) -> FramerateMs {
+        match kind {
+            StateKind::Main => return FramerateMs::Fps15,
+            StateKind::Menu => {
+                *self.menu = MenuState::new();
+                (*self.menu).load(storage, pet, species);
+                return MenuState::get_fps();
+            }
+            StateKind::Game1 => {
+                *self.game1 = PawGame1::new();
+                (*self.game1).load(storage, pet, species);
+                return PawGame1::get_fps();
+            }
+            StateKind::Egg => {
+                *self.egg = EggState::new();
+                (*self.egg).load(storage, pet, species);
+                return EggState::get_fps();
+            }
+            StateKind::Empty => {
+                *self.empty = EmptyState::new();
+                (*self.empty).load(storage, pet, species);
+                return EmptyState::get_fps();
+            }
+            StateKind::Memorial => {
+                *self.memorial = MemorialState::new();
+                (*self.memorial).load(storage, pet, species);
+                return MemorialState::get_fps();
+            }
+            StateKind::Home => {
+                *self.home = HomeState::new();
+                (*self.home).load(storage, pet, species);
+                return HomeState::get_fps();
+            }
+            StateKind::Settings => {
+                *self.settings = SettingsState::new();
+                (*self.settings).load(storage, pet, species);
+                return SettingsState::get_fps();
+            }
+        }
+    }
 }
 
 pub struct PawRunner {
@@ -73,20 +236,47 @@ pub struct PawRunner {
     info_text: MonoTextStyle<'static, BinaryColor>,
     state_obj: StateUnion,
     state: StateKind,
+    next_state: Option<StateKind>, // loaded once the transition needs its frame
+    state_loaded: bool,            // boot state is constructed but not loaded before the first tick
+    transition: Transition,
 
-    debug: bool,
     total_frametime_ms: u32,
     dropped_frame_count: u32,
     blocked_update: u32,
+    overlay_area: Rectangle, // drawn over the state by the runner last frame
 
     frametime_ms: [u32; 8],
     frametime_index: usize,
     battery: PawImage,
+    fuel_gauge: FuelGauge,
+    battery_lockout: bool, // critical battery, game loop does not run
+    battery_warned: bool,
+    started: bool, // startup song started, game loop has run
+    icons: PawImage,
+    sound: Sound,
+
+    pet: Pet,
+    species: Species,
+    storage_loaded: bool, // species and save file read from flash
+    reload_state: bool, // active state needs to reload assets (pet evolved or died)
+    alerts: AlertState,
+    settings: Settings,
+    resets: ResetStats,
 }
 
-const INACTIVITY_SLEEP_SEC: u32 = 60;
+pub(crate) const SECONDS_PER_HOUR: u32 = 60 * 60;
+pub(crate) const SECONDS_PER_DAY: u32 = 24 * SECONDS_PER_HOUR;
 static BATTERY_SPRITES: &'static [u8] =
     include_bytes_align_as!(u32, "../../../sprites/battery.paw");
+pub(crate) static ICON_SPRITES: &'static [u8] =
+    include_bytes_align_as!(u32, "../../../sprites/icons.paw");
+// icons.paw frames
+pub(crate) const ICON_ATTENTION: u8 = 0;
+pub(crate) const ICON_SICK: u8 = 1;
+pub(crate) const ICON_FEED: u8 = 2;
+pub(crate) const ICON_PLAY: u8 = 3;
+pub(crate) const ICON_CLEAN: u8 = 4;
+pub(crate) const ICON_SUBMENU: u8 = 5;
 
 impl PawRunner {
     pub fn new() -> Self {
@@ -98,25 +288,58 @@ impl PawRunner {
 
         Self {
             tick: 0,
-            ticks_to_sleep: FramerateMs::Fps30 as u32 * INACTIVITY_SLEEP_SEC, // ~1 minute of inactivity
+            ticks_to_sleep: 0, // set once settings are loaded
             total_frametime_ms: 0,
             dropped_frame_count: 0,
             sleep_mode: None,
             info_text,
             state_obj: StateUnion {
-                menu: ManuallyDrop::new(MenuState::new()),
+                home: ManuallyDrop::new(HomeState::new()),
             },
-            framerate: FramerateMs::Fps30,
-            state: StateKind::Menu,
-            debug: false,
+            framerate: FramerateMs::Fps15,
+            state: StateKind::Home,
+            next_state: None,
+            state_loaded: false,
+            transition: Transition::new(),
             blocked_update: 0,
+            overlay_area: FULL_SCREEN,
             frametime_ms: [0; 8],
             frametime_index: 0,
             battery: PawImage::new(Some(BATTERY_SPRITES)),
+            fuel_gauge: FuelGauge::new(BatteryChemistry::Alkaline),
+            battery_lockout: false,
+            battery_warned: false,
+            started: false,
+            icons: PawImage::new(Some(ICON_SPRITES)),
+            sound: Sound::new(),
+            pet: Pet::new(),
+            species: Species::default_species(),
+            storage_loaded: false,
+            reload_state: true, // boot state is loaded on the first tick
+            alerts: AlertState::new(),
+            settings: Settings::new(),
+            resets: ResetStats::new(),
         }
     }
 
+    // reset counts kept by the device, shown in the debug overlay
+    pub fn set_reset_stats(&mut self, resets: ResetStats) {
+        self.resets = resets;
+    }
+
+    pub fn reset_stats(&self) -> &ResetStats {
+        return &self.resets;
+    }
+
     pub fn get_framerate_ms(&self) -> u32 {
+        return self.frame_ms();
+    }
+
+    // transitions run at 30 fps whatever the states run at
+    fn frame_ms(&self) -> u32 {
+        if self.transition.is_active() {
+            return FramerateMs::Fps30 as u32;
+        }
         return self.framerate as u32;
     }
 
@@ -124,43 +347,284 @@ impl PawRunner {
         return self.sleep_mode;
     }
 
+    // critical battery, nothing is scheduled and only a button press should wake
+    pub fn deep_sleep_request(&self) -> bool {
+        return self.sleep_mode.is_some() && self.battery_lockout;
+    }
+
+    // battery icon from the filtered fuel gauge estimate, see battery.rs for the curves
     pub fn update_battery_frame(&mut self, mon: &mut impl BatteryMonitor) {
-        // 1.5*2 alk, 0.8v cutoff
-        // 1.4*2 nimh? 1.0v cutoff
-        //
-        // discharge curves are non-linear, needs tunning
-        // 2.6v >- full
-        // 2.4v >- 3/4  - nimh spends most time here
-        // 2.2v >- 2/4
-        // 2.1v >- 1/4
-        // 2.01v >- empty
-        // 2.0v < shutdown, refuse to boot, below cutoff voltage
-        // 1.9v - display will not turn on at this voltage
-        // below 2v, display battery replace logo
-        //
-        // 2.7v - full
-        // 2.6v - 3/4
-        // 2.4v - 2/4SWS
-        // 2.2v - 1/4
-        // 2.0v - empty
-        // 1.9v - display will not turn on
-        // 1.6v cutoff, display will have already been inoperable
-        let bat = mon.read();
-        let frame;
-
-        if bat > 260 {
-            frame = 0;
-        } else if bat > 240 {
-            frame = 1;
-        } else if bat > 230 {
-            frame = 2;
-        } else if bat > 220 {
-            frame = 3;
-        } else {
-            frame = 4;
+        self.fuel_gauge.update(mon.read());
+        self.battery.set_frame(self.fuel_gauge.frame())
+    }
+
+    // low battery policy, returns true while the game loop is locked out by a critical battery
+    //
+    // critical: save, leave a replace battery screen on the memory lcd (holds the image without
+    // updates) and deep sleep with the watchdog off, re-checked on every button wake
+    fn check_battery(
+        &mut self,
+        display: &mut (impl Display + DrawTarget<Color = BinaryColor>),
+        tone: &impl Tone,
+        storage: &mut impl Storage,
+        scheduler: &mut impl WakeScheduler,
+    ) -> bool {
+        match self.fuel_gauge.level(self.battery_lockout) {
+            BatteryLevel::Critical => {
+                if !self.battery_lockout {
+                    self.battery_lockout = true;
+                    debug_rprintln!("battery critical {}", self.fuel_gauge.voltage());
+
+                    if self.started {
+                        self.write_save(storage);
+                    }
+
+                    scheduler.cancel(WakeEvent::ClockRedraw);
+                    scheduler.cancel(WakeEvent::PetNeeds);
+                    scheduler.cancel(WakeEvent::Alarm);
+
+                    self.sound.stop(tone);
+                    self.draw_replace_battery(display);
+                }
+
+                self.sleep_mode = Some(WatchdogTimeouts::Seconds64);
+                return true;
+            }
+            BatteryLevel::Low => {
+                if !self.battery_warned {
+                    self.battery_warned = true;
+                    self.sound.play(Sfx::Alert);
+                }
+            }
+            BatteryLevel::Ok => {
+                if self.fuel_gauge.warning_cleared() {
+                    self.battery_warned = false;
+                }
+            }
+        }
+
+        if self.battery_lockout {
+            // recovered, redraw the state over the replace battery screen
+            self.battery_lockout = false;
+            self.reload_state = true;
+            self.tick = 0;
+            self.sleep_mode = None;
+        }
+        return false;
+    }
+
+    fn draw_replace_battery(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        display.clear(BinaryColor::Off).ok();
+
+        let text = MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(BinaryColor::On)
+            .background_color(BinaryColor::Off)
+            .build();
+
+        self.battery.set_frame(EMPTY_FRAME);
+        self.battery.draw(display, 24, 16);
+
+        Text::new("Replace\nbattery", Point::new(11, 38), text)
+            .draw(display)
+            .ok();
+
+        // has to reach the panel, nothing redraws it until the battery recovers
+        while !display.update() {}
+    }
+
+    // register background wake events, called once when entering sleep
+    fn schedule_wake_events(&self, rtc: &impl Rtc, scheduler: &mut impl WakeScheduler) {
+        let now = rtc.get_time();
+
+        // redraw the clock on the hour
+        let next_hour = now - (now % SECONDS_PER_HOUR) + SECONDS_PER_HOUR;
+        scheduler.schedule(WakeEvent::ClockRedraw, next_hour, SECONDS_PER_HOUR);
+
+        // daily alarm from the settings, it may have been turned off since the last sleep
+        match self.settings.alarm() {
+            Some(alarm) => {
+                let mut at = now - (now % SECONDS_PER_DAY) + alarm;
+                if at <= now {
+                    at += SECONDS_PER_DAY;
+                }
+                scheduler.schedule(WakeEvent::Alarm, at, SECONDS_PER_DAY);
+            }
+            None => scheduler.cancel(WakeEvent::Alarm),
         }
 
-        self.battery.set_frame(frame)
+        scheduler.schedule(WakeEvent::PetNeeds, self.alerts.next_check(now), 0);
+    }
+
+    // handle a scheduled wake event, does the minimal amount of work and does not run the update loop
+    // the caller is expected to go back to sleep if sleep_request() is still set
+    pub fn wake(
+        &mut self,
+        event: WakeEvent,
+        display: &mut (impl Display + DrawTarget<Color = BinaryColor>),
+        tone: &impl Tone,
+        timer: &mut impl SysTimer,
+        storage: &mut impl Storage,
+        rtc: &impl Rtc,
+        scheduler: &mut impl WakeScheduler,
+    ) {
+        match event {
+            WakeEvent::ClockRedraw => {
+                self.draw_sleep_frame(display, rtc);
+            }
+            WakeEvent::PetNeeds => {
+                let now = rtc.get_time();
+
+                if self.update_pet(now) == Some(PetEvent::Died) {
+                    // full wake to show the memorial
+                    scheduler.cancel(WakeEvent::ClockRedraw);
+                    self.tick = 0;
+                    self.sleep_mode = None;
+                }
+                if self.pet.take_dirty() {
+                    self.write_save(storage);
+                }
+
+                // held during quiet hours
+                if self.alerts.update(&self.pet.needs, now).is_some() {
+                    self.draw_sleep_frame(display, rtc);
+                    self.alerts.play(tone, timer);
+                }
+
+                scheduler.schedule(WakeEvent::PetNeeds, self.alerts.next_check(now), 0);
+            }
+            WakeEvent::Alarm => {
+                for _ in 0..3 {
+                    tone.tone(TONE_NOTES[51]);
+                    timer.delay_ms(150);
+                    tone.no_tone();
+                    timer.delay_ms(100);
+                }
+
+                // full wake, clock redraws are not needed while the update loop runs
+                scheduler.cancel(WakeEvent::ClockRedraw);
+                self.tick = 0;
+                self.sleep_mode = None;
+            }
+        }
+    }
+
+    // display is double buffered, redraw the whole frame instead of patching in changes
+    fn draw_sleep_frame(
+        &mut self,
+        display: &mut (impl Display + DrawTarget<Color = BinaryColor>),
+        rtc: &impl Rtc,
+    ) {
+        self.draw_state(display);
+        self.draw_clock(display, rtc);
+        self.battery.draw(display, 48, 0);
+        self.draw_alert_icon(display);
+        display.update();
+    }
+
+    fn draw_alert_icon(&mut self, display: &mut impl Display) {
+        if self.pet.needs.most_urgent(NEED_ALERT_THRESHOLD).is_some() {
+            self.icons.set_frame(ICON_ATTENTION);
+            self.icons.draw(display, 38, 0);
+        }
+        if self.pet.is_sick() {
+            self.icons.set_frame(ICON_SICK);
+            self.icons.draw(display, 28, 0);
+        }
+    }
+
+    // state is reloaded on evolution and death, death switches to the memorial once reloaded
+    fn update_pet(&mut self, now: u32) -> Option<PetEvent> {
+        let event = self.pet.update(now, &self.species);
+        match event {
+            Some(PetEvent::Evolved) | Some(PetEvent::Died) => self.reload_state = true,
+            Some(PetEvent::Sick) | None => {}
+        }
+        return event;
+    }
+
+    fn draw_clock(&self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>), rtc: &impl Rtc) {
+        let seconds_of_day = rtc.get_time() % SECONDS_PER_DAY;
+
+        let mut s: String<8> = String::new();
+        write!(
+            s,
+            "{:02}:{:02}",
+            seconds_of_day / SECONDS_PER_HOUR,
+            (seconds_of_day % SECONDS_PER_HOUR) / 60
+        )
+        .ok();
+
+        Text::new(&s, Point::new(0, 6), self.info_text)
+            .draw(display)
+            .ok();
+    }
+
+    // species definition from flash, built-in definition is kept if missing or invalid
+    fn load_species(&mut self, storage: &mut impl Storage) {
+        if let Some(data) = storage.load_image(SPECIES_FILE) {
+            match Species::parse(data) {
+                Ok(species) => self.species = species,
+                Err(e) => debug_rprintln!("invalid species file {:?}", e),
+            }
+        }
+    }
+
+    // settings and pet from the newest save file, a missing or unreadable save starts a new pet
+    fn load_save(&mut self, storage: &mut impl Storage) {
+        let mut data: [u8; SAVE_BUFFER_SIZE] = [0; SAVE_BUFFER_SIZE];
+        let length = storage.load_save(&mut data);
+        if length == 0 {
+            return;
+        }
+
+        let mut reader = SaveReader::new(&data[0..length]);
+        let loaded = match reader.get_u8() {
+            Some(SAVE_VERSION) => Settings::restore(&mut reader)
+                .and_then(|settings| Pet::restore(&mut reader).map(|pet| (settings, pet))),
+            _ => None,
+        };
+
+        match loaded {
+            Some((settings, pet)) => {
+                self.settings = settings;
+                self.pet = pet;
+            }
+            None => debug_rprintln!("invalid save file"),
+        }
+    }
+
+    fn write_save(&mut self, storage: &mut impl Storage) {
+        let mut data: [u8; SAVE_BUFFER_SIZE] = [0; SAVE_BUFFER_SIZE];
+        let mut writer = SaveWriter::new(&mut data);
+
+        writer.put_u8(SAVE_VERSION);
+        self.settings.save(&mut writer);
+        self.pet.save(&mut writer);
+        let length = writer.len();
+
+        if let Err(e) = storage.write_save(&data[0..length]) {
+            debug_rprintln!("save failed {:?}", e);
+        }
+    }
+
+    // push settings out to the devices, called at boot and after a setting changes
+    fn apply_settings(&mut self, display: &mut impl Display, tone: &impl Tone) {
+        self.sound.set_muted(tone, !self.settings.sound);
+        self.sound.set_volume(tone, self.settings.volume);
+        display.set_rotation(self.settings.rotation);
+        self.fuel_gauge.set_chemistry(self.settings.battery);
+        self.alerts.set_quiet_hours(self.settings.quiet_hours());
+        self.ticks_to_sleep = self.sleep_ticks();
+    }
+
+    fn sleep_ticks(&self) -> u32 {
+        return self.settings.sleep_timeout_sec as u32 * 1000 / self.framerate as u32;
+    }
+
+    // draw current state without ticking it
+    fn draw_state(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        unsafe { self.state_obj.draw(self.state, display) };
     }
 
     pub fn tick(
@@ -172,6 +636,8 @@ impl PawRunner {
         timer: &mut impl SysTimer,
         battery: &mut impl BatteryMonitor,
         storage: &mut impl Storage,
+        rtc: &mut impl Rtc,
+        scheduler: &mut impl WakeScheduler,
         // sleep device? sleep function pointer?
         // filesystem device
         // functor to ui drawing object? ui drawing object to pass down?
@@ -187,7 +653,40 @@ impl PawRunner {
         buttons.update_buttons();
 
         // reset tick count if buttons are pressed
+        if !self.storage_loaded {
+            self.storage_loaded = true;
+            self.load_species(storage);
+            self.load_save(storage);
+            self.apply_settings(display, tone);
+        }
+
+        self.update_battery_frame(battery);
+        if self.check_battery(display, tone, storage, scheduler) {
+            return;
+        }
+
+        if !self.started {
+            self.started = true;
+            // already muted when sound is off
+            let song = Song::load(storage, STARTUP_SONG_FILE).unwrap_or(Song::startup());
+            self.sound.play_music(song);
+        }
+
+        let now = rtc.get_time();
+        self.settings.clock = now;
+        self.update_pet(now);
+        // a reload during a transition waits for it to finish
+        let reload = self.reload_state && !self.transition.is_active();
+        if reload {
+            self.reload_state = false;
+        }
+
         if buttons.get_state() > 0 {
+            if self.sleep_mode.is_some() {
+                scheduler.cancel(WakeEvent::ClockRedraw);
+                scheduler.cancel(WakeEvent::PetNeeds);
+            }
+            self.alerts.acknowledge(now);
             self.tick = 0;
             self.sleep_mode = None;
         }
@@ -202,99 +701,159 @@ impl PawRunner {
 
         let mut new_state: StateKind = StateKind::Main;
 
-        // TOOD make this a proc gen macro
-        match self.state {
-            StateKind::Main => {}
-            StateKind::Menu => unsafe {
-                let state = &mut (*self.state_obj.menu);
-                new_state = state.tick(buttons, tone, battery);
-
-                state.draw(display);
-
-                if new_state != self.state {
-                    ManuallyDrop::drop(&mut self.state_obj.menu);
-                }
-            },
-            StateKind::Game1 => unsafe {
-                let state = &mut (*self.state_obj.game1);
-                new_state = state.tick(buttons, tone, battery);
-
-                state.draw(display);
-
-                if new_state != self.state {
-                    ManuallyDrop::drop(&mut self.state_obj.game1);
-                }
-            },
-            StateKind::Egg => unsafe {
-                let state = &mut (*self.state_obj.egg);
-                new_state = state.tick(buttons, tone, battery);
-
-                state.draw(display);
-
-                if new_state != self.state {
-                    ManuallyDrop::drop(&mut self.state_obj.egg);
-                }
-            },
-            StateKind::Empty => unsafe {
-                let state = &mut (*self.state_obj.empty);
-                new_state = state.tick(buttons, tone, battery);
-
-                state.draw(display);
-
-                if new_state != self.state {
-                    ManuallyDrop::drop(&mut self.state_obj.empty);
-                }
-            },
-        }
+        // states that only repaint what changed have to restore what the overlays covered
+        let overlay = self.overlay_area;
 
-        // Update union to next state
-        if new_state != self.state {
-            match new_state {
+        if self.transition.is_active() {
+            // the new state gets input once the transition is done
+            new_state = self.state;
+            self.transition.advance(self.frame_ms());
+        } else {
+            // TOOD make this a proc gen macro
+            match self.state {
                 StateKind::Main => {}
                 StateKind::Menu => unsafe {
-                    *self.state_obj.menu = MenuState::new();
-                    (*self.state_obj.menu).load(storage);
-                    self.framerate = MenuState::get_fps();
+                    let state = &mut (*self.state_obj.menu);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
                 },
                 StateKind::Game1 => unsafe {
-                    *self.state_obj.game1 = PawGame1::new();
-                    (*self.state_obj.game1).load(storage);
-                    self.framerate = PawGame1::get_fps();
+                    let state = &mut (*self.state_obj.game1);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
                 },
                 StateKind::Egg => unsafe {
-                    *self.state_obj.egg = EggState::new();
-                    (*self.state_obj.egg).load(storage);
-                    self.framerate = EggState::get_fps();
+                    let state = &mut (*self.state_obj.egg);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
                 },
                 StateKind::Empty => unsafe {
-                    *self.state_obj.empty = EmptyState::new();
-                    (*self.state_obj.empty).load(storage);
-                    self.framerate = EmptyState::get_fps();
+                    let state = &mut (*self.state_obj.empty);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
+                },
+                StateKind::Memorial => unsafe {
+                    let state = &mut (*self.state_obj.memorial);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
                 },
+                StateKind::Home => unsafe {
+                    let state = &mut (*self.state_obj.home);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
+                },
+                StateKind::Settings => unsafe {
+                    let state = &mut (*self.state_obj.settings);
+                    new_state = state.tick(buttons, &mut self.sound, battery, &mut self.pet, &mut self.settings);
+
+                    state.invalidate(overlay);
+                    state.draw(display);
+                },
+            }
+        }
+
+        if let Some(time) = self.settings.clock_request.take() {
+            let delta = time.wrapping_sub(now) as i32;
+            rtc.set_time(time);
+            self.pet.shift_time(delta);
+            self.alerts.shift_time(delta);
+        }
+
+        // dead pets only show the memorial until a new egg is started
+        if !self.pet.is_alive() && reload {
+            new_state = StateKind::Memorial;
+        }
+
+        if self.pet.take_dirty() {
+            self.write_save(storage);
+        }
+
+        // Drop the old state, the outgoing frame is kept for the transition
+        if new_state != self.state || reload {
+            let effect = transition::effect_for(self.state, new_state, reload).filter(|_| self.state_loaded);
+            if let Some(effect) = effect {
+                self.transition.start(effect, effect.duration_ms());
+                unsafe {
+                    // drawn in full, without the overlays
+                    self.state_obj.invalidate(self.state, FULL_SCREEN);
+                    self.state_obj.draw(self.state, self.transition.outgoing());
+                }
             }
 
-            self.ticks_to_sleep = self.framerate as u32 /*(FPS)*/ * INACTIVITY_SLEEP_SEC;
-            // ~1 minute of inactivity
+            unsafe { self.state_obj.drop_state(self.state) };
+            self.state = StateKind::Main;
+            self.next_state = Some(new_state);
+        }
+
+        // Update union to next state, deferred while the transition doesn't need it yet
+        if let Some(next) = self.next_state {
+            if !self.transition.is_active() || self.transition.needs_incoming() {
+                self.next_state = None;
+                self.framerate = unsafe { self.state_obj.load(next, storage, &self.pet, &self.species) };
+                self.state = next;
+                self.state_loaded = true;
+                self.ticks_to_sleep = self.sleep_ticks();
+
+                if self.transition.is_active() {
+                    unsafe { self.state_obj.draw(next, self.transition.incoming()) };
+                }
+            }
         }
 
-        self.state = new_state;
+        if self.transition.is_active() {
+            self.transition.draw(display);
+
+            if self.transition.is_done() {
+                // states repaint everything over the last transition frame
+                self.transition.stop();
+                unsafe {
+                    self.state_obj.invalidate(self.state, FULL_SCREEN);
+                    self.state_obj.entered(self.state);
+                }
+            }
+        }
 
         // let time = disable_interrupts(|_| rtc.current_time());
         // hprintln!("JDEC {:?} {}", id.device_id(), rot).ok();
 
         // global toggle debug menu
-        if buttons.is_held(Buttons::P) && buttons.is_held(Buttons::Left) {
-            self.debug = true;
+        if buttons.is_held(Buttons::P) && buttons.is_held(Buttons::Left) && !self.settings.debug_overlay {
+            self.settings.debug_overlay = true;
+            self.settings.set_changed();
         }
-        if buttons.is_held(Buttons::P) && buttons.is_held(Buttons::Right) {
-            self.debug = false;
+        if buttons.is_held(Buttons::P) && buttons.is_held(Buttons::Right) && self.settings.debug_overlay {
+            self.settings.debug_overlay = false;
+            self.settings.set_changed();
         }
 
+        if self.settings.take_changed() {
+            self.apply_settings(display, tone);
+            self.write_save(storage);
+        }
+
+        // measured time of the last frame, it only runs past the frame time when a frame is dropped
+        self.sound.update(tone, self.total_frametime_ms.max(self.frame_ms()));
+
         self.tick += 1;
 
         // enter sleep, don't exit until button press occurs (or other event)
-        if self.tick > self.ticks_to_sleep {
+        if self.tick > self.ticks_to_sleep && self.sleep_mode.is_none() {
             self.sleep_mode = Some(WatchdogTimeouts::Seconds64);
+            self.sound.stop(tone);
+            self.schedule_wake_events(rtc, scheduler);
+            self.write_save(storage);
         }
 
         // display.clear(BinaryColor::Off).ok();
@@ -325,7 +884,13 @@ impl PawRunner {
             self.frametime_index = self.frametime_index % self.frametime_ms.len();
             self.frametime_ms[self.frametime_index] = self.total_frametime_ms;
 
-            if self.debug {
+            self.overlay_area = if self.settings.debug_overlay {
+                FULL_SCREEN
+            } else {
+                STATUS_BAR
+            };
+
+            if self.settings.debug_overlay {
                 let mut s: String<256> = String::new();
                 // asm::bkpt();
 
@@ -338,7 +903,40 @@ impl PawRunner {
                     self.blocked_update,
                 )
                 .ok();
-                write!(s, "\n{}", battery.read()).ok();
+                write!(
+                    s,
+                    "\n{}.{:02}v {}%\n~{}h",
+                    self.fuel_gauge.voltage() / 100,
+                    self.fuel_gauge.voltage() % 100,
+                    self.fuel_gauge.percent(),
+                    self.fuel_gauge.runtime_hours()
+                )
+                .ok();
+
+                // lines sent by the last display update, transfer ms vs a full frame
+                let stats = display.update_stats();
+                write!(
+                    s,
+                    "\n{:3}l{:2}.{}/{}.{}",
+                    stats.lines,
+                    stats.transfer_us / 1000,
+                    stats.transfer_us % 1000 / 100,
+                    stats.full_transfer_us / 1000,
+                    stats.full_transfer_us % 1000 / 100
+                )
+                .ok();
+
+                // last reset cause, watchdog and brownout counts
+                if let Some(cause) = self.resets.last() {
+                    write!(
+                        s,
+                        "\n{} w{} b{}",
+                        cause.name(),
+                        self.resets.count(ResetCause::Watchdog),
+                        self.resets.count(ResetCause::Brownout)
+                    )
+                    .ok();
+                }
 
                 if self.sleep_mode.is_some() {
                     write!(s, "\nSLEEP").ok();
@@ -347,6 +945,9 @@ impl PawRunner {
                 Text::new(&s, Point::new(0, 6), self.info_text)
                     .draw(display)
                     .ok();
+            } else if self.sleep_mode.is_some() || self.state == StateKind::Home {
+                // status bar
+                self.draw_clock(display, rtc);
             } else {
                 let mut s: String<64> = String::new();
 
@@ -358,9 +959,12 @@ impl PawRunner {
             }
         }
 
-        self.update_battery_frame(battery);
-
-        self.battery.draw(display, 48, 0);
+        // flash the battery icon while low
+        let half_second = self.tick * self.framerate as u32 / 500;
+        if !self.battery_warned || half_second % 2 == 0 {
+            self.battery.draw(display, 48, 0);
+        }
+        self.draw_alert_icon(display);
 
         // display was busy
         let display_busy = !display.update();
@@ -370,7 +974,7 @@ impl PawRunner {
             buttons.poll_buttons();
             // TODO, future, instead sleep?
         });
-        timer.start(self.framerate as u32);
+        timer.start(self.frame_ms());
 
         // if  self.frametime_index == 0
         // {
@@ -383,7 +987,7 @@ impl PawRunner {
 
         // }
 
-        if self.total_frametime_ms > (self.framerate as u32) {
+        if self.total_frametime_ms > self.frame_ms() {
             self.dropped_frame_count += 1;
         }
 
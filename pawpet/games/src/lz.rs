@@ -0,0 +1,174 @@
+/*!
+ * Small window LZ used by the compressed .paw encodings, the stream is decoded a byte at a time
+ * into the bitmap decoders so only the 256 byte window is needed, not a frame sized buffer.
+ *
+ * tokens, written by png2c
+ *      0LLLLLLL                literal run, the next L + 1 bytes are copied as is
+ *      1LLLLLLL DDDDDDDD       copy L + 3 bytes starting D + 1 bytes back
+ *
+ * A copy can overlap the bytes it produces, a distance of 1 repeats the last byte (run length).
+ */
+
+pub const LZ_WINDOW: usize = 256;
+const MATCH_FLAG: u8 = 0x80;
+const LENGTH_MASK: u8 = 0x7F;
+const MIN_MATCH: u8 = 3;
+
+pub struct LzBytes<'a> {
+    data: &'a [u8],
+    position: usize,
+    window: [u8; LZ_WINDOW],
+    head: u8, // next write index, wraps with the window
+    literal: u8,
+    copy: u8,
+    distance: u8,
+}
+
+impl<'a> LzBytes<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Self {
+            data,
+            position: 0,
+            window: [0; LZ_WINDOW],
+            head: 0,
+            literal: 0,
+            copy: 0,
+            distance: 0,
+        };
+    }
+
+    fn next_input(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.position)?;
+        self.position += 1;
+        return Some(byte);
+    }
+
+    fn push(&mut self, byte: u8) -> u8 {
+        self.window[self.head as usize] = byte;
+        self.head = self.head.wrapping_add(1);
+        return byte;
+    }
+}
+
+impl<'a> Iterator for LzBytes<'a> {
+    type Item = u8;
+
+    // None once the input runs out
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            if self.literal > 0 {
+                self.literal -= 1;
+                let byte = self.next_input()?;
+                return Some(self.push(byte));
+            }
+
+            if self.copy > 0 {
+                self.copy -= 1;
+                let from = self.head.wrapping_sub(self.distance).wrapping_sub(1);
+                let byte = self.window[from as usize];
+                return Some(self.push(byte));
+            }
+
+            let token = self.next_input()?;
+            if token & MATCH_FLAG == 0 {
+                self.literal = (token & LENGTH_MASK) + 1;
+            } else {
+                self.copy = (token & LENGTH_MASK) + MIN_MATCH;
+                self.distance = self.next_input()?;
+            }
+        }
+    }
+}
+
+// true if the stream produces at least `length` bytes and never copies from before its start
+pub fn check(data: &[u8], length: usize) -> bool {
+    let mut position = 0;
+    let mut produced = 0;
+
+    while produced < length {
+        let token = match data.get(position) {
+            Some(token) => *token,
+            None => return false,
+        };
+
+        if token & MATCH_FLAG == 0 {
+            let count = (token & LENGTH_MASK) as usize + 1;
+            if position + 1 + count > data.len() {
+                return false;
+            }
+            position += 1 + count;
+            produced += count;
+        } else {
+            let distance = match data.get(position + 1) {
+                Some(distance) => *distance as usize + 1,
+                None => return false,
+            };
+            if distance > produced {
+                return false;
+            }
+            position += 2;
+            produced += (token & LENGTH_MASK) as usize + MIN_MATCH as usize;
+        }
+    }
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn decode(data: &[u8]) -> Vec<u8> {
+        return LzBytes::new(data).collect();
+    }
+
+    #[test]
+    fn literals_and_copies() {
+        // "abc", copy 3 from 3 back, literal "d"
+        let data = [0x02, b'a', b'b', b'c', 0x80, 0x02, 0x00, b'd'];
+        assert_eq!(decode(&data), b"abcabcd");
+        assert!(check(&data, 7));
+        assert!(!check(&data, 8));
+    }
+
+    #[test]
+    fn overlapping_copy_is_a_run() {
+        // one 0xFF then 130 more from 1 back
+        let data = [0x00, 0xFF, 0xFF, 0x00];
+        let bytes = decode(&data);
+        assert_eq!(bytes.len(), 131);
+        assert!(bytes.iter().all(|b| *b == 0xFF));
+    }
+
+    #[test]
+    fn far_copy_wraps_the_window() {
+        let mut data = Vec::new();
+        let mut expected = Vec::new();
+        for chunk in 0..3u8 {
+            data.push(0x7F);
+            data.extend((0..128).map(|i| i as u8 ^ chunk));
+            expected.extend((0..128).map(|i| i as u8 ^ chunk));
+        }
+        // 4 bytes from 256 back
+        data.extend_from_slice(&[0x81, 0xFF]);
+        expected.extend_from_slice(&expected[384 - 256..384 - 256 + 4].to_vec());
+
+        assert_eq!(decode(&data), expected);
+        assert!(check(&data, expected.len()));
+    }
+
+    #[test]
+    fn rejects_bad_streams() {
+        // copy before any output
+        assert!(!check(&[0x80, 0x00], 3));
+        // distance past what was produced
+        assert!(!check(&[0x01, 1, 2, 0x80, 0x02], 5));
+        // literal run cut short
+        assert!(!check(&[0x05, 1, 2], 6));
+        // missing distance byte
+        assert!(!check(&[0x00, 1, 0x80], 4));
+        assert!(check(&[], 0));
+    }
+}
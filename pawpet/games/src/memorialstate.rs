@@ -0,0 +1,112 @@
+use core::fmt::Write;
+use embedded_graphics::primitives::Rectangle;
+use heapless::String;
+
+use pawdevicetraits::*;
+
+use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+use pawdevicetraits::StorageDevice as Storage;
+use crate::FramerateMs;
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use crate::font::Font;
+use crate::font::TextBox;
+use crate::font::TextStyle;
+use crate::pet::DeathCause;
+use crate::pet::Memorial;
+use crate::pet::Pet;
+use crate::species::Species;
+use crate::settings::Settings;
+use crate::sound::Sound;
+use crate::GameState;
+use crate::StateKind;
+
+const STATS_AREA: Rectangle = Rectangle::new(Point::new(2, 12), Size::new(62, 36));
+
+/**
+ * Shown after the pet has died, the pet stays dead until P starts a new egg.
+ * Up/Down page through the stats.
+ */
+pub struct MemorialState {
+    memorial: Option<Memorial>,
+    text: String<64>,
+    font: Font,
+    stats: TextBox,
+}
+impl MemorialState {
+    pub fn new() -> Self {
+        let font = Font::default_font();
+        Self {
+            memorial: None,
+            text: String::new(),
+            font,
+            stats: TextBox::new(font, STATS_AREA),
+        }
+    }
+}
+impl GameState for MemorialState {
+    fn tick(
+        &mut self,
+        buttons: &mut impl Input,
+        _sound: &mut Sound,
+        _battery: &mut impl BatteryMonitor,
+        pet: &mut Pet,
+        _settings: &mut Settings,
+    ) -> StateKind {
+        if buttons.is_pressed(Buttons::P) {
+            pet.new_egg();
+            return StateKind::Egg;
+        } else if buttons.is_pressed(Buttons::Down) {
+            self.stats.next_page(&self.text);
+        } else if buttons.is_pressed(Buttons::Up) {
+            self.stats.previous_page();
+        }
+
+        return StateKind::Memorial;
+    }
+
+    fn get_fps() -> FramerateMs {
+        return FramerateMs::Fps5;
+    }
+
+    fn load(&mut self, storage: &mut impl Storage, pet: &Pet, _species: &Species) {
+        self.memorial = pet.memorial();
+        self.font = Font::load(storage);
+        self.stats.set_font(self.font);
+
+        self.text.clear();
+        if let Some(m) = self.memorial {
+            let hours = m.age_seconds / 3600;
+            let cause = match m.cause {
+                DeathCause::OldAge => "old age",
+                DeathCause::Illness => "illness",
+            };
+
+            write!(
+                self.text,
+                "gen {}\nage {}d {}h\ncare {}%\nsick {}x\n{}",
+                m.generation + 1,
+                hours / 24,
+                hours % 24,
+                m.care,
+                m.sick_count,
+                cause
+            )
+            .ok();
+        }
+    }
+
+    fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        display.clear(BinaryColor::Off).ok();
+
+        let title = "R.I.P.";
+        let x = (64 - self.font.text_width(title) as i32) / 2;
+        self.font.draw(display, title, x, 1, TextStyle::HIGHLIGHT);
+
+        self.stats.draw(display, &self.text);
+        self.font.draw(display, "P: new egg", 2, 55, TextStyle::NORMAL);
+    }
+}
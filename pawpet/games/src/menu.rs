@@ -0,0 +1,280 @@
+/*!
+ * Data driven menu widget, menus are declared as statics and linked together by reference.
+ *
+ *  static ROOT: Menu = Menu {
+ *      items: &[
+ *          MenuItem::new("Home", MenuAction::State(StateKind::Home)),
+ *          MenuItem::new("Options", MenuAction::Submenu(&OPTIONS)).icon(ICON_X),
+ *      ],
+ *  };
+ *
+ * Up/Down moves the selection, P activates, A goes back up a level (or exits at the root).
+ * Every press clicks, activating an item plays its sound instead and a disabled item errors.
+ */
+
+use heapless::Vec;
+
+use pawdevicetraits::*;
+
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+
+use crate::font::Font;
+use crate::font::TextStyle;
+use crate::image::PawImage;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    Drawable,
+};
+
+use crate::pet::Pet;
+use crate::sound::Sfx;
+use crate::sound::Sound;
+use crate::StateKind;
+use crate::ICON_SPRITES;
+
+pub const MENU_DEPTH: usize = 4;
+// disabled items are tracked as a bitmask
+pub const MENU_MAX_ITEMS: usize = 32;
+
+const ROW_HEIGHT: i32 = 10;
+const FIRST_ROW_Y: i32 = 15; // text baseline, first 8 rows are the status bar
+const VISIBLE_ROWS: u8 = 5;
+const ICON_WIDTH: i32 = 10;
+
+pub enum MenuAction {
+    State(StateKind),
+    Submenu(&'static Menu),
+    // runs the callback, switches state if it returns one
+    Call(fn(&mut Pet) -> Option<StateKind>),
+}
+
+pub struct MenuItem {
+    pub label: &'static str,
+    pub icon: Option<u8>, // frame in icons.paw
+    pub action: MenuAction,
+    pub enabled: Option<fn(&Pet) -> bool>, // None is always enabled
+    pub sound: Option<Sfx>,                 // played when activated, None clicks
+}
+
+impl MenuItem {
+    pub const fn new(label: &'static str, action: MenuAction) -> Self {
+        Self {
+            label,
+            icon: None,
+            action,
+            enabled: None,
+            sound: None,
+        }
+    }
+
+    pub const fn icon(mut self, icon: u8) -> Self {
+        self.icon = Some(icon);
+        return self;
+    }
+
+    pub const fn enabled_if(mut self, enabled: fn(&Pet) -> bool) -> Self {
+        self.enabled = Some(enabled);
+        return self;
+    }
+
+    pub const fn sound(mut self, sound: Sfx) -> Self {
+        self.sound = Some(sound);
+        return self;
+    }
+}
+
+pub struct Menu {
+    pub items: &'static [MenuItem],
+}
+
+pub enum MenuEvent {
+    None,
+    Exit, // back pressed on the root menu
+    State(StateKind),
+}
+
+struct MenuLevel {
+    menu: &'static Menu,
+    selection: u8,
+    scroll: u8,
+}
+
+pub struct MenuWidget {
+    stack: Vec<MenuLevel, MENU_DEPTH>,
+    icons: PawImage,
+    font: Font,
+    disabled: u32,
+}
+
+impl MenuWidget {
+    pub fn new(root: &'static Menu) -> Self {
+        let mut stack = Vec::new();
+        stack
+            .push(MenuLevel {
+                menu: root,
+                selection: 0,
+                scroll: 0,
+            })
+            .ok();
+
+        Self {
+            stack,
+            icons: PawImage::new(Some(ICON_SPRITES)),
+            font: Font::default_font(),
+            disabled: 0,
+        }
+    }
+
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+
+    fn level(&self) -> &MenuLevel {
+        return self.stack.last().unwrap();
+    }
+
+    fn update_disabled(&mut self, pet: &Pet) {
+        self.disabled = 0;
+        let items = self.level().menu.items;
+
+        for i in 0..items.len().min(MENU_MAX_ITEMS) {
+            if let Some(enabled) = items[i].enabled {
+                if !enabled(pet) {
+                    self.disabled |= 1 << i;
+                }
+            }
+        }
+    }
+
+    fn is_disabled(&self, index: usize) -> bool {
+        return index < MENU_MAX_ITEMS && (self.disabled >> index) & 1 > 0;
+    }
+
+    fn move_selection(&mut self, up: bool) {
+        let level = self.stack.last_mut().unwrap();
+        let count = level.menu.items.len() as u8;
+
+        if up && level.selection > 0 {
+            level.selection -= 1;
+        } else if !up && level.selection + 1 < count {
+            level.selection += 1;
+        }
+
+        // keep the selection on screen
+        if level.selection < level.scroll {
+            level.scroll = level.selection;
+        } else if level.selection >= level.scroll + VISIBLE_ROWS {
+            level.scroll = level.selection + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    pub fn tick(&mut self, buttons: &mut impl Input, pet: &mut Pet, sound: &mut Sound) -> MenuEvent {
+        self.update_disabled(pet);
+
+        if buttons.is_pressed(Buttons::Up) {
+            sound.play(Sfx::Click);
+            self.move_selection(true);
+        } else if buttons.is_pressed(Buttons::Down) {
+            sound.play(Sfx::Click);
+            self.move_selection(false);
+        } else if buttons.is_pressed(Buttons::A) {
+            sound.play(Sfx::Click);
+            if self.stack.len() > 1 {
+                self.stack.pop();
+                self.update_disabled(pet);
+            } else {
+                return MenuEvent::Exit;
+            }
+        } else if buttons.is_pressed(Buttons::P) {
+            let selection = self.level().selection as usize;
+            if selection >= self.level().menu.items.len() || self.is_disabled(selection) {
+                sound.play(Sfx::Error);
+                return MenuEvent::None;
+            }
+
+            let item = &self.level().menu.items[selection];
+            sound.play(item.sound.unwrap_or(Sfx::Click));
+
+            match &item.action {
+                MenuAction::State(state) => {
+                    return MenuEvent::State(*state);
+                }
+                MenuAction::Submenu(menu) => {
+                    let level = MenuLevel {
+                        menu,
+                        selection: 0,
+                        scroll: 0,
+                    };
+                    if self.stack.push(level).is_ok() {
+                        self.update_disabled(pet);
+                    }
+                }
+                MenuAction::Call(callback) => {
+                    if let Some(state) = callback(pet) {
+                        return MenuEvent::State(state);
+                    }
+                    // callback may have changed what is enabled
+                    self.update_disabled(pet);
+                }
+            }
+        }
+
+        return MenuEvent::None;
+    }
+
+    pub fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        let level = self.level();
+        let items = level.menu.items;
+        let first = level.scroll as usize;
+        let last = (first + VISIBLE_ROWS as usize).min(items.len());
+        let selection = level.selection as usize;
+
+        for i in first..last {
+            let y = ROW_HEIGHT * (i - first) as i32 + FIRST_ROW_Y;
+            let item = &items[i];
+
+            if let Some(icon) = item.icon {
+                self.icons.set_frame(icon);
+                self.icons.draw(display, 0, y - 7);
+            }
+
+            let (style, line_color) = if selection == i {
+                (TextStyle::HIGHLIGHT, BinaryColor::Off)
+            } else {
+                (TextStyle::NORMAL, BinaryColor::On)
+            };
+            // the bottom row of the glyph cells sits on the baseline
+            let text_y = y + 1 - self.font.height() as i32;
+            let end = self.font.draw(display, item.label, ICON_WIDTH, text_y, style);
+
+            // disabled items are drawn with a strike through
+            if self.is_disabled(i) {
+                let width = (end - ICON_WIDTH) as u32;
+                let strike_y = text_y + self.font.height() as i32 / 2;
+                Rectangle::new(Point::new(ICON_WIDTH, strike_y), Size::new(width, 1))
+                    .into_styled(PrimitiveStyle::with_fill(line_color))
+                    .draw(display)
+                    .ok();
+            }
+        }
+
+        // scroll bar
+        if items.len() > VISIBLE_ROWS as usize {
+            let track = ROW_HEIGHT * VISIBLE_ROWS as i32;
+            let height = track * VISIBLE_ROWS as i32 / items.len() as i32;
+            let offset = track * first as i32 / items.len() as i32;
+
+            Rectangle::new(
+                Point::new(62, FIRST_ROW_Y - 8 + offset),
+                Size::new(2, height.max(2) as u32),
+            )
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)
+            .ok();
+        }
+    }
+}
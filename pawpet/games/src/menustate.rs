@@ -1,51 +1,92 @@
-use embedded_graphics::mono_font::MonoTextStyle;
-use embedded_graphics::mono_font::MonoTextStyleBuilder;
-use heapless::String;
-
-use pawdevicetraits::*;
-
 use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
 use pawdevicetraits::ButtonsDevice as Input;
 use pawdevicetraits::DisplayDevice as Display;
-use pawdevicetraits::ToneDevice as Tone;
 use pawdevicetraits::StorageDevice as Storage;
 
-use crate::image::PawImage;
-use crate::image::PawAnimation;
-
 use embedded_graphics::{
-    mono_font::ascii::FONT_6X10,
     pixelcolor::BinaryColor,
     prelude::*,
-    text::Text,
-    Drawable,
 };
 
-use crate::include_bytes_align_as;
-
+use crate::font::Font;
+use crate::homestate::CLEAN_AMOUNT;
+use crate::homestate::FEED_AMOUNT;
+use crate::menu::Menu;
+use crate::menu::MenuAction;
+use crate::menu::MenuEvent;
+use crate::menu::MenuItem;
+use crate::menu::MenuWidget;
+use crate::pet::Need;
+use crate::pet::Pet;
+use crate::species::Species;
+use crate::settings::Settings;
+use crate::sound::Sfx;
+use crate::sound::Sound;
 use crate::GameState;
-
 use crate::StateKind;
+use crate::{ICON_CLEAN, ICON_FEED, ICON_PLAY, ICON_SICK, ICON_SUBMENU};
+
+const PLAY_AMOUNT: u8 = 20;
+
+// care actions go back to the home screen so the player sees the pet react
+fn feed(pet: &mut Pet) -> Option<StateKind> {
+    pet.care(Need::Hunger, FEED_AMOUNT);
+    return Some(StateKind::Home);
+}
+
+fn play(pet: &mut Pet) -> Option<StateKind> {
+    pet.care(Need::Happiness, PLAY_AMOUNT);
+    return Some(StateKind::Home);
+}
+
+fn clean(pet: &mut Pet) -> Option<StateKind> {
+    pet.care(Need::Hygiene, CLEAN_AMOUNT);
+    return Some(StateKind::Home);
+}
+
+fn medicine(pet: &mut Pet) -> Option<StateKind> {
+    pet.give_medicine();
+    return Some(StateKind::Home);
+}
+
+fn is_sick(pet: &Pet) -> bool {
+    return pet.is_sick();
+}
+
+static DEBUG_MENU: Menu = Menu {
+    items: &[
+        MenuItem::new("Egg", MenuAction::State(StateKind::Egg)),
+        MenuItem::new("Empty", MenuAction::State(StateKind::Empty)),
+    ],
+};
+
+static CARE_MENU: Menu = Menu {
+    items: &[
+        MenuItem::new("Feed", MenuAction::Call(feed))
+            .icon(ICON_FEED)
+            .sound(Sfx::Eat),
+        MenuItem::new("Play", MenuAction::Call(play))
+            .icon(ICON_PLAY)
+            .sound(Sfx::Happy),
+        MenuItem::new("Clean", MenuAction::Call(clean))
+            .icon(ICON_CLEAN)
+            .sound(Sfx::Happy),
+        MenuItem::new("Medicine", MenuAction::Call(medicine))
+            .icon(ICON_SICK)
+            .enabled_if(is_sick)
+            .sound(Sfx::Happy),
+        MenuItem::new("Settings", MenuAction::State(StateKind::Settings)).icon(ICON_SUBMENU),
+        MenuItem::new("Debug", MenuAction::Submenu(&DEBUG_MENU)).icon(ICON_SUBMENU),
+    ],
+};
 
 pub struct MenuState {
-    str: String<64>,
-    selection: usize,
-    menu_items: [String<8>; 5],
-    redraw: bool,
+    menu: MenuWidget,
 }
 impl MenuState {
     pub fn new() -> Self {
         Self {
-            str: String::new(),
-            selection: 0,
-            menu_items: [
-                "Egg".into(),
-                "Empty".into(),
-                "Item 3".into(),
-                "Item 4".into(),
-                "Item 5".into(),
-            ],
-            redraw: true,
+            menu: MenuWidget::new(&CARE_MENU),
         }
     }
 }
@@ -54,78 +95,27 @@ impl GameState for MenuState {
     fn tick(
         &mut self,
         buttons: &mut impl Input,
-        _tone: &impl Tone,
+        sound: &mut Sound,
         _battery: &mut impl BatteryMonitor,
+        pet: &mut Pet,
+        _settings: &mut Settings,
     ) -> StateKind {
-        self.redraw = true;
-
-        if buttons.is_pressed(Buttons::Up) {
-            if self.selection > 0 {
-                self.selection -= 1;
-            }
-            self.redraw = true;
-        } else if buttons.is_pressed(Buttons::Down) {
-            self.selection += 1;
-            if self.selection >= self.menu_items.len() {
-                self.selection = self.menu_items.len() - 1;
-            }
-
-            self.redraw = true;
-        } else if buttons.is_pressed(Buttons::P) {
-            match self.selection
-            {
-                0 => {
-                    return StateKind::Egg
-                },
-                1 => {
-                    return StateKind::Empty
-                },
-                _ => {}
-            }
-
+        match self.menu.tick(buttons, pet, sound) {
+            MenuEvent::None => {}
+            MenuEvent::Exit => return StateKind::Home,
+            MenuEvent::State(state) => return state,
         }
 
         return StateKind::Menu;
     }
 
+    fn load(&mut self, storage: &mut impl Storage, _pet: &Pet, _species: &Species) {
+        self.menu.set_font(Font::load(storage));
+    }
+
     fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
         display.clear(BinaryColor::Off).ok();
 
-        let white_text: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
-            .font(&FONT_6X10)
-            .text_color(BinaryColor::On)
-            .background_color(BinaryColor::Off)
-            .build();
-
-        let black_text: MonoTextStyle<'static, BinaryColor> = MonoTextStyleBuilder::new()
-            .font(&FONT_6X10)
-            .text_color(BinaryColor::Off)
-            .background_color(BinaryColor::On)
-            .build();
-
-        // self.str.clear();
-        // write!(self.str, "{:08b}\n{}", buttons.get_pressed(),self.selection).ok();
-
-        for i in 0..self.menu_items.len() {
-            let x = 0;
-            let y = 10 * (i as i32) + 15;
-            let mut style = white_text;
-
-            if self.selection == i {
-                style = black_text;
-            }
-
-            Text::new(&self.menu_items[i], Point::new(x, y), style)
-                .draw(display)
-                .ok();
-        }
-        Text::new(&self.str, Point::new(2, 8), white_text)
-            .draw(display)
-            .ok();
-
-    }
-
-    fn need_redraw(&mut self) -> bool {
-        return self.redraw;
+        self.menu.draw(display);
     }
 }
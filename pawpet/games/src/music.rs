@@ -0,0 +1,388 @@
+/*!
+ * Songs for the beeper, a single voice of notes and rests. Compiled on host by png2c/song.py
+ * from an MML-like text notation.
+ *
+ * little endian
+ * [header 8 bytes]
+ *      magic "PSNG", version u8, tempo u8 (beats per minute), event bytes u16
+ * [events]
+ *      0x00-0x4F duration u8       note, index into TONE_NOTES, duration in ticks
+ *      REST      duration u8
+ *      TEMPO     bpm u8
+ *      LOOP      count u8          plays the events up to the matching END_LOOP count times, 0 forever
+ *      END_LOOP
+ *      ARPEGGIO  offsets u8        semitones above the note, high and low nibble, 0 turns it off
+ *      GATE      eighths u8        part of a note that sounds (1-8), the rest is silent
+ *
+ * A beat is TICKS_PER_BEAT ticks. The sequencer doesn't block, update() is called from the frame
+ * tick (or a timer interrupt) with the time since the last call, so timing resolution is the
+ * update period. It returns the frequency to play, sound.rs sends it to the tone device.
+ */
+
+use heapless::Vec;
+use pawdevicetraits::StorageDevice as Storage;
+use pawdevicetraits::TONE_NOTES;
+use rtt_target::debug_rprintln;
+
+pub const TICKS_PER_BEAT: u32 = 12;
+pub const STARTUP_SONG_FILE: &str = "startup";
+
+const MAGIC: &[u8; 4] = b"PSNG";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 8;
+const MAX_LOOP_DEPTH: usize = 4;
+const DEFAULT_GATE: u8 = 7;
+const ARPEGGIO_MS: u32 = 33; // each note of an arpeggio, about a frame at 30 fps
+
+const NOTE_COUNT: u8 = 80;
+const REST: u8 = 0x80;
+const TEMPO: u8 = 0x81;
+const LOOP: u8 = 0x82;
+const END_LOOP: u8 = 0x83;
+const ARPEGGIO: u8 = 0x84;
+const GATE: u8 = 0x85;
+
+// two beeps, replaceable with a "startup" song in storage
+#[rustfmt::skip]
+static STARTUP_SONG: [u8; 16] = [
+    b'P', b'S', b'N', b'G', VERSION, 120, 8, 0,
+    GATE, 8,
+    38, 6,
+    REST, 6,
+    44, 6,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum SongError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion,
+    BadTempo,
+    BadEvent(usize), // offset into the events
+    BadLoop(usize),
+}
+
+#[derive(Copy, Clone)]
+pub struct Song {
+    tempo: u8,
+    events: &'static [u8],
+}
+
+impl Song {
+    pub fn startup() -> Self {
+        return Song::parse(&STARTUP_SONG).unwrap();
+    }
+
+    // song file from storage, None if missing or invalid
+    pub fn load(storage: &mut impl Storage, name: &str) -> Option<Self> {
+        let data = storage.load_image(name)?;
+        match Song::parse(data) {
+            Ok(song) => return Some(song),
+            Err(e) => {
+                debug_rprintln!("invalid song {} {:?}", name, e);
+                return None;
+            }
+        }
+    }
+
+    // checks every event so the sequencer can play without error handling
+    pub fn parse(data: &'static [u8]) -> Result<Self, SongError> {
+        if data.len() < HEADER_SIZE {
+            return Err(SongError::Truncated);
+        }
+        if &data[0..4] != MAGIC {
+            return Err(SongError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(SongError::UnsupportedVersion);
+        }
+        let tempo = data[5];
+        if tempo == 0 {
+            return Err(SongError::BadTempo);
+        }
+
+        let length = u16::from_le_bytes([data[6], data[7]]) as usize;
+        if data.len() < HEADER_SIZE + length {
+            return Err(SongError::Truncated);
+        }
+        let events = &data[HEADER_SIZE..HEADER_SIZE + length];
+
+        // loop start offset and whether a note or rest has been seen since, a forever loop
+        // without either would never give up the sequencer
+        let mut loops: Vec<(usize, bool, bool), MAX_LOOP_DEPTH> = Vec::new();
+        let mut at = 0;
+        while at < events.len() {
+            let event = events[at];
+            if event == END_LOOP {
+                let (start, forever, timed) = loops.pop().ok_or(SongError::BadLoop(at))?;
+                if forever && !timed {
+                    return Err(SongError::BadLoop(start));
+                }
+                if let Some(outer) = loops.last_mut() {
+                    outer.2 |= timed;
+                }
+                at += 1;
+                continue;
+            }
+
+            let value = *events.get(at + 1).ok_or(SongError::Truncated)?;
+            match event {
+                0..=0x4F | REST => {
+                    // notes and rests are at least a tick
+                    if value == 0 {
+                        return Err(SongError::BadEvent(at));
+                    }
+                    if let Some(inner) = loops.last_mut() {
+                        inner.2 = true;
+                    }
+                }
+                TEMPO if value > 0 => {}
+                LOOP => {
+                    loops.push((at, value == 0, false)).map_err(|_| SongError::BadLoop(at))?;
+                }
+                ARPEGGIO => {}
+                GATE if (1..=8).contains(&value) => {}
+                _ => return Err(SongError::BadEvent(at)),
+            }
+            at += 2;
+        }
+        if let Some((start, _, _)) = loops.last() {
+            return Err(SongError::BadLoop(*start));
+        }
+
+        return Ok(Self { tempo, events });
+    }
+}
+
+struct LoopFrame {
+    start: usize,   // event after the LOOP
+    remaining: u8, // plays left including the current one, 0 forever
+}
+
+pub struct Sequencer {
+    song: Option<Song>,
+    position: usize,
+    loops: Vec<LoopFrame, MAX_LOOP_DEPTH>,
+    tick_us: u32,
+    gate: u8,
+    arpeggio: u8,
+    note: Option<u8>, // None while resting
+    event_us: u32,    // length of the current note or rest
+    time_us: u32,     // time into the current note or rest
+}
+
+impl Sequencer {
+    pub const fn new() -> Self {
+        Self {
+            song: None,
+            position: 0,
+            loops: Vec::new(),
+            tick_us: 0,
+            gate: DEFAULT_GATE,
+            arpeggio: 0,
+            note: None,
+            event_us: 0,
+            time_us: 0,
+        }
+    }
+
+    // starts from the beginning, the first note sounds on the next update
+    pub fn play(&mut self, song: Song) {
+        self.song = Some(song);
+        self.position = 0;
+        self.loops.clear();
+        self.set_tempo(song.tempo);
+        self.gate = DEFAULT_GATE;
+        self.arpeggio = 0;
+        self.note = None;
+        self.event_us = 0;
+        self.time_us = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.song = None;
+        self.note = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        return self.song.is_some();
+    }
+
+    fn set_tempo(&mut self, bpm: u8) {
+        self.tick_us = 60_000_000 / (bpm as u32 * TICKS_PER_BEAT);
+    }
+
+    // runs control events up to the next note or rest, false at the end of the song
+    fn next_event(&mut self) -> bool {
+        let events = match self.song {
+            Some(song) => song.events,
+            None => return false,
+        };
+
+        while self.position < events.len() {
+            let event = events[self.position];
+            if event == END_LOOP {
+                self.position += 1;
+                if let Some(frame) = self.loops.last_mut() {
+                    if frame.remaining == 0 || frame.remaining > 1 {
+                        frame.remaining = frame.remaining.saturating_sub(1);
+                        self.position = frame.start;
+                    } else {
+                        self.loops.pop();
+                    }
+                }
+                continue;
+            }
+
+            // validated by Song::parse
+            let value = events[self.position + 1];
+            self.position += 2;
+            match event {
+                REST => {
+                    self.note = None;
+                    self.event_us = value as u32 * self.tick_us;
+                    return true;
+                }
+                TEMPO => self.set_tempo(value),
+                LOOP => {
+                    let frame = LoopFrame {
+                        start: self.position,
+                        remaining: value,
+                    };
+                    self.loops.push(frame).ok();
+                }
+                ARPEGGIO => self.arpeggio = value,
+                GATE => self.gate = value,
+                _ => {
+                    self.note = Some(event);
+                    self.event_us = value as u32 * self.tick_us;
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    // advance by the time since the last update, frequency for the current position or 0 for
+    // silence
+    pub fn update(&mut self, elapsed_ms: u32) -> u32 {
+        if self.song.is_none() {
+            return 0;
+        }
+
+        self.time_us += elapsed_ms * 1000;
+        while self.time_us >= self.event_us {
+            self.time_us -= self.event_us;
+            if !self.next_event() {
+                self.stop();
+                return 0;
+            }
+        }
+
+        match self.note {
+            Some(note) if self.time_us < self.event_us / 8 * self.gate as u32 => {
+                let offset = match (self.time_us / 1000 / ARPEGGIO_MS) % 3 {
+                    1 if self.arpeggio > 0 => self.arpeggio >> 4,
+                    2 if self.arpeggio > 0 => self.arpeggio & 0xF,
+                    _ => 0,
+                };
+                let index = (note + offset).min(NOTE_COUNT - 1);
+                return TONE_NOTES[index as usize];
+            }
+            _ => return 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn song(events: &[u8]) -> Result<Song, SongError> {
+        let mut data = Vec::from(&MAGIC[..]);
+        data.extend_from_slice(&[VERSION, 125]);
+        data.extend_from_slice(&(events.len() as u16).to_le_bytes());
+        data.extend_from_slice(events);
+        return Song::parse(Vec::leak(data));
+    }
+
+    // frequency changes with an update every 10 ms, 0 is silence
+    fn changes(sequencer: &mut Sequencer, ms: u32) -> Vec<u32> {
+        let mut played = Vec::new();
+        let mut last = 0;
+        for _ in 0..ms / 10 {
+            let freq = sequencer.update(10);
+            if freq != last {
+                played.push(freq);
+                last = freq;
+            }
+        }
+        return played;
+    }
+
+    // 125 bpm is 40 ms per tick
+    fn play(song: Song, ms: u32) -> Vec<u32> {
+        let mut sequencer = Sequencer::new();
+        sequencer.play(song);
+        return changes(&mut sequencer, ms);
+    }
+
+    #[test]
+    fn notes_rests_and_gate() {
+        let song = song(&[GATE, 8, 27, 1, REST, 1, 27, 2, GATE, 4, 39, 2, 39, 1]).unwrap();
+        let c4 = TONE_NOTES[27];
+        let c5 = TONE_NOTES[39];
+        // repeated notes only sound separately with a rest or a shorter gate between them
+        assert_eq!(play(song, 1000), [c4, 0, c4, c5, 0, c5, 0]);
+    }
+
+    #[test]
+    fn loops_and_tempo() {
+        // note, twice (note, twice (rest)), the inner loop ends before the outer one
+        let nested = song(&[GATE, 4, LOOP, 2, 10, 1, LOOP, 2, REST, 1, END_LOOP, END_LOOP]).unwrap();
+        let note = TONE_NOTES[10];
+        assert_eq!(play(nested, 1000), [note, 0, note, 0]);
+
+        // 250 bpm halves the tick, the song ends after 8 ticks of 20 ms and the beeper stops
+        let fast = song(&[TEMPO, 250, GATE, 8, 10, 8, 20, 1]).unwrap();
+        let mut sequencer = Sequencer::new();
+        sequencer.play(fast);
+        assert_eq!(changes(&mut sequencer, 150), [note]);
+        assert_eq!(sequencer.update(10), TONE_NOTES[20]);
+        assert_eq!(sequencer.update(20), 0);
+        assert!(!sequencer.is_playing());
+
+        // forever keeps going, the first note then a change every tick
+        let forever = song(&[LOOP, 0, 10, 1, REST, 1, END_LOOP]).unwrap();
+        assert_eq!(play(forever, 4000).len(), 101);
+    }
+
+    #[test]
+    fn arpeggio_cycles_the_chord() {
+        let song = song(&[GATE, 8, ARPEGGIO, 0x47, 27, 48]).unwrap();
+        let chord = [TONE_NOTES[27], TONE_NOTES[31], TONE_NOTES[34]];
+        let played = play(song, 400);
+        assert_eq!(&played[0..6], [chord, chord].concat().as_slice());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Song::parse(&STARTUP_SONG).is_ok());
+        assert_eq!(song(&[80, 1]).err(), Some(SongError::BadEvent(0)));
+        assert_eq!(song(&[10, 0]).err(), Some(SongError::BadEvent(0)));
+        assert_eq!(song(&[GATE, 9]).err(), Some(SongError::BadEvent(0)));
+        assert_eq!(song(&[10]).err(), Some(SongError::Truncated));
+        assert_eq!(song(&[END_LOOP]).err(), Some(SongError::BadLoop(0)));
+        assert_eq!(song(&[10, 1, LOOP, 2, 10, 1]).err(), Some(SongError::BadLoop(2)));
+        // would never reach a note
+        assert_eq!(song(&[LOOP, 0, TEMPO, 100, END_LOOP]).err(), Some(SongError::BadLoop(0)));
+        assert!(song(&[LOOP, 0, LOOP, 2, 10, 1, END_LOOP, END_LOOP]).is_ok());
+        assert_eq!(
+            song(&[LOOP, 2, LOOP, 2, LOOP, 2, LOOP, 2, LOOP, 2]).err(),
+            Some(SongError::BadLoop(8))
+        );
+    }
+}
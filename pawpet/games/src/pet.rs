@@ -0,0 +1,575 @@
+/*!
+ * Pet needs, each need drains over real (rtc) time and is refilled by care actions.
+ */
+
+use crate::save::SaveReader;
+use crate::save::SaveWriter;
+use crate::species::Species;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Need {
+    Hunger = 0,
+    Happiness = 1,
+    Hygiene = 2,
+}
+
+pub const NEED_COUNT: usize = 3;
+pub const NEED_MAX: u8 = 100;
+
+// seconds for a need to drop by one point, hunger goes from full to empty in ~16 hours
+const DECAY_SECONDS: [u32; NEED_COUNT] = [600, 900, 1200];
+
+pub struct PetNeeds {
+    levels: [u8; NEED_COUNT],
+    // seconds accumulated towards the next decay point
+    carry: [u32; NEED_COUNT],
+    last_update: Option<u32>,
+}
+
+impl PetNeeds {
+    pub fn new() -> Self {
+        Self {
+            levels: [NEED_MAX; NEED_COUNT],
+            carry: [0; NEED_COUNT],
+            last_update: None,
+        }
+    }
+
+    // decay needs by time passed since the last update, rtc going backwards (clock set) is ignored
+    pub fn update(&mut self, now: u32) {
+        let elapsed = match self.last_update {
+            Some(last) => now.saturating_sub(last),
+            None => 0,
+        };
+        self.last_update = Some(now);
+
+        for i in 0..NEED_COUNT {
+            self.carry[i] += elapsed;
+            let drop = self.carry[i] / DECAY_SECONDS[i];
+            self.carry[i] %= DECAY_SECONDS[i];
+
+            self.levels[i] = self.levels[i].saturating_sub(drop.min(NEED_MAX as u32) as u8);
+        }
+    }
+
+    fn save(&self, writer: &mut SaveWriter) {
+        for i in 0..NEED_COUNT {
+            writer.put_u8(self.levels[i]);
+            writer.put_u32(self.carry[i]);
+        }
+        writer.put_time(self.last_update);
+    }
+
+    fn restore(reader: &mut SaveReader) -> Option<Self> {
+        let mut needs = PetNeeds::new();
+        for i in 0..NEED_COUNT {
+            needs.levels[i] = reader.get_u8()?.min(NEED_MAX);
+            needs.carry[i] = reader.get_u32()?;
+        }
+        needs.last_update = reader.get_time()?;
+        return Some(needs);
+    }
+
+    pub fn level(&self, need: Need) -> u8 {
+        return self.levels[need as usize];
+    }
+
+    pub fn satisfy(&mut self, need: Need, amount: u8) {
+        let level = &mut self.levels[need as usize];
+        *level = level.saturating_add(amount).min(NEED_MAX);
+    }
+
+    pub fn average(&self) -> u8 {
+        let mut sum: u32 = 0;
+        for l in self.levels {
+            sum += l as u32;
+        }
+        return (sum / NEED_COUNT as u32) as u8;
+    }
+
+    // lowest need that is below the threshold
+    pub fn most_urgent(&self, threshold: u8) -> Option<Need> {
+        let mut urgent: Option<Need> = None;
+        for need in [Need::Hunger, Need::Happiness, Need::Hygiene] {
+            if self.level(need) < threshold
+                && (urgent.is_none() || self.level(need) < self.level(urgent.unwrap()))
+            {
+                urgent = Some(need);
+            }
+        }
+        return urgent;
+    }
+}
+
+// average need level below which the pet is neglected, sick after being neglected for a while
+const NEGLECT_THRESHOLD: u8 = 30;
+const NEGLECT_SICK_SECONDS: u32 = 2 * 60 * 60;
+// dies of illness if not given medicine in time
+const SICK_DEATH_SECONDS: u32 = 24 * 60 * 60;
+
+pub const HISTORY_SIZE: usize = 4;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DeathCause {
+    OldAge = 0,
+    Illness = 1,
+}
+
+impl DeathCause {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DeathCause::OldAge),
+            1 => Some(DeathCause::Illness),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PetEvent {
+    Evolved,
+    Sick,
+    Died,
+}
+
+/**
+ * Record of a pet that has passed on, kept in the save file history.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Memorial {
+    pub generation: u16,
+    pub age_seconds: u32,
+    pub stage: u8,
+    pub care: u8, // average care quality over the whole life, 0-100
+    pub sick_count: u8,
+    pub cause: DeathCause,
+}
+
+impl Memorial {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.put_u16(self.generation);
+        writer.put_u32(self.age_seconds);
+        writer.put_u8(self.stage);
+        writer.put_u8(self.care);
+        writer.put_u8(self.sick_count);
+        writer.put_u8(self.cause as u8);
+    }
+
+    fn restore(reader: &mut SaveReader) -> Option<Self> {
+        return Some(Self {
+            generation: reader.get_u16()?,
+            age_seconds: reader.get_u32()?,
+            stage: reader.get_u8()?,
+            care: reader.get_u8()?,
+            sick_count: reader.get_u8()?,
+            cause: DeathCause::from_u8(reader.get_u8()?)?,
+        });
+    }
+}
+
+/**
+ * Pet life, current evolution stage and care quality tracked over the stage.
+ * Neglect makes the pet sick, untreated sickness or reaching the end of the final
+ * stage's lifespan kills it. A dead pet stays around for the memorial until a new egg is started.
+ */
+pub struct Pet {
+    pub needs: PetNeeds,
+    stage: u8,
+    stage_start: Option<u32>,
+    // need levels integrated over time spent in the stage
+    care_sum: u64,
+    care_seconds: u32,
+    last_update: Option<u32>,
+
+    birth: Option<u32>,
+    life_care_sum: u64,
+    life_care_seconds: u32,
+    neglect_seconds: u32,
+    sick_since: Option<u32>,
+    sick_count: u8,
+    death: Option<(DeathCause, u32)>, // cause, rtc time of death
+
+    generation: u16,
+    history: [Option<Memorial>; HISTORY_SIZE], // newest first
+    dirty: bool, // changed in a way that should be saved right away
+}
+
+impl Pet {
+    pub fn new() -> Self {
+        Self {
+            needs: PetNeeds::new(),
+            stage: 0,
+            stage_start: None,
+            care_sum: 0,
+            care_seconds: 0,
+            last_update: None,
+            birth: None,
+            life_care_sum: 0,
+            life_care_seconds: 0,
+            neglect_seconds: 0,
+            sick_since: None,
+            sick_count: 0,
+            death: None,
+            generation: 0,
+            history: [None; HISTORY_SIZE],
+            dirty: false,
+        }
+    }
+
+    pub fn stage(&self) -> u8 {
+        return self.stage;
+    }
+
+    pub fn is_alive(&self) -> bool {
+        return self.death.is_none();
+    }
+
+    pub fn is_sick(&self) -> bool {
+        return self.sick_since.is_some();
+    }
+
+    pub fn history(&self) -> &[Option<Memorial>; HISTORY_SIZE] {
+        return &self.history;
+    }
+
+    // average need level over the current stage, 0-100
+    pub fn care_quality(&self) -> u8 {
+        if self.care_seconds == 0 {
+            return NEED_MAX;
+        }
+        return (self.care_sum / self.care_seconds as u64) as u8;
+    }
+
+    // decays needs, progresses sickness and evolves when the stage duration has passed
+    pub fn update(&mut self, now: u32, species: &Species) -> Option<PetEvent> {
+        if !self.is_alive() {
+            return None;
+        }
+
+        let elapsed = match self.last_update {
+            Some(last) => now.saturating_sub(last),
+            None => 0,
+        };
+        self.last_update = Some(now);
+
+        // sample care before decay so the elapsed time is credited with the levels it started at
+        let average = self.needs.average();
+        self.care_sum += average as u64 * elapsed as u64;
+        self.care_seconds += elapsed;
+        self.life_care_sum += average as u64 * elapsed as u64;
+        self.life_care_seconds += elapsed;
+
+        self.needs.update(now);
+
+        self.birth.get_or_insert(now);
+        let stage_start = *self.stage_start.get_or_insert(now);
+
+        match self.sick_since {
+            Some(since) => {
+                if now.saturating_sub(since) >= SICK_DEATH_SECONDS {
+                    self.die(DeathCause::Illness, now);
+                    return Some(PetEvent::Died);
+                }
+            }
+            None => {
+                if average < NEGLECT_THRESHOLD {
+                    self.neglect_seconds += elapsed;
+                } else {
+                    self.neglect_seconds = 0;
+                }
+
+                if self.neglect_seconds >= NEGLECT_SICK_SECONDS {
+                    self.neglect_seconds = 0;
+                    self.sick_since = Some(now);
+                    self.sick_count = self.sick_count.saturating_add(1);
+                    self.dirty = true;
+                    return Some(PetEvent::Sick);
+                }
+            }
+        }
+
+        let stage = species.stage(self.stage);
+        if stage.is_none() {
+            // species file changed underneath the pet, restart from the first stage
+            self.set_stage(0, now);
+            return Some(PetEvent::Evolved);
+        }
+
+        let stage = stage.unwrap();
+        if stage.duration_min == 0 || now.saturating_sub(stage_start) < stage.duration_min as u32 * 60 {
+            return None;
+        }
+
+        // final stage duration is the lifespan
+        if stage.is_final() {
+            self.die(DeathCause::OldAge, now);
+            return Some(PetEvent::Died);
+        }
+
+        match species.next_stage(self.stage, self.care_quality()) {
+            Some(next) => {
+                self.set_stage(next, now);
+                self.dirty = true;
+                return Some(PetEvent::Evolved);
+            }
+            None => {
+                return None;
+            }
+        }
+    }
+
+    // care action from the player, saved right away
+    pub fn care(&mut self, need: Need, amount: u8) {
+        self.needs.satisfy(need, amount);
+        self.dirty = true;
+    }
+
+    // cures sickness, returns false if the pet was not sick
+    pub fn give_medicine(&mut self) -> bool {
+        if !self.is_alive() || self.sick_since.is_none() {
+            return false;
+        }
+        self.sick_since = None;
+        self.neglect_seconds = 0;
+        self.dirty = true;
+        return true;
+    }
+
+    // life summary of the current pet, only available once it has died
+    pub fn memorial(&self) -> Option<Memorial> {
+        let (cause, died_at) = self.death?;
+
+        let care = if self.life_care_seconds == 0 {
+            NEED_MAX
+        } else {
+            (self.life_care_sum / self.life_care_seconds as u64) as u8
+        };
+
+        return Some(Memorial {
+            generation: self.generation,
+            age_seconds: died_at.saturating_sub(self.birth.unwrap_or(died_at)),
+            stage: self.stage,
+            care,
+            sick_count: self.sick_count,
+            cause,
+        });
+    }
+
+    // archive the dead pet into the history and start over from an egg
+    pub fn new_egg(&mut self) {
+        let memorial = self.memorial();
+        let mut history = self.history;
+
+        if memorial.is_some() {
+            history.rotate_right(1);
+            history[0] = memorial;
+        }
+
+        *self = Pet::new();
+        self.history = history;
+        self.generation = match memorial {
+            Some(m) => m.generation.wrapping_add(1),
+            None => self.generation,
+        };
+        self.dirty = true;
+    }
+
+    // returns true once after a change that should be written to the save file
+    pub fn take_dirty(&mut self) -> bool {
+        let dirty = self.dirty;
+        self.dirty = false;
+        return dirty;
+    }
+
+    fn die(&mut self, cause: DeathCause, now: u32) {
+        self.death = Some((cause, now));
+        self.sick_since = None;
+        self.dirty = true;
+    }
+
+    fn set_stage(&mut self, stage: u8, now: u32) {
+        self.stage = stage;
+        self.stage_start = Some(now);
+        self.care_sum = 0;
+        self.care_seconds = 0;
+    }
+
+    // clock was set, move timestamps so the jump does not count as time passed
+    pub fn shift_time(&mut self, delta: i32) {
+        let shift = |t: &mut Option<u32>| {
+            if let Some(t) = t {
+                *t = t.wrapping_add(delta as u32);
+            }
+        };
+
+        shift(&mut self.needs.last_update);
+        shift(&mut self.stage_start);
+        shift(&mut self.last_update);
+        shift(&mut self.birth);
+        shift(&mut self.sick_since);
+        if let Some((_, at)) = &mut self.death {
+            *at = at.wrapping_add(delta as u32);
+        }
+    }
+
+    pub fn save(&self, writer: &mut SaveWriter) {
+        self.needs.save(writer);
+
+        writer.put_u8(self.stage);
+        writer.put_time(self.stage_start);
+        writer.put_u32((self.care_sum >> 32) as u32);
+        writer.put_u32(self.care_sum as u32);
+        writer.put_u32(self.care_seconds);
+        writer.put_time(self.last_update);
+
+        writer.put_time(self.birth);
+        writer.put_u32((self.life_care_sum >> 32) as u32);
+        writer.put_u32(self.life_care_sum as u32);
+        writer.put_u32(self.life_care_seconds);
+        writer.put_u32(self.neglect_seconds);
+        writer.put_time(self.sick_since);
+        writer.put_u8(self.sick_count);
+        match self.death {
+            Some((cause, at)) => {
+                writer.put_u8(1);
+                writer.put_u8(cause as u8);
+                writer.put_u32(at);
+            }
+            None => {
+                writer.put_u8(0);
+                writer.put_u8(0);
+                writer.put_u32(0);
+            }
+        }
+
+        writer.put_u16(self.generation);
+        for entry in self.history.iter() {
+            match entry {
+                Some(memorial) => {
+                    writer.put_u8(1);
+                    memorial.save(writer);
+                }
+                None => writer.put_u8(0),
+            }
+        }
+    }
+
+    pub fn restore(reader: &mut SaveReader) -> Option<Self> {
+        let mut pet = Pet::new();
+        pet.needs = PetNeeds::restore(reader)?;
+
+        pet.stage = reader.get_u8()?;
+        pet.stage_start = reader.get_time()?;
+        pet.care_sum = (reader.get_u32()? as u64) << 32 | reader.get_u32()? as u64;
+        pet.care_seconds = reader.get_u32()?;
+        pet.last_update = reader.get_time()?;
+
+        pet.birth = reader.get_time()?;
+        pet.life_care_sum = (reader.get_u32()? as u64) << 32 | reader.get_u32()? as u64;
+        pet.life_care_seconds = reader.get_u32()?;
+        pet.neglect_seconds = reader.get_u32()?;
+        pet.sick_since = reader.get_time()?;
+        pet.sick_count = reader.get_u8()?;
+
+        let dead = reader.get_u8()?;
+        let cause = reader.get_u8()?;
+        let at = reader.get_u32()?;
+        if dead != 0 {
+            pet.death = Some((DeathCause::from_u8(cause)?, at));
+        }
+
+        pet.generation = reader.get_u16()?;
+        for i in 0..HISTORY_SIZE {
+            if reader.get_u8()? != 0 {
+                pet.history[i] = Some(Memorial::restore(reader)?);
+            }
+        }
+
+        return Some(pet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::SAVE_BUFFER_SIZE;
+
+    #[test]
+    fn needs_decay_over_time() {
+        let mut needs = PetNeeds::new();
+        needs.update(1000);
+        assert_eq!(needs.level(Need::Hunger), NEED_MAX);
+
+        // partial periods carry over to the next update
+        needs.update(1000 + 599);
+        assert_eq!(needs.level(Need::Hunger), NEED_MAX);
+        needs.update(1000 + 600);
+        assert_eq!(needs.level(Need::Hunger), NEED_MAX - 1);
+
+        needs.update(1000 + 3600);
+        assert_eq!(needs.level(Need::Hunger), NEED_MAX - 6);
+        assert_eq!(needs.level(Need::Happiness), NEED_MAX - 4);
+        assert_eq!(needs.level(Need::Hygiene), NEED_MAX - 3);
+
+        // bottoms out at 0
+        needs.update(1000 + 365 * 24 * 3600);
+        assert_eq!(needs.level(Need::Hunger), 0);
+        assert_eq!(needs.average(), 0);
+    }
+
+    #[test]
+    fn clock_going_back_is_ignored() {
+        let mut needs = PetNeeds::new();
+        needs.update(10_000);
+        needs.update(0);
+        assert_eq!(needs.average(), NEED_MAX);
+
+        needs.update(1200);
+        assert_eq!(needs.level(Need::Hygiene), NEED_MAX - 1);
+    }
+
+    #[test]
+    fn satisfy_and_most_urgent() {
+        let mut needs = PetNeeds::new();
+        needs.update(0);
+        needs.update(50 * 600);
+        assert_eq!(needs.level(Need::Happiness), 67);
+        assert_eq!(needs.most_urgent(60), Some(Need::Hunger));
+        assert_eq!(needs.most_urgent(50), None);
+
+        needs.satisfy(Need::Hunger, 200);
+        assert_eq!(needs.level(Need::Hunger), NEED_MAX);
+        assert_eq!(needs.most_urgent(70), Some(Need::Happiness));
+        assert_eq!(needs.most_urgent(60), None);
+    }
+
+    #[test]
+    fn care_marks_the_pet_dirty() {
+        let mut pet = Pet::new();
+        assert!(!pet.take_dirty());
+
+        pet.care(Need::Hunger, 10);
+        assert!(pet.take_dirty());
+        assert!(!pet.take_dirty());
+    }
+
+    #[test]
+    fn care_sum_survives_a_long_final_stage() {
+        let mut pet = Pet::new();
+        // ~600 days at full care overflows 32 bits
+        pet.care_seconds = 600 * 24 * 3600;
+        pet.care_sum = NEED_MAX as u64 * pet.care_seconds as u64;
+        assert_eq!(pet.care_quality(), NEED_MAX);
+
+        let mut data = [0; SAVE_BUFFER_SIZE];
+        let mut writer = SaveWriter::new(&mut data);
+        pet.save(&mut writer);
+        let length = writer.len();
+
+        let restored = Pet::restore(&mut SaveReader::new(&data[0..length])).unwrap();
+        assert_eq!(restored.care_sum, pet.care_sum);
+        assert_eq!(restored.care_quality(), NEED_MAX);
+    }
+}
@@ -0,0 +1,37 @@
+/*!
+ * Reset counters per cause since power on, kept by the device outside of the save so units that
+ * keep rebooting (watchdog or brownout) can be spotted from the debug overlay or over usb.
+ */
+
+use pawdevicetraits::ResetCause;
+use pawdevicetraits::RESET_CAUSE_COUNT;
+
+pub struct ResetStats {
+    counts: [u16; RESET_CAUSE_COUNT],
+    last: Option<ResetCause>,
+}
+
+impl ResetStats {
+    pub fn new() -> Self {
+        Self {
+            counts: [0; RESET_CAUSE_COUNT],
+            last: None,
+        }
+    }
+
+    // `last` is the reset that started this boot, already included in the counts
+    pub fn from_counts(counts: [u16; RESET_CAUSE_COUNT], last: ResetCause) -> Self {
+        Self {
+            counts,
+            last: Some(last),
+        }
+    }
+
+    pub fn count(&self, cause: ResetCause) -> u16 {
+        return self.counts[cause as usize];
+    }
+
+    pub fn last(&self) -> Option<ResetCause> {
+        return self.last;
+    }
+}
@@ -0,0 +1,83 @@
+/*!
+ * Little endian field reader/writer for the save file, the flash layer handles
+ * the header, crc and wear leveling. Values are written in a fixed order per version.
+ */
+
+// 1 - settings, pet
+pub const SAVE_VERSION: u8 = 1;
+pub const SAVE_BUFFER_SIZE: usize = 256;
+
+// rtc time 0 is a valid timestamp, use the max value for an unset time
+const NONE_TIME: u32 = u32::MAX;
+
+pub struct SaveWriter<'a> {
+    data: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> SaveWriter<'a> {
+    pub fn new(data: &'a mut [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.offset;
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.data[self.offset] = value;
+        self.offset += 1;
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.data[self.offset..self.offset + 2].copy_from_slice(&value.to_le_bytes());
+        self.offset += 2;
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.data[self.offset..self.offset + 4].copy_from_slice(&value.to_le_bytes());
+        self.offset += 4;
+    }
+
+    pub fn put_time(&mut self, value: Option<u32>) {
+        self.put_u32(value.unwrap_or(NONE_TIME));
+    }
+}
+
+pub struct SaveReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+// reads past the end of the data return None, a truncated save is treated as missing
+impl<'a> SaveReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn get_u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.offset)?;
+        self.offset += 1;
+        return Some(value);
+    }
+
+    pub fn get_u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.offset..self.offset + 2)?;
+        self.offset += 2;
+        return Some(u16::from_le_bytes(bytes.try_into().unwrap()));
+    }
+
+    pub fn get_u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.offset..self.offset + 4)?;
+        self.offset += 4;
+        return Some(u32::from_le_bytes(bytes.try_into().unwrap()));
+    }
+
+    pub fn get_time(&mut self) -> Option<Option<u32>> {
+        let value = self.get_u32()?;
+        if value == NONE_TIME {
+            return Some(None);
+        }
+        return Some(Some(value));
+    }
+}
@@ -0,0 +1,460 @@
+/*!
+ * Retained scene of sprites, text and primitives. Nodes keep their position, z-order and
+ * visibility between frames, every change marks the area the node covered before and after
+ * as dirty and `draw` only clears and repaints the dirty areas.
+ *
+ * Nodes are drawn lowest z first, equal z in the order they were added. Combined with the
+ * Sharp display only sending changed lines a frame where nothing moved costs almost nothing.
+ */
+
+use embedded_graphics::image::Image;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::Text;
+use heapless::String;
+use heapless::Vec;
+use pawdevicetraits::DisplayDevice as Display;
+
+use crate::image::{PawAnimation, PawImage};
+
+pub const SCENE_NODES: usize = 12;
+pub const SCENE_TEXT_SIZE: usize = 24;
+const DIRTY_AREAS: usize = 6;
+const SCREEN: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(64, 64));
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NodeId(u8);
+
+pub enum NodeKind {
+    Image(PawImage),
+    Animation(PawAnimation),
+    Text(String<SCENE_TEXT_SIZE>, MonoTextStyle<'static, BinaryColor>),
+    // primitives are relative to the node position
+    Rect(Rectangle, PrimitiveStyle<BinaryColor>),
+    Line(Line, PrimitiveStyle<BinaryColor>),
+}
+
+struct Node {
+    kind: NodeKind,
+    position: Point,
+    z: i8,
+    visible: bool,
+    bounds: Rectangle, // screen area covered at the last change
+}
+
+impl Node {
+    fn area(&self) -> Rectangle {
+        match &self.kind {
+            NodeKind::Image(image) => return Rectangle::new(self.position, image.size()),
+            NodeKind::Animation(animation) => {
+                return Rectangle::new(self.position, animation.image().size())
+            }
+            NodeKind::Text(text, style) => {
+                return Text::new(text, self.position, *style).bounding_box()
+            }
+            NodeKind::Rect(rect, style) => {
+                return rect.translate(self.position).into_styled(*style).bounding_box()
+            }
+            NodeKind::Line(line, style) => {
+                return line.translate(self.position).into_styled(*style).bounding_box()
+            }
+        }
+    }
+
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) {
+        match &self.kind {
+            NodeKind::Image(image) => {
+                Image::new(image, self.position).draw(target).ok();
+            }
+            NodeKind::Animation(animation) => {
+                Image::new(animation.image(), self.position).draw(target).ok();
+            }
+            NodeKind::Text(text, style) => {
+                Text::new(text, self.position, *style).draw(target).ok();
+            }
+            NodeKind::Rect(rect, style) => {
+                rect.translate(self.position).into_styled(*style).draw(target).ok();
+            }
+            NodeKind::Line(line, style) => {
+                line.translate(self.position).into_styled(*style).draw(target).ok();
+            }
+        }
+    }
+}
+
+fn is_empty(area: &Rectangle) -> bool {
+    return area.size.width == 0 || area.size.height == 0;
+}
+
+// smallest rectangle containing both
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let a_end = a.top_left + a.size;
+    let b_end = b.top_left + b.size;
+    let top_left = a.top_left.component_min(b.top_left);
+    let end = a_end.component_max(b_end);
+    return Rectangle::new(top_left, Size::new((end.x - top_left.x) as u32, (end.y - top_left.y) as u32));
+}
+
+pub struct Scene {
+    nodes: Vec<Node, SCENE_NODES>,
+    dirty: Vec<Rectangle, DIRTY_AREAS>,
+    background: BinaryColor,
+}
+
+impl Scene {
+    // the first draw repaints the whole screen
+    pub fn new() -> Self {
+        let mut scene = Self {
+            nodes: Vec::new(),
+            dirty: Vec::new(),
+            background: BinaryColor::Off,
+        };
+        scene.invalidate_all();
+        return scene;
+    }
+
+    pub fn set_background(&mut self, color: BinaryColor) {
+        self.background = color;
+        self.invalidate_all();
+    }
+
+    // None when the scene is full
+    pub fn add(&mut self, kind: NodeKind, position: Point, z: i8) -> Option<NodeId> {
+        let id = NodeId(self.nodes.len() as u8);
+        let mut node = Node {
+            kind,
+            position,
+            z,
+            visible: true,
+            bounds: Rectangle::zero(),
+        };
+        node.bounds = node.area();
+
+        let bounds = node.bounds;
+        self.nodes.push(node).ok()?;
+        self.invalidate(bounds);
+        return Some(id);
+    }
+
+    pub fn add_image(&mut self, image: PawImage, position: Point, z: i8) -> Option<NodeId> {
+        return self.add(NodeKind::Image(image), position, z);
+    }
+
+    pub fn add_animation(&mut self, animation: PawAnimation, position: Point, z: i8) -> Option<NodeId> {
+        return self.add(NodeKind::Animation(animation), position, z);
+    }
+
+    // text longer than SCENE_TEXT_SIZE is cut
+    pub fn add_text(
+        &mut self,
+        text: &str,
+        style: MonoTextStyle<'static, BinaryColor>,
+        position: Point,
+        z: i8,
+    ) -> Option<NodeId> {
+        return self.add(NodeKind::Text(Self::to_string(text), style), position, z);
+    }
+
+    /**
+     * Marks an area to be cleared and repainted on the next draw, for anything drawn over the
+     * scene from outside of it.
+     */
+    pub fn invalidate(&mut self, area: Rectangle) {
+        let mut area = area.intersection(&SCREEN);
+        if is_empty(&area) {
+            return;
+        }
+
+        // grow the area over every dirty area it touches, they are repainted once
+        let mut i = 0;
+        while i < self.dirty.len() {
+            if is_empty(&self.dirty[i].intersection(&area)) {
+                i += 1;
+                continue;
+            }
+            area = union(&area, &self.dirty.swap_remove(i));
+            i = 0;
+        }
+
+        if let Err(area) = self.dirty.push(area) {
+            // out of slots, merged into the first area
+            self.dirty[0] = union(&self.dirty[0], &area);
+        }
+    }
+
+    pub fn invalidate_all(&mut self) {
+        self.dirty.clear();
+        self.dirty.push(SCREEN).ok();
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        return !self.dirty.is_empty();
+    }
+
+    // repaints the area of a node after a change
+    fn touch(&mut self, id: NodeId) {
+        let node = &mut self.nodes[id.0 as usize];
+        let old = node.bounds;
+        node.bounds = node.area();
+        let (new, visible) = (node.bounds, node.visible);
+
+        self.invalidate(old);
+        if visible {
+            self.invalidate(new);
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        return self.nodes.get_mut(id.0 as usize);
+    }
+
+    pub fn set_position(&mut self, id: NodeId, position: Point) {
+        if let Some(node) = self.node_mut(id) {
+            if node.position != position {
+                node.position = position;
+                self.touch(id);
+            }
+        }
+    }
+
+    pub fn position(&self, id: NodeId) -> Point {
+        return self.nodes.get(id.0 as usize).map(|n| n.position).unwrap_or_default();
+    }
+
+    pub fn set_visible(&mut self, id: NodeId, visible: bool) {
+        if let Some(node) = self.node_mut(id) {
+            if node.visible != visible {
+                node.visible = visible;
+                self.touch(id);
+            }
+        }
+    }
+
+    pub fn set_z(&mut self, id: NodeId, z: i8) {
+        if let Some(node) = self.node_mut(id) {
+            if node.z != z {
+                node.z = z;
+                self.touch(id);
+            }
+        }
+    }
+
+    pub fn set_text(&mut self, id: NodeId, text: &str) {
+        if let Some(Node {
+            kind: NodeKind::Text(current, _),
+            ..
+        }) = self.node_mut(id)
+        {
+            if current.as_str() != text {
+                *current = Self::to_string(text);
+                self.touch(id);
+            }
+        }
+    }
+
+    // changes to the kind (frame, colors, image data) through the closure are repainted
+    pub fn update(&mut self, id: NodeId, f: impl FnOnce(&mut NodeKind)) {
+        if let Some(node) = self.node_mut(id) {
+            f(&mut node.kind);
+            self.touch(id);
+        }
+    }
+
+    pub fn update_image(&mut self, id: NodeId, f: impl FnOnce(&mut PawImage)) {
+        self.update(id, |kind| {
+            if let NodeKind::Image(image) = kind {
+                f(image);
+            }
+        });
+    }
+
+    pub fn update_animation(&mut self, id: NodeId, f: impl FnOnce(&mut PawAnimation)) {
+        self.update(id, |kind| {
+            if let NodeKind::Animation(animation) = kind {
+                f(animation);
+            }
+        });
+    }
+
+    // advances every animation, only the ones that changed frame are repainted
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        for i in 0..self.nodes.len() {
+            if let NodeKind::Animation(animation) = &mut self.nodes[i].kind {
+                let frame = animation.frame();
+                animation.advance(elapsed_ms);
+                if animation.frame() != frame && self.nodes[i].visible {
+                    self.touch(NodeId(i as u8));
+                }
+            }
+        }
+    }
+
+    // node indices lowest z first, stable for equal z
+    fn draw_order(&self) -> Vec<u8, SCENE_NODES> {
+        let mut order: Vec<u8, SCENE_NODES> = Vec::new();
+        for i in 0..self.nodes.len() {
+            let z = self.nodes[i].z;
+            let at = order
+                .iter()
+                .position(|j| self.nodes[*j as usize].z > z)
+                .unwrap_or(order.len());
+            order.insert(at, i as u8).ok();
+        }
+        return order;
+    }
+
+    // returns false if nothing changed since the last draw
+    pub fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) -> bool {
+        if self.dirty.is_empty() {
+            return false;
+        }
+
+        let order = self.draw_order();
+        let background = self.background == BinaryColor::On;
+
+        for area in self.dirty.iter() {
+            display.fill_rect(
+                area.top_left.x as u8,
+                area.top_left.y as u8,
+                area.size.width as u8,
+                area.size.height as u8,
+                background,
+            );
+
+            let mut clipped = display.clipped(area);
+            for i in order.iter() {
+                let node = &self.nodes[*i as usize];
+                if node.visible && !is_empty(&node.bounds.intersection(area)) {
+                    node.draw(&mut clipped);
+                }
+            }
+        }
+
+        self.dirty.clear();
+        return true;
+    }
+
+    fn to_string(text: &str) -> String<SCENE_TEXT_SIZE> {
+        let mut s = String::new();
+        for c in text.chars() {
+            if s.push(c).is_err() {
+                break;
+            }
+        }
+        return s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::Grid;
+    use embedded_graphics::mono_font::ascii::FONT_5X8;
+
+    fn filled(size: u32) -> NodeKind {
+        return NodeKind::Rect(
+            Rectangle::new(Point::zero(), Size::new(size, size)),
+            PrimitiveStyle::with_fill(BinaryColor::On),
+        );
+    }
+
+    #[test]
+    fn unchanged_scene_draws_nothing() {
+        let mut scene = Scene::new();
+        let mut grid = Grid::new();
+        scene.add(filled(4), Point::new(10, 10), 0).unwrap();
+
+        assert!(scene.draw(&mut grid));
+        assert!(grid.is_on(10, 10));
+
+        grid.writes = 0;
+        assert!(!scene.draw(&mut grid));
+        assert_eq!(grid.writes, 0);
+    }
+
+    #[test]
+    fn move_repaints_old_and_new_area_only() {
+        let mut scene = Scene::new();
+        let mut grid = Grid::new();
+        let block = scene.add(filled(4), Point::new(10, 10), 0).unwrap();
+        scene.add(filled(4), Point::new(40, 40), 0).unwrap();
+        scene.draw(&mut grid);
+
+        scene.set_position(block, Point::new(20, 10));
+        assert_eq!(scene.dirty.len(), 2);
+
+        grid.writes = 0;
+        scene.draw(&mut grid);
+        // cleared and repainted, 2 areas of 4x4
+        assert_eq!(grid.writes, 2 * 16 + 16);
+        assert!(!grid.is_on(10, 10));
+        assert!(grid.is_on(20, 10));
+        assert!(grid.is_on(40, 40));
+    }
+
+    #[test]
+    fn z_order_and_visibility() {
+        let mut scene = Scene::new();
+        let mut grid = Grid::new();
+
+        let cover = NodeKind::Rect(
+            Rectangle::new(Point::zero(), Size::new(8, 8)),
+            PrimitiveStyle::with_fill(BinaryColor::Off),
+        );
+        let top = scene.add(cover, Point::new(0, 0), 1).unwrap();
+        scene.add(filled(8), Point::new(0, 0), 0).unwrap();
+        scene.draw(&mut grid);
+        assert!(!grid.is_on(4, 4));
+
+        scene.set_z(top, -1);
+        scene.draw(&mut grid);
+        assert!(grid.is_on(4, 4));
+
+        scene.set_z(top, 1);
+        scene.set_visible(top, false);
+        scene.draw(&mut grid);
+        assert!(grid.is_on(4, 4));
+    }
+
+    #[test]
+    fn overlapping_areas_are_merged() {
+        let mut scene = Scene::new();
+        let mut grid = Grid::new();
+        scene.draw(&mut grid);
+
+        scene.invalidate(Rectangle::new(Point::new(0, 0), Size::new(10, 10)));
+        scene.invalidate(Rectangle::new(Point::new(30, 30), Size::new(4, 4)));
+        scene.invalidate(Rectangle::new(Point::new(8, 8), Size::new(4, 4)));
+        assert_eq!(scene.dirty.len(), 2);
+        assert!(scene.dirty.contains(&Rectangle::new(Point::new(0, 0), Size::new(12, 12))));
+
+        // off screen parts are dropped
+        scene.invalidate(Rectangle::new(Point::new(60, -4), Size::new(10, 10)));
+        assert!(scene.dirty.contains(&Rectangle::new(Point::new(60, 0), Size::new(4, 6))));
+
+        for i in 0..DIRTY_AREAS as i32 {
+            scene.invalidate(Rectangle::new(Point::new(i * 9, 50), Size::new(2, 2)));
+        }
+        assert_eq!(scene.dirty.len(), DIRTY_AREAS);
+    }
+
+    #[test]
+    fn text_changes() {
+        let mut scene = Scene::new();
+        let mut grid = Grid::new();
+        let style = MonoTextStyle::new(&FONT_5X8, BinaryColor::On);
+        let text = scene.add_text("12:00", style, Point::new(0, 6), 0).unwrap();
+        scene.draw(&mut grid);
+
+        scene.set_text(text, "12:00");
+        assert!(!scene.is_dirty());
+
+        scene.set_text(text, "12:01");
+        assert_eq!(scene.dirty.len(), 1);
+        assert_eq!(scene.dirty[0].size, Size::new(25, 8));
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+ * Device settings, saved to flash along with the pet and applied by the runner at boot
+ * and whenever a setting changes.
+ */
+
+use crate::save::SaveReader;
+use crate::save::SaveWriter;
+use pawdevicetraits::VOLUME_MAX;
+
+// choices offered by the settings screen
+pub const SLEEP_TIMEOUT_OPTIONS: [u16; 5] = [15, 30, 60, 120, 300];
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+pub const HOURS_PER_DAY: u8 = 24;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BatteryChemistry {
+    Alkaline = 0,
+    NiMH = 1,
+}
+
+impl BatteryChemistry {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(BatteryChemistry::Alkaline),
+            1 => Some(BatteryChemistry::NiMH),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatteryChemistry::Alkaline => "Alk",
+            BatteryChemistry::NiMH => "NiMH",
+        }
+    }
+}
+
+pub struct Settings {
+    pub sleep_timeout_sec: u16,
+    pub sound: bool,
+    pub volume: u8, // 1 to VOLUME_MAX
+    pub rotation: u8, // quarter turns
+    pub battery: BatteryChemistry,
+    pub debug_overlay: bool,
+    pub alarm_on: bool,
+    pub alarm_minutes: u16, // minutes into the day, kept while the alarm is off
+    pub quiet_on: bool,
+    pub quiet_start: u8, // hour alerts stop, kept while quiet hours are off
+    pub quiet_end: u8, // hour alerts resume
+
+    // runtime only, not saved
+    pub clock: u32, // rtc time, refreshed by the runner every tick
+    pub clock_request: Option<u32>, // new rtc time to be set by the runner
+    changed: bool,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {
+            sleep_timeout_sec: 60,
+            sound: true,
+            volume: VOLUME_MAX,
+            rotation: 0,
+            battery: BatteryChemistry::Alkaline,
+            debug_overlay: false,
+            alarm_on: false,
+            alarm_minutes: 7 * 60,
+            quiet_on: true,
+            quiet_start: 22,
+            quiet_end: 7,
+            clock: 0,
+            clock_request: None,
+            changed: false,
+        }
+    }
+
+    // mark settings to be applied and saved by the runner
+    pub fn set_changed(&mut self) {
+        self.changed = true;
+    }
+
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        return changed;
+    }
+
+    // seconds into the day the alarm rings at
+    pub fn alarm(&self) -> Option<u32> {
+        if !self.alarm_on {
+            return None;
+        }
+        return Some(self.alarm_minutes as u32 * 60);
+    }
+
+    // start and end hour pet alerts are held for
+    pub fn quiet_hours(&self) -> Option<(u8, u8)> {
+        if !self.quiet_on {
+            return None;
+        }
+        return Some((self.quiet_start, self.quiet_end));
+    }
+
+    pub fn save(&self, writer: &mut SaveWriter) {
+        writer.put_u16(self.sleep_timeout_sec);
+        writer.put_u8(self.sound as u8);
+        writer.put_u8(self.volume);
+        writer.put_u8(self.rotation);
+        writer.put_u8(self.battery as u8);
+        writer.put_u8(self.debug_overlay as u8);
+        writer.put_u8(self.alarm_on as u8);
+        writer.put_u16(self.alarm_minutes);
+        writer.put_u8(self.quiet_on as u8);
+        writer.put_u8(self.quiet_start);
+        writer.put_u8(self.quiet_end);
+    }
+
+    // out of range values fall back to defaults instead of rejecting the whole save
+    pub fn restore(reader: &mut SaveReader) -> Option<Self> {
+        let mut settings = Settings::new();
+
+        let timeout = reader.get_u16()?;
+        if SLEEP_TIMEOUT_OPTIONS.contains(&timeout) {
+            settings.sleep_timeout_sec = timeout;
+        }
+        settings.sound = reader.get_u8()? != 0;
+        let volume = reader.get_u8()?;
+        if (1..=VOLUME_MAX).contains(&volume) {
+            settings.volume = volume;
+        }
+        settings.rotation = reader.get_u8()? % 4;
+        settings.battery = BatteryChemistry::from_u8(reader.get_u8()?).unwrap_or(settings.battery);
+        settings.debug_overlay = reader.get_u8()? != 0;
+        settings.alarm_on = reader.get_u8()? != 0;
+        let alarm_minutes = reader.get_u16()?;
+        if alarm_minutes < MINUTES_PER_DAY {
+            settings.alarm_minutes = alarm_minutes;
+        }
+        settings.quiet_on = reader.get_u8()? != 0;
+        let (start, end) = (reader.get_u8()?, reader.get_u8()?);
+        if start < HOURS_PER_DAY && end < HOURS_PER_DAY {
+            settings.quiet_start = start;
+            settings.quiet_end = end;
+        }
+
+        return Some(settings);
+    }
+}
+
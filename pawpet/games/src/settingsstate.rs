@@ -0,0 +1,334 @@
+/*!
+ * Settings list, Left/Right changes the selected value. P on the clock or the alarm edits the
+ * hours then the minutes, on the quiet hours the start then the end hour, A steps back a field.
+ * Left/Right on the alarm and the quiet hours turns them on and off.
+ * Changes are applied right away, A goes back to the menu.
+ */
+
+use core::fmt::Write;
+use heapless::String;
+
+use pawdevicetraits::*;
+
+use pawdevicetraits::BatteryMonitorDevice as BatteryMonitor;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+use pawdevicetraits::StorageDevice as Storage;
+use crate::FramerateMs;
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use crate::dialog::DialogResult;
+use crate::font::Font;
+use crate::font::TextStyle;
+use crate::pet::Pet;
+use crate::species::Species;
+use crate::settings::BatteryChemistry;
+use crate::settings::Settings;
+use crate::sound::Sfx;
+use crate::sound::Sound;
+use crate::settings::SLEEP_TIMEOUT_OPTIONS;
+use crate::spinner::Spinner;
+use crate::GameState;
+use crate::StateKind;
+use crate::{SECONDS_PER_DAY, SECONDS_PER_HOUR};
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Row {
+    Sleep,
+    Sound,
+    Volume,
+    Rotation,
+    Clock,
+    Alarm,
+    Quiet,
+    Battery,
+    Debug,
+}
+
+const ROWS: [Row; 9] = [
+    Row::Sleep,
+    Row::Sound,
+    Row::Volume,
+    Row::Rotation,
+    Row::Clock,
+    Row::Alarm,
+    Row::Quiet,
+    Row::Battery,
+    Row::Debug,
+];
+
+const ROW_HEIGHT: i32 = 8;
+const FIRST_ROW_Y: i32 = 10; // top of the first row, below the status bar
+const VISIBLE_ROWS: usize = 6;
+const VALUE_X: i32 = 36;
+
+// hours and minutes of the clock or the alarm, start and end hour of the quiet hours
+struct TimeEdit {
+    row: Row,
+    first: Spinner,
+    second: Spinner,
+    second_selected: bool,
+}
+
+pub struct SettingsState {
+    selection: usize,
+    scroll: usize,
+    time_edit: Option<TimeEdit>,
+    values: [String<8>; ROWS.len()], // formatted in tick, draw has no access to the settings
+    font: Font,
+}
+
+impl SettingsState {
+    pub fn new() -> Self {
+        Self {
+            selection: 0,
+            scroll: 0,
+            time_edit: None,
+            values: Default::default(),
+            font: Font::default_font(),
+        }
+    }
+
+    fn change(&mut self, settings: &mut Settings, up: bool) {
+        match ROWS[self.selection] {
+            Row::Sleep => {
+                let index = SLEEP_TIMEOUT_OPTIONS
+                    .iter()
+                    .position(|t| *t == settings.sleep_timeout_sec)
+                    .unwrap_or(0);
+                let index = if up {
+                    (index + 1).min(SLEEP_TIMEOUT_OPTIONS.len() - 1)
+                } else {
+                    index.saturating_sub(1)
+                };
+                settings.sleep_timeout_sec = SLEEP_TIMEOUT_OPTIONS[index];
+            }
+            Row::Sound => settings.sound = !settings.sound,
+            Row::Volume => {
+                settings.volume = if up {
+                    (settings.volume + 1).min(VOLUME_MAX)
+                } else {
+                    (settings.volume - 1).max(1)
+                };
+            }
+            Row::Rotation => {
+                settings.rotation = if up {
+                    (settings.rotation + 1) % 4
+                } else {
+                    (settings.rotation + 3) % 4
+                };
+            }
+            Row::Battery => {
+                settings.battery = match settings.battery {
+                    BatteryChemistry::Alkaline => BatteryChemistry::NiMH,
+                    BatteryChemistry::NiMH => BatteryChemistry::Alkaline,
+                };
+            }
+            Row::Debug => settings.debug_overlay = !settings.debug_overlay,
+            Row::Alarm => settings.alarm_on = !settings.alarm_on,
+            Row::Quiet => settings.quiet_on = !settings.quiet_on,
+            Row::Clock => return,
+        }
+        settings.set_changed();
+    }
+
+    // current value of a time row as its two fields
+    fn time_of(row: Row, settings: &Settings) -> (u32, u32) {
+        let minutes_of_day = match row {
+            Row::Quiet => return (settings.quiet_start as u32, settings.quiet_end as u32),
+            Row::Alarm => settings.alarm_minutes as u32,
+            _ => settings.clock % SECONDS_PER_DAY / 60,
+        };
+        return (minutes_of_day / 60, minutes_of_day % 60);
+    }
+
+    fn time_separator(row: Row) -> &'static str {
+        match row {
+            Row::Quiet => "-",
+            _ => ":",
+        }
+    }
+
+    // returns true while a time is being edited
+    fn tick_time_edit(&mut self, buttons: &mut impl Input, settings: &mut Settings) -> bool {
+        let edit = match &mut self.time_edit {
+            Some(edit) => edit,
+            None => return false,
+        };
+
+        if buttons.is_pressed(Buttons::Left) || buttons.is_pressed(Buttons::Right) {
+            edit.second_selected = !edit.second_selected;
+            return true;
+        }
+
+        let spinner = if edit.second_selected {
+            &mut edit.second
+        } else {
+            &mut edit.first
+        };
+
+        match spinner.tick(buttons) {
+            Some(DialogResult::Ok) if !edit.second_selected => edit.second_selected = true,
+            Some(DialogResult::Ok) => {
+                let first = edit.first.value() as u32;
+                let second = edit.second.value() as u32;
+                match edit.row {
+                    Row::Alarm => {
+                        settings.alarm_minutes = (first * 60 + second) as u16;
+                        settings.alarm_on = true;
+                        settings.set_changed();
+                    }
+                    Row::Quiet => {
+                        settings.quiet_start = first as u8;
+                        settings.quiet_end = second as u8;
+                        settings.quiet_on = true;
+                        settings.set_changed();
+                    }
+                    _ => {
+                        let day_start = settings.clock - settings.clock % SECONDS_PER_DAY;
+                        settings.clock_request = Some(day_start + first * SECONDS_PER_HOUR + second * 60);
+                    }
+                }
+                self.time_edit = None;
+            }
+            Some(DialogResult::Cancel) if edit.second_selected => edit.second_selected = false,
+            Some(DialogResult::Cancel) => self.time_edit = None,
+            None => {}
+        }
+        return true;
+    }
+
+    fn format_values(&mut self, settings: &Settings) {
+        for (i, row) in ROWS.iter().enumerate() {
+            let s = &mut self.values[i];
+            s.clear();
+
+            match row {
+                Row::Sleep => write!(s, "{}s", settings.sleep_timeout_sec).ok(),
+                Row::Sound => write!(s, "{}", if settings.sound { "on" } else { "off" }).ok(),
+                Row::Volume => write!(s, "{}/{}", settings.volume, VOLUME_MAX).ok(),
+                Row::Rotation => write!(s, "{}", settings.rotation as u32 * 90).ok(),
+                Row::Battery => write!(s, "{}", settings.battery.name()).ok(),
+                Row::Debug => write!(s, "{}", if settings.debug_overlay { "on" } else { "off" }).ok(),
+                Row::Alarm if !settings.alarm_on => write!(s, "off").ok(),
+                Row::Quiet if !settings.quiet_on => write!(s, "off").ok(),
+                Row::Clock | Row::Alarm | Row::Quiet => {
+                    let (first, second) = SettingsState::time_of(*row, settings);
+                    let separator = SettingsState::time_separator(*row);
+                    write!(s, "{:02}{}{:02}", first, separator, second).ok()
+                }
+            };
+        }
+    }
+
+    fn label(row: Row) -> &'static str {
+        match row {
+            Row::Sleep => "Sleep",
+            Row::Sound => "Sound",
+            Row::Volume => "Volume",
+            Row::Rotation => "Rotate",
+            Row::Clock => "Clock",
+            Row::Alarm => "Alarm",
+            Row::Quiet => "Quiet",
+            Row::Battery => "Batt",
+            Row::Debug => "Debug",
+        }
+    }
+}
+
+impl GameState for SettingsState {
+    fn tick(
+        &mut self,
+        buttons: &mut impl Input,
+        sound: &mut Sound,
+        _battery: &mut impl BatteryMonitor,
+        _pet: &mut Pet,
+        settings: &mut Settings,
+    ) -> StateKind {
+        let keys = [Buttons::A, Buttons::P, Buttons::Up, Buttons::Down, Buttons::Left, Buttons::Right];
+        if keys.into_iter().any(|b| buttons.is_pressed(b)) {
+            sound.play(Sfx::Click);
+        }
+
+        if !self.tick_time_edit(buttons, settings) {
+            if buttons.is_pressed(Buttons::A) {
+                return StateKind::Menu;
+            } else if buttons.is_pressed(Buttons::Up) {
+                self.selection = self.selection.saturating_sub(1);
+            } else if buttons.is_pressed(Buttons::Down) {
+                self.selection = (self.selection + 1).min(ROWS.len() - 1);
+            } else if buttons.is_pressed(Buttons::Left) {
+                self.change(settings, false);
+            } else if buttons.is_pressed(Buttons::Right) {
+                self.change(settings, true);
+            } else if buttons.is_pressed(Buttons::P)
+                && matches!(ROWS[self.selection], Row::Clock | Row::Alarm | Row::Quiet)
+            {
+                let row = ROWS[self.selection];
+                let (first, second) = SettingsState::time_of(row, settings);
+                let second_max = if row == Row::Quiet { 23 } else { 59 };
+
+                let mut edit = TimeEdit {
+                    row,
+                    first: Spinner::new(0, 23, first as i32).wrapping().digits(2),
+                    second: Spinner::new(0, second_max, second as i32).wrapping().digits(2),
+                    second_selected: false,
+                };
+                edit.first.set_font(self.font);
+                edit.second.set_font(self.font);
+                self.time_edit = Some(edit);
+            }
+
+            if self.selection < self.scroll {
+                self.scroll = self.selection;
+            } else if self.selection >= self.scroll + VISIBLE_ROWS {
+                self.scroll = self.selection + 1 - VISIBLE_ROWS;
+            }
+        }
+
+        self.format_values(settings);
+
+        return StateKind::Settings;
+    }
+
+    fn get_fps() -> FramerateMs {
+        return FramerateMs::Fps15;
+    }
+
+    fn load(&mut self, storage: &mut impl Storage, _pet: &Pet, _species: &Species) {
+        self.font = Font::load(storage);
+    }
+
+    fn draw(&mut self, display: &mut (impl Display + DrawTarget<Color = BinaryColor>)) {
+        display.clear(BinaryColor::Off).ok();
+
+        let last = (self.scroll + VISIBLE_ROWS).min(ROWS.len());
+        for i in self.scroll..last {
+            let y = ROW_HEIGHT * (i - self.scroll) as i32 + FIRST_ROW_Y;
+            let selected = self.selection == i;
+
+            // while editing a time only the value is highlighted
+            let label_style = if selected && self.time_edit.is_none() {
+                TextStyle::HIGHLIGHT
+            } else {
+                TextStyle::NORMAL
+            };
+            self.font
+                .draw(display, SettingsState::label(ROWS[i]), 0, y, label_style);
+
+            match &self.time_edit {
+                Some(edit) if ROWS[i] == edit.row => {
+                    let next = edit.first.draw(display, VALUE_X, y, !edit.second_selected);
+                    let separator = SettingsState::time_separator(edit.row);
+                    let next = self.font.draw(display, separator, next, y, TextStyle::NORMAL);
+                    edit.second.draw(display, next, y, edit.second_selected);
+                }
+                _ => {
+                    self.font
+                        .draw(display, &self.values[i], VALUE_X, y, TextStyle::NORMAL);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+/*!
+ * Everything that uses the beeper goes through here. There is a single channel, a sound effect
+ * takes it over from the music and the music picks up where it left off once the effect is done.
+ *
+ *  sound.play(Sfx::Eat);
+ *
+ * Effects are short frequency sweeps. A playing effect is only interrupted by one of the same or
+ * a higher priority, so a click can't cut off an alert. Muted (sound setting off) nothing
+ * plays and the music keeps time silently. The volume setting applies to effects and music
+ * alike, a change is heard on the tone that is playing.
+ *
+ * States queue effects during their tick, the runner calls update() once per frame.
+ */
+
+use pawdevicetraits::SysTimerDevice as SysTimer;
+use pawdevicetraits::ToneDevice as Tone;
+use pawdevicetraits::VOLUME_MAX;
+
+use crate::music::Sequencer;
+use crate::music::Song;
+
+// start hz, end hz, ms. 0 hz is silence
+type Sweep = (u32, u32, u32);
+
+static CLICK: [Sweep; 1] = [(1800, 1800, 20)];
+static EAT: [Sweep; 3] = [(300, 600, 60), (0, 0, 40), (300, 600, 60)];
+static HAPPY: [Sweep; 2] = [(800, 1600, 100), (1200, 2400, 120)];
+static ALERT: [Sweep; 4] = [(880, 880, 80), (0, 0, 60), (880, 880, 80), (1319, 1319, 160)];
+static ERROR: [Sweep; 3] = [(440, 440, 80), (0, 0, 40), (330, 200, 200)];
+
+// frequency steps of a sweep played while blocking
+const BLOCKING_STEP_MS: u32 = 10;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Sfx {
+    Click, // button feedback
+    Eat,
+    Happy,
+    Alert, // pet needs attention, low battery
+    Error, // action not available
+}
+
+impl Sfx {
+    fn sweeps(&self) -> &'static [Sweep] {
+        match self {
+            Sfx::Click => &CLICK,
+            Sfx::Eat => &EAT,
+            Sfx::Happy => &HAPPY,
+            Sfx::Alert => &ALERT,
+            Sfx::Error => &ERROR,
+        }
+    }
+
+    // music is below every effect
+    fn priority(&self) -> u8 {
+        match self {
+            Sfx::Click => 1,
+            Sfx::Eat | Sfx::Happy | Sfx::Error => 2,
+            Sfx::Alert => 3,
+        }
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        return self.sweeps().iter().map(|(_, _, ms)| ms).sum();
+    }
+
+    // None once the effect is over
+    fn freq_at(&self, ms: u32) -> Option<u32> {
+        let mut start = 0;
+        for (from, to, length) in self.sweeps() {
+            if ms < start + length {
+                let t = (ms - start) as i32;
+                let freq = *from as i32 + (*to as i32 - *from as i32) * t / *length as i32;
+                return Some(freq as u32);
+            }
+            start += length;
+        }
+        return None;
+    }
+
+    // for wake events that sound and go back to sleep without running the frame loop
+    pub fn play_blocking(&self, tone: &impl Tone, timer: &mut impl SysTimer) {
+        let mut ms = 0;
+        while let Some(freq) = self.freq_at(ms) {
+            if freq == 0 {
+                tone.no_tone();
+            } else {
+                tone.tone(freq);
+            }
+            timer.delay_ms(BLOCKING_STEP_MS);
+            ms += BLOCKING_STEP_MS;
+        }
+        tone.no_tone();
+    }
+}
+
+pub struct Sound {
+    music: Sequencer,
+    effect: Option<Sfx>,
+    effect_ms: u32,
+    effect_started: bool, // first update plays the start of the effect
+    muted: bool,
+    volume: u8,
+    freq: u32, // last frequency sent to the tone device, 0 silent
+}
+
+impl Sound {
+    pub const fn new() -> Self {
+        Self {
+            music: Sequencer::new(),
+            effect: None,
+            effect_ms: 0,
+            effect_started: false,
+            muted: true, // until settings are applied
+            volume: VOLUME_MAX,
+            freq: 0,
+        }
+    }
+
+    pub fn set_muted(&mut self, tone: &impl Tone, muted: bool) {
+        tone.set_muted(muted);
+        self.muted = muted;
+        if muted {
+            self.effect = None;
+            self.set_freq(tone, 0);
+        }
+    }
+
+    pub fn set_volume(&mut self, tone: &impl Tone, volume: u8) {
+        if volume == self.volume {
+            return;
+        }
+        self.volume = volume;
+        tone.set_volume(volume);
+
+        // the device only takes the volume with the next tone
+        if self.freq != 0 {
+            tone.tone(self.freq);
+        }
+    }
+
+    pub fn play(&mut self, sfx: Sfx) {
+        if self.muted {
+            return;
+        }
+        if let Some(playing) = self.effect {
+            if playing.priority() > sfx.priority() {
+                return;
+            }
+        }
+
+        self.effect = Some(sfx);
+        self.effect_ms = 0;
+        self.effect_started = false;
+    }
+
+    pub fn play_music(&mut self, song: Song) {
+        self.music.play(song);
+    }
+
+    pub fn stop_music(&mut self) {
+        self.music.stop();
+    }
+
+    pub fn is_busy(&self) -> bool {
+        return self.effect.is_some() || self.music.is_playing();
+    }
+
+    // silence right away, before sleeping
+    pub fn stop(&mut self, tone: &impl Tone) {
+        self.effect = None;
+        self.music.stop();
+        self.set_freq(tone, 0);
+    }
+
+    fn set_freq(&mut self, tone: &impl Tone, freq: u32) {
+        if freq == self.freq {
+            return;
+        }
+        self.freq = freq;
+        if freq == 0 {
+            tone.no_tone();
+        } else {
+            tone.tone(freq);
+        }
+    }
+
+    pub fn update(&mut self, tone: &impl Tone, elapsed_ms: u32) {
+        let mut freq = None;
+        if let Some(sfx) = self.effect {
+            if self.effect_started {
+                self.effect_ms += elapsed_ms;
+            }
+            self.effect_started = true;
+
+            freq = sfx.freq_at(self.effect_ms);
+            if freq.is_none() {
+                self.effect = None;
+            }
+        }
+
+        // music is paused under an effect
+        let freq = match freq {
+            Some(freq) => freq,
+            None => self.music.update(elapsed_ms),
+        };
+
+        if self.muted {
+            self.set_freq(tone, 0);
+        } else {
+            self.set_freq(tone, freq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::Cell;
+    use pawdevicetraits::TONE_NOTES;
+
+    struct Beeper {
+        freq: Cell<u32>,
+        muted: Cell<bool>,
+        volume: Cell<u8>,
+        tone_volume: Cell<u8>, // volume the current tone was started with
+    }
+
+    impl Tone for Beeper {
+        fn tone(&self, freq: u32) {
+            self.freq.set(freq);
+            self.tone_volume.set(self.volume.get());
+        }
+
+        fn no_tone(&self) {
+            self.freq.set(0);
+        }
+
+        fn set_muted(&self, muted: bool) {
+            self.muted.set(muted);
+        }
+
+        fn set_volume(&self, volume: u8) {
+            self.volume.set(volume);
+        }
+    }
+
+    fn beeper() -> Beeper {
+        return Beeper {
+            freq: Cell::new(0),
+            muted: Cell::new(false),
+            volume: Cell::new(VOLUME_MAX),
+            tone_volume: Cell::new(0),
+        };
+    }
+
+    // one long A4, 120 bpm
+    static SONG: [u8; 10] = [b'P', b'S', b'N', b'G', 1, 120, 2, 0, 36, 240];
+
+    #[test]
+    fn sweeps() {
+        assert_eq!(Sfx::Eat.freq_at(0), Some(300));
+        assert_eq!(Sfx::Eat.freq_at(30), Some(450));
+        assert_eq!(Sfx::Eat.freq_at(70), Some(0));
+        assert_eq!(Sfx::Eat.freq_at(Sfx::Eat.duration_ms()), None);
+        assert_eq!(Sfx::Error.freq_at(220), Some(265));
+    }
+
+    #[test]
+    fn effect_interrupts_music_and_restores_it() {
+        let tone = beeper();
+        let mut sound = Sound::new();
+        sound.set_muted(&tone, false);
+        sound.play_music(Song::parse(&SONG).unwrap());
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), TONE_NOTES[36]);
+
+        // starts on the next update, from the beginning of the sweep
+        sound.play(Sfx::Alert);
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), 880);
+
+        // lower priority is dropped, the same priority replaces
+        sound.play(Sfx::Click);
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), 880);
+        sound.play(Sfx::Alert);
+        sound.update(&tone, 0);
+        assert_eq!(tone.freq.get(), 880);
+
+        for _ in 0..Sfx::Alert.duration_ms() / 33 {
+            sound.update(&tone, 33);
+        }
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), TONE_NOTES[36]);
+        assert!(sound.is_busy());
+
+        sound.stop(&tone);
+        assert_eq!(tone.freq.get(), 0);
+        assert!(!sound.is_busy());
+    }
+
+    #[test]
+    fn muted_plays_nothing() {
+        let tone = beeper();
+        let mut sound = Sound::new();
+        sound.set_muted(&tone, false);
+        sound.play(Sfx::Happy);
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), 800);
+
+        sound.set_muted(&tone, true);
+        assert!(tone.muted.get());
+        assert_eq!(tone.freq.get(), 0);
+
+        sound.play(Sfx::Alert);
+        sound.play_music(Song::parse(&SONG).unwrap());
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), 0);
+
+        // the music kept time while muted
+        sound.set_muted(&tone, false);
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), TONE_NOTES[36]);
+    }
+
+    #[test]
+    fn volume_applies_to_music_and_effects() {
+        let tone = beeper();
+        let mut sound = Sound::new();
+        sound.set_muted(&tone, false);
+        sound.play_music(Song::parse(&SONG).unwrap());
+        sound.update(&tone, 33);
+        assert_eq!(tone.tone_volume.get(), VOLUME_MAX);
+
+        // the playing note is restarted at the new volume
+        sound.set_volume(&tone, 1);
+        assert_eq!(tone.freq.get(), TONE_NOTES[36]);
+        assert_eq!(tone.tone_volume.get(), 1);
+
+        sound.play(Sfx::Happy);
+        sound.update(&tone, 33);
+        assert_eq!(tone.freq.get(), 800);
+        assert_eq!(tone.tone_volume.get(), 1);
+    }
+}
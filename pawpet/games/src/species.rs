@@ -0,0 +1,374 @@
+/*!
+ * Species/evolution definition, stored in flash as a file so new creatures can be added
+ * by pushing assets without a firmware rebuild. Compiled and validated on host by png2c/species.py
+ *
+ * little endian, fixed size records
+ * [header 8 bytes]
+ *      magic "PEVO", version u8, stage count u8, branch count u8, reserved u8
+ * [stage 32 bytes] * stage count
+ *      sprite key [u8; 16] (null padded)
+ *      duration u16 - minutes spent in stage before evolving, lifespan for a final stage (0 lives forever)
+ *      first branch u8, branch count u8
+ *      animation tags [start u8, end u8, ticks per frame u8] * 4 (idle, walk, sit, sleep)
+ *          ticks are 1/15 s, frame durations in the sprite override them
+ * [branch 4 bytes] * branch count
+ *      min care u8 - average care quality (0-100) over the stage needed to take this branch
+ *      next stage u8, reserved u16
+ *
+ * branches of a stage are checked in order, first one with a met care requirement is taken
+ */
+
+use crate::FramerateMs;
+
+pub const SPECIES_FILE: &str = "species";
+
+const MAGIC: &[u8; 4] = b"PEVO";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 8;
+const STAGE_SIZE: usize = 32;
+const BRANCH_SIZE: usize = 4;
+const KEY_SIZE: usize = 16;
+pub const TAG_COUNT: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum SpeciesError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion,
+    NoStages,
+    BadKey,
+    BadBranch,
+    BadTag,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AnimTag {
+    Idle = 0,
+    Walk = 1,
+    Sit = 2,
+    Sleep = 3,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TagRange {
+    pub start: u8,
+    pub end: u8,
+    pub ticks_per_frame: u8,
+}
+
+// species files count frame time in ticks of the 15 fps home screen
+pub const SPECIES_TICK_MS: u16 = FramerateMs::Fps15 as u16;
+
+impl TagRange {
+    pub fn frame_ms(&self) -> u16 {
+        return self.ticks_per_frame as u16 * SPECIES_TICK_MS;
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Stage {
+    pub key: &'static str,
+    pub duration_min: u16,
+    first_branch: u8,
+    branch_count: u8,
+    tags: [TagRange; TAG_COUNT],
+}
+
+impl Stage {
+    // falls back to the idle animation if the tag is not defined (empty range)
+    pub fn tag(&self, tag: AnimTag) -> TagRange {
+        let range = self.tags[tag as usize];
+        if range.end <= range.start {
+            return self.tags[AnimTag::Idle as usize];
+        }
+        return range;
+    }
+
+    pub fn is_final(&self) -> bool {
+        return self.branch_count == 0;
+    }
+}
+
+// egg_wobble -> pet1_idle, used when no species file has been pushed to the device
+// pet1_idle only has 2 frames, walk plays them faster, sit and sleep hold one each
+#[rustfmt::skip]
+static DEFAULT_SPECIES: [u8; HEADER_SIZE + STAGE_SIZE * 2 + BRANCH_SIZE] = [
+    b'P', b'E', b'V', b'O', VERSION, 2, 1, 0,
+    // stage 0, egg
+    b'e', b'g', b'g', b'_', b'w', b'o', b'b', b'b', b'l', b'e', 0, 0, 0, 0, 0, 0,
+    1, 0, 0, 1,
+    0, 4, 8,  0, 0, 0,  0, 0, 0,  0, 0, 0,
+    // stage 1, final
+    b'p', b'e', b't', b'1', b'_', b'i', b'd', b'l', b'e', 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0,
+    0, 2, 8,  0, 2, 4,  0, 1, 8,  1, 2, 8,
+    // branch 0, egg -> stage 1 regardless of care
+    0, 1, 0, 0,
+];
+
+pub struct Species {
+    data: &'static [u8],
+    stage_count: u8,
+    branch_count: u8,
+}
+
+impl Species {
+    pub fn default_species() -> Self {
+        return Species::parse(&DEFAULT_SPECIES).ok().unwrap();
+    }
+
+    pub fn parse(data: &'static [u8]) -> Result<Self, SpeciesError> {
+        if data.len() < HEADER_SIZE {
+            return Err(SpeciesError::Truncated);
+        }
+        if &data[0..4] != MAGIC {
+            return Err(SpeciesError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(SpeciesError::UnsupportedVersion);
+        }
+
+        let stage_count = data[5];
+        let branch_count = data[6];
+
+        if stage_count == 0 {
+            return Err(SpeciesError::NoStages);
+        }
+
+        let length =
+            HEADER_SIZE + STAGE_SIZE * stage_count as usize + BRANCH_SIZE * branch_count as usize;
+        if data.len() < length {
+            return Err(SpeciesError::Truncated);
+        }
+
+        let species = Self {
+            data,
+            stage_count,
+            branch_count,
+        };
+
+        for s in 0..stage_count {
+            let offset = species.stage_offset(s);
+
+            if !Self::valid_key(&data[offset..offset + KEY_SIZE]) {
+                return Err(SpeciesError::BadKey);
+            }
+
+            let first_branch = data[offset + 18] as usize;
+            let stage_branches = data[offset + 19] as usize;
+            if first_branch + stage_branches > branch_count as usize {
+                return Err(SpeciesError::BadBranch);
+            }
+
+            for t in 0..TAG_COUNT {
+                let tag = offset + 20 + t * 3;
+                if data[tag + 1] < data[tag] {
+                    return Err(SpeciesError::BadTag);
+                }
+            }
+        }
+
+        for b in 0..branch_count {
+            let next = data[species.branch_offset(b) + 1];
+            if next >= stage_count {
+                return Err(SpeciesError::BadBranch);
+            }
+        }
+
+        return Ok(species);
+    }
+
+    // printable ascii padded with nulls, nothing after the first null
+    fn valid_key(key: &[u8]) -> bool {
+        let length = key.iter().position(|&c| c == 0).unwrap_or(key.len());
+        return length > 0
+            && key[..length].iter().all(|c| c.is_ascii_graphic() || *c == b' ')
+            && key[length..].iter().all(|&c| c == 0);
+    }
+
+    fn stage_offset(&self, stage: u8) -> usize {
+        return HEADER_SIZE + STAGE_SIZE * stage as usize;
+    }
+
+    fn branch_offset(&self, branch: u8) -> usize {
+        return HEADER_SIZE + STAGE_SIZE * self.stage_count as usize + BRANCH_SIZE * branch as usize;
+    }
+
+    pub fn stage_count(&self) -> u8 {
+        return self.stage_count;
+    }
+
+    pub fn stage(&self, stage: u8) -> Option<Stage> {
+        if stage >= self.stage_count {
+            return None;
+        }
+
+        let offset = self.stage_offset(stage);
+        let data = self.data;
+
+        // validated in parse
+        let key = unsafe { core::str::from_utf8_unchecked(&data[offset..offset + KEY_SIZE]) }
+            .trim_end_matches(char::from(0));
+
+        let mut tags = [TagRange {
+            start: 0,
+            end: 0,
+            ticks_per_frame: 0,
+        }; TAG_COUNT];
+
+        for t in 0..TAG_COUNT {
+            let tag = offset + 20 + t * 3;
+            tags[t] = TagRange {
+                start: data[tag],
+                end: data[tag + 1],
+                ticks_per_frame: data[tag + 2],
+            };
+        }
+
+        return Some(Stage {
+            key,
+            duration_min: u16::from_le_bytes([data[offset + 16], data[offset + 17]]),
+            first_branch: data[offset + 18],
+            branch_count: data[offset + 19],
+            tags,
+        });
+    }
+
+    // stage to evolve into given the average care quality over the current stage
+    pub fn next_stage(&self, stage: u8, care: u8) -> Option<u8> {
+        let current = self.stage(stage)?;
+
+        for b in current.first_branch..current.first_branch + current.branch_count {
+            let offset = self.branch_offset(b);
+            if care >= self.data[offset] {
+                return Some(self.data[offset + 1]);
+            }
+        }
+        return None;
+    }
+
+    // every stage reachable in one evolution step, used to preload sprites
+    pub fn branch_targets(&self, stage: u8) -> impl Iterator<Item = u8> + '_ {
+        let (first, count) = match self.stage(stage) {
+            Some(s) => (s.first_branch, s.branch_count),
+            None => (0, 0),
+        };
+        (first..first + count).map(move |b| self.data[self.branch_offset(b) + 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    fn stage(key: &[u8], duration_min: u16, first_branch: u8, branch_count: u8) -> Vec<u8> {
+        let mut data = std::vec![0; STAGE_SIZE];
+        data[..key.len()].copy_from_slice(key);
+        data[16..18].copy_from_slice(&duration_min.to_le_bytes());
+        data[18] = first_branch;
+        data[19] = branch_count;
+        data[20..23].copy_from_slice(&[0, 2, 8]); // idle
+        return data;
+    }
+
+    // egg -> good (care >= 60) or plain (any care), plain -> old, good and old are final
+    fn branching() -> Vec<u8> {
+        let mut data = std::vec![b'P', b'E', b'V', b'O', VERSION, 4, 3, 0];
+        data.extend(stage(b"egg", 10, 0, 2));
+        data.extend(stage(b"plain", 60, 2, 1));
+        data.extend(stage(b"good", 0, 0, 0));
+        data.extend(stage(b"old", 0, 0, 0));
+        data.extend([60, 2, 0, 0]);
+        data.extend([0, 1, 0, 0]);
+        data.extend([0, 3, 0, 0]);
+        return data;
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Species, SpeciesError> {
+        return Species::parse(Box::leak(data.into_boxed_slice()));
+    }
+
+    // default species with the first stage's key replaced
+    fn with_key(key: &[u8]) -> Result<Species, SpeciesError> {
+        let mut data = DEFAULT_SPECIES;
+        data[HEADER_SIZE..HEADER_SIZE + KEY_SIZE].fill(0);
+        data[HEADER_SIZE..HEADER_SIZE + key.len()].copy_from_slice(key);
+        return Species::parse(Box::leak(Box::new(data)));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(with_key(b"egg_wobble").unwrap().stage(0).unwrap().key, "egg_wobble");
+        assert_eq!(with_key(b"sixteen_chars_ok").unwrap().stage(0).unwrap().key, "sixteen_chars_ok");
+
+        assert_eq!(with_key(b"").err(), Some(SpeciesError::BadKey));
+        assert_eq!(with_key(b"egg\0wobble").err(), Some(SpeciesError::BadKey));
+        assert_eq!(with_key(b"egg\nwobble").err(), Some(SpeciesError::BadKey));
+        assert_eq!(with_key("egg_\u{e9}".as_bytes()).err(), Some(SpeciesError::BadKey));
+    }
+
+    #[test]
+    fn default_species_has_every_tag() {
+        let species = Species::default_species();
+        let pet = species.stage(species.next_stage(0, 0).unwrap()).unwrap();
+
+        let idle = pet.tag(AnimTag::Idle);
+        for tag in [AnimTag::Walk, AnimTag::Sit, AnimTag::Sleep] {
+            assert!(pet.tag(tag) != idle, "{:?}", tag);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        const STAGE_1: usize = HEADER_SIZE + STAGE_SIZE;
+        const BRANCH_2: usize = HEADER_SIZE + STAGE_SIZE * 4 + BRANCH_SIZE * 2;
+
+        let cases: [(&str, fn(&mut Vec<u8>), SpeciesError); 8] = [
+            ("short header", |d| d.truncate(HEADER_SIZE - 1), SpeciesError::Truncated),
+            ("short body", |d| { d.pop(); }, SpeciesError::Truncated),
+            ("magic", |d| d[0] = b'X', SpeciesError::BadMagic),
+            ("version", |d| d[4] = VERSION + 1, SpeciesError::UnsupportedVersion),
+            ("no stages", |d| d[5] = 0, SpeciesError::NoStages),
+            // first branch + count past the branch count
+            ("branch range", |d| d[STAGE_1 + 18] = 3, SpeciesError::BadBranch),
+            ("branch target", |d| d[BRANCH_2 + 1] = 4, SpeciesError::BadBranch),
+            // idle ends before it starts
+            ("tag", |d| d[STAGE_1 + 20] = 3, SpeciesError::BadTag),
+        ];
+
+        assert!(parse(branching()).is_ok());
+        for (name, corrupt, error) in cases {
+            let mut data = branching();
+            corrupt(&mut data);
+            assert_eq!(parse(data).err(), Some(error), "{}", name);
+        }
+    }
+
+    #[test]
+    fn next_stage_takes_the_first_met_branch() {
+        let species = parse(branching()).unwrap();
+
+        assert_eq!(species.next_stage(0, 100), Some(2));
+        assert_eq!(species.next_stage(0, 60), Some(2));
+        assert_eq!(species.next_stage(0, 59), Some(1));
+        assert_eq!(species.next_stage(1, 0), Some(3));
+        assert_eq!(species.next_stage(2, 100), None);
+        assert_eq!(species.next_stage(4, 100), None);
+        assert!(species.stage(3).unwrap().is_final());
+    }
+
+    #[test]
+    fn branch_targets() {
+        let species = parse(branching()).unwrap();
+
+        assert_eq!(species.branch_targets(0).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(species.branch_targets(1).collect::<Vec<_>>(), [3]);
+        assert_eq!(species.branch_targets(3).count(), 0);
+        assert_eq!(species.branch_targets(4).count(), 0);
+    }
+}
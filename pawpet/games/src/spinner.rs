@@ -0,0 +1,187 @@
+/*!
+ * Numeric value picker drawn inline, the caller places it. Up/Down change the value and repeat
+ * while held, P confirms and A goes back.
+ *
+ *  Spinner::new(0, 23, hours).wrapping().digits(2)
+ */
+
+use core::fmt::Write;
+use heapless::String;
+use pawdevicetraits::Buttons;
+use pawdevicetraits::ButtonsDevice as Input;
+use pawdevicetraits::DisplayDevice as Display;
+
+use crate::dialog::DialogResult;
+use crate::font::Font;
+use crate::font::TextStyle;
+
+// in frames, the spinner is ticked once per frame
+const REPEAT_DELAY: u8 = 8;
+const REPEAT_INTERVAL: u8 = 2;
+
+pub struct Spinner {
+    value: i32,
+    min: i32,
+    max: i32,
+    step: i32,
+    wrap: bool,
+    digits: u8, // zero padded width
+    held: u8,   // frames Up or Down has been held
+    font: Font,
+}
+
+impl Spinner {
+    pub fn new(min: i32, max: i32, value: i32) -> Self {
+        Self {
+            value: value.clamp(min, max),
+            min,
+            max,
+            step: 1,
+            wrap: false,
+            digits: 1,
+            held: 0,
+            font: Font::default_font(),
+        }
+    }
+
+    // past max goes back to min and the other way around
+    pub fn wrapping(mut self) -> Self {
+        self.wrap = true;
+        return self;
+    }
+
+    pub fn step(mut self, step: i32) -> Self {
+        self.step = step.max(1);
+        return self;
+    }
+
+    pub fn digits(mut self, digits: u8) -> Self {
+        self.digits = digits;
+        return self;
+    }
+
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+
+    pub fn value(&self) -> i32 {
+        return self.value;
+    }
+
+    pub fn set_value(&mut self, value: i32) {
+        self.value = value.clamp(self.min, self.max);
+    }
+
+    fn change(&mut self, up: bool) {
+        let next = if up {
+            self.value + self.step
+        } else {
+            self.value - self.step
+        };
+
+        self.value = if next > self.max {
+            if self.wrap { self.min } else { self.max }
+        } else if next < self.min {
+            if self.wrap { self.max } else { self.min }
+        } else {
+            next
+        };
+    }
+
+    // None until confirmed or cancelled, the value is read with value()
+    pub fn tick(&mut self, buttons: &mut impl Input) -> Option<DialogResult> {
+        if buttons.is_pressed(Buttons::A) {
+            return Some(DialogResult::Cancel);
+        } else if buttons.is_pressed(Buttons::P) {
+            return Some(DialogResult::Ok);
+        }
+
+        if buttons.is_pressed(Buttons::Up) || buttons.is_pressed(Buttons::Down) {
+            self.held = 0;
+            self.change(buttons.is_pressed(Buttons::Up));
+        } else if buttons.is_held(Buttons::Up) || buttons.is_held(Buttons::Down) {
+            self.held += 1;
+            if self.held >= REPEAT_DELAY {
+                self.held = REPEAT_DELAY - REPEAT_INTERVAL;
+                self.change(buttons.is_held(Buttons::Up));
+            }
+        } else {
+            self.held = 0;
+        }
+        return None;
+    }
+
+    pub fn format(&self) -> String<12> {
+        let mut text = String::new();
+        write!(text, "{:0width$}", self.value, width = self.digits as usize).ok();
+        return text;
+    }
+
+    // highlighted while active, returns the x after the value
+    pub fn draw(&self, disp: &mut impl Display, x: i32, y: i32, active: bool) -> i32 {
+        let style = if active {
+            TextStyle::HIGHLIGHT
+        } else {
+            TextStyle::NORMAL
+        };
+        return self.font.draw(disp, &self.format(), x, y, style);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Keys;
+
+    #[test]
+    fn steps_wrap_and_clamp() {
+        let mut hours = Spinner::new(0, 23, 23).wrapping().digits(2);
+        hours.tick(&mut Keys::press(Buttons::Up));
+        assert_eq!(hours.value(), 0);
+        assert_eq!(hours.format(), "00");
+        hours.tick(&mut Keys::press(Buttons::Down));
+        assert_eq!(hours.value(), 23);
+
+        let mut volume = Spinner::new(1, 3, 5);
+        assert_eq!(volume.value(), 3);
+        volume.tick(&mut Keys::press(Buttons::Up));
+        assert_eq!(volume.value(), 3);
+
+        let mut minutes = Spinner::new(0, 12, 0).step(5);
+        for _ in 0..3 {
+            minutes.tick(&mut Keys::press(Buttons::Up));
+        }
+        assert_eq!(minutes.value(), 12);
+        assert_eq!(minutes.format(), "12");
+    }
+
+    #[test]
+    fn repeats_while_held() {
+        let mut spinner = Spinner::new(0, 100, 0);
+        let mut hold = Keys {
+            pressed: 0,
+            held: Buttons::Up as u8,
+        };
+
+        spinner.tick(&mut Keys::press(Buttons::Up));
+        for _ in 0..REPEAT_DELAY - 1 {
+            spinner.tick(&mut hold);
+        }
+        assert_eq!(spinner.value(), 1);
+
+        spinner.tick(&mut hold);
+        assert_eq!(spinner.value(), 2);
+        for _ in 0..REPEAT_INTERVAL * 3 {
+            spinner.tick(&mut hold);
+        }
+        assert_eq!(spinner.value(), 5);
+
+        // released, the delay starts over
+        spinner.tick(&mut Keys { pressed: 0, held: 0 });
+        spinner.tick(&mut hold);
+        assert_eq!(spinner.value(), 5);
+
+        assert_eq!(spinner.tick(&mut Keys::press(Buttons::P)), Some(DialogResult::Ok));
+        assert_eq!(spinner.tick(&mut Keys::press(Buttons::A)), Some(DialogResult::Cancel));
+    }
+}
@@ -0,0 +1,119 @@
+/*!
+ * Display and input mocks shared by the unit tests.
+ */
+
+extern crate std;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use pawdevicetraits::Buttons;
+use pawdevicetraits::ButtonsDevice;
+use pawdevicetraits::DisplayDevice;
+use pawdevicetraits::DisplayUpdateStats;
+use std::string::String;
+use std::vec::Vec;
+
+// 64x64 screen, '#' on, '.' off and ' ' never drawn
+// counts pixel writes so tests can tell what was repainted
+pub struct Grid {
+    pub pixels: [[u8; 64]; 64],
+    pub writes: usize,
+}
+
+impl Grid {
+    pub fn new() -> Self {
+        return Self {
+            pixels: [[b' '; 64]; 64],
+            writes: 0,
+        };
+    }
+
+    pub fn is_on(&self, x: usize, y: usize) -> bool {
+        return self.pixels[y][x] == b'#';
+    }
+
+    pub fn rows(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<String> {
+        return (y..y + height)
+            .map(|row| String::from_utf8(self.pixels[row][x..x + width].to_vec()).unwrap())
+            .collect();
+    }
+}
+
+impl DisplayDevice for Grid {
+    fn draw_pixel(&mut self, x: u8, y: u8, color: bool) {
+        self.pixels[y as usize][x as usize] = if color { b'#' } else { b'.' };
+        self.writes += 1;
+    }
+
+    fn update(&mut self) -> bool {
+        return true;
+    }
+
+    fn set_rotation(&mut self, _rotation: u8) {}
+
+    fn update_stats(&self) -> DisplayUpdateStats {
+        return DisplayUpdateStats::default();
+    }
+}
+
+impl OriginDimensions for Grid {
+    fn size(&self) -> Size {
+        return Size::new(64, 64);
+    }
+}
+
+impl DrawTarget for Grid {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                self.draw_pixel(point.x as u8, point.y as u8, color.is_on());
+            }
+        }
+        return Ok(());
+    }
+}
+
+// buttons as bit masks, nothing is ever released
+pub struct Keys {
+    pub pressed: u8,
+    pub held: u8,
+}
+
+impl Keys {
+    // pressed this frame and held
+    pub fn press(b: Buttons) -> Self {
+        let bits = b as u8;
+        return Self {
+            pressed: bits,
+            held: bits,
+        };
+    }
+}
+
+impl ButtonsDevice for Keys {
+    fn poll_buttons(&mut self) {}
+    fn update_buttons(&mut self) {}
+
+    fn is_held(&self, b: Buttons) -> bool {
+        return self.held & b as u8 > 0;
+    }
+
+    fn is_pressed(&self, b: Buttons) -> bool {
+        return self.pressed & b as u8 > 0;
+    }
+
+    fn is_released(&self, _b: Buttons) -> bool {
+        return false;
+    }
+
+    fn get_state(&self) -> u8 {
+        return self.held;
+    }
+}
@@ -0,0 +1,336 @@
+/*!
+ * Tile map drawn from a sprite map of 8x8 tiles, for backgrounds larger than the screen.
+ * Compiled on host by png2c/tilemap.py
+ *
+ * little endian
+ * [header 8 bytes]
+ *      magic "TMAP", version u8, width u8 (tiles), height u8 (tiles), reserved u8
+ * [tiles u8 * width * height]
+ *      row major frame index in the tile sheet, EMPTY_TILE draws nothing
+ *
+ * The camera is the map pixel shown at the top left of the view, tiles are drawn with
+ * per pixel offsets and clipped to the view.
+ */
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use pawdevicetraits::DisplayDevice;
+use rtt_target::debug_rprintln;
+
+use crate::image::PawImage;
+
+pub const TILE_SIZE: u8 = 8;
+pub const EMPTY_TILE: u8 = 0xFF;
+
+const MAGIC: &[u8; 4] = b"TMAP";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 8;
+const SCREEN_SIZE: u32 = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum TileMapError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion,
+    Empty,
+}
+
+pub struct TileMapData {
+    width: u8,
+    height: u8,
+    tiles: &'static [u8],
+}
+
+impl TileMapData {
+    pub fn parse(data: &'static [u8]) -> Result<Self, TileMapError> {
+        if data.len() < HEADER_SIZE {
+            return Err(TileMapError::Truncated);
+        }
+        if &data[0..4] != MAGIC {
+            return Err(TileMapError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(TileMapError::UnsupportedVersion);
+        }
+
+        let width = data[5];
+        let height = data[6];
+        if width == 0 || height == 0 {
+            return Err(TileMapError::Empty);
+        }
+
+        let length = HEADER_SIZE + width as usize * height as usize;
+        if data.len() < length {
+            return Err(TileMapError::Truncated);
+        }
+
+        return Ok(Self {
+            width,
+            height,
+            tiles: &data[HEADER_SIZE..length],
+        });
+    }
+
+    pub fn width(&self) -> u8 {
+        return self.width;
+    }
+
+    pub fn height(&self) -> u8 {
+        return self.height;
+    }
+
+    // EMPTY_TILE outside the map
+    pub fn tile(&self, x: i32, y: i32) -> u8 {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return EMPTY_TILE;
+        }
+        return self.tiles[y as usize * self.width as usize + x as usize];
+    }
+}
+
+pub struct TileMap {
+    sheet: PawImage,
+    map: Option<TileMapData>,
+    camera: Point,
+    view: Rectangle,
+}
+
+impl TileMap {
+    pub fn new() -> Self {
+        Self {
+            sheet: PawImage::new_no_data(),
+            map: None,
+            camera: Point::zero(),
+            view: Rectangle::new(Point::zero(), Size::new(SCREEN_SIZE, SCREEN_SIZE)),
+        }
+    }
+
+    // sheets with tiles other than 8x8 are ignored
+    pub fn set_tiles(&mut self, data: Option<&'static [u8]>) {
+        self.sheet.set_image(data);
+
+        if self.sheet.width() != TILE_SIZE || self.sheet.height() != TILE_SIZE {
+            if data.is_some() {
+                debug_rprintln!("tile sheet is not 8x8");
+            }
+            self.sheet.set_image(None);
+        }
+    }
+
+    pub fn set_map(&mut self, data: Option<&'static [u8]>) {
+        self.map = match data.map(TileMapData::parse) {
+            Some(Ok(map)) => Some(map),
+            Some(Err(error)) => {
+                debug_rprintln!("bad tile map {:?}", error);
+                None
+            }
+            None => None,
+        };
+        self.set_camera(self.camera);
+    }
+
+    pub fn set_colors(&mut self, on_color: bool, off_color: bool, alpha_color: Option<bool>) {
+        self.sheet.set_colors(on_color, off_color, alpha_color);
+    }
+
+    // screen area the map is drawn into, the camera is kept inside the map for this size
+    pub fn set_view(&mut self, view: Rectangle) {
+        self.view = view;
+        self.set_camera(self.camera);
+    }
+
+    pub fn view(&self) -> Rectangle {
+        return self.view;
+    }
+
+    // map size in pixels
+    pub fn size(&self) -> Size {
+        match &self.map {
+            Some(map) => {
+                return Size::new(
+                    map.width as u32 * TILE_SIZE as u32,
+                    map.height as u32 * TILE_SIZE as u32,
+                )
+            }
+            None => return Size::zero(),
+        }
+    }
+
+    // top left map pixel of the view, limited so the view never shows past the map edges
+    pub fn set_camera(&mut self, camera: Point) {
+        let size = self.size();
+        let max_x = (size.width as i32 - self.view.size.width as i32).max(0);
+        let max_y = (size.height as i32 - self.view.size.height as i32).max(0);
+
+        self.camera = Point::new(camera.x.clamp(0, max_x), camera.y.clamp(0, max_y));
+    }
+
+    pub fn scroll(&mut self, dx: i32, dy: i32) {
+        self.set_camera(self.camera + Point::new(dx, dy));
+    }
+
+    pub fn camera(&self) -> Point {
+        return self.camera;
+    }
+
+    // centers the view on a map pixel, for following the pet
+    pub fn look_at(&mut self, point: Point) {
+        let half = Point::new(
+            self.view.size.width as i32 / 2,
+            self.view.size.height as i32 / 2,
+        );
+        self.set_camera(point - half);
+    }
+
+    // tile under a map pixel, for collision
+    pub fn tile_at(&self, point: Point) -> u8 {
+        match &self.map {
+            Some(map) => {
+                return map.tile(
+                    point.x.div_euclid(TILE_SIZE as i32),
+                    point.y.div_euclid(TILE_SIZE as i32),
+                )
+            }
+            None => return EMPTY_TILE,
+        }
+    }
+
+    // map pixel to screen position
+    pub fn to_screen(&self, point: Point) -> Point {
+        return point - self.camera + self.view.top_left;
+    }
+
+    pub fn draw(&mut self, disp: &mut impl DisplayDevice) {
+        let map = match &self.map {
+            Some(map) => map,
+            None => return,
+        };
+
+        let tile = TILE_SIZE as i32;
+        let view = self.view;
+        let view_end = view.top_left + view.size;
+
+        // partially visible tiles on each edge are included
+        let first = Point::new(self.camera.x.div_euclid(tile), self.camera.y.div_euclid(tile));
+        let last = Point::new(
+            (self.camera.x + view.size.width as i32 - 1).div_euclid(tile),
+            (self.camera.y + view.size.height as i32 - 1).div_euclid(tile),
+        );
+
+        for ty in first.y..=last.y {
+            for tx in first.x..=last.x {
+                let index = map.tile(tx, ty);
+                if index == EMPTY_TILE || index >= self.sheet.tile_count() {
+                    continue;
+                }
+
+                let screen = self.to_screen(Point::new(tx * tile, ty * tile));
+
+                // view edges in tile coordinates
+                let clip_start = (view.top_left - screen).component_max(Point::zero());
+                let clip_end = (view_end - screen).component_min(Point::new(tile, tile));
+                let clip = Rectangle::with_corners(clip_start, clip_end - Point::new(1, 1));
+
+                self.sheet.set_frame(index);
+                self.sheet.set_clip(Some(clip));
+                self.sheet.draw(disp, screen.x, screen.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::Grid;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    fn leak(data: Vec<u8>) -> &'static [u8] {
+        return Box::leak(data.into_boxed_slice());
+    }
+
+    // tile 0 solid on, tile 1 solid off
+    fn sheet() -> &'static [u8] {
+        let mut data = Vec::new();
+        for value in [8u16, 8, 0, 2, 0, 8] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[0xFF; 8]);
+        data.extend_from_slice(&[0x00; 8]);
+        return leak(data);
+    }
+
+    fn map() -> &'static [u8] {
+        let mut data = Vec::from(*b"TMAP");
+        data.extend_from_slice(&[VERSION, 3, 2, 0]);
+        data.extend_from_slice(&[0, 1, 0, 1, 0, EMPTY_TILE]);
+        return leak(data);
+    }
+
+    fn tile_map() -> TileMap {
+        let mut tiles = TileMap::new();
+        tiles.set_tiles(Some(sheet()));
+        tiles.set_map(Some(map()));
+        return tiles;
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(TileMapData::parse(&map()[..7]).err(), Some(TileMapError::Truncated));
+        assert_eq!(TileMapData::parse(&map()[..13]).err(), Some(TileMapError::Truncated));
+        assert_eq!(TileMapData::parse(b"TMAX\x01\x01\x01\x00\x00").err(), Some(TileMapError::BadMagic));
+        assert_eq!(TileMapData::parse(b"TMAP\x02\x01\x01\x00\x00").err(), Some(TileMapError::UnsupportedVersion));
+        assert_eq!(TileMapData::parse(b"TMAP\x01\x00\x01\x00").err(), Some(TileMapError::Empty));
+    }
+
+    #[test]
+    fn camera_stays_inside_the_map() {
+        let mut tiles = tile_map();
+        assert_eq!(tiles.size(), Size::new(24, 16));
+
+        tiles.set_view(Rectangle::new(Point::new(4, 4), Size::new(10, 6)));
+        tiles.set_camera(Point::new(100, 100));
+        assert_eq!(tiles.camera(), Point::new(14, 10));
+        tiles.scroll(-20, -3);
+        assert_eq!(tiles.camera(), Point::new(0, 7));
+
+        // map smaller than the view
+        tiles.set_view(Rectangle::new(Point::zero(), Size::new(64, 64)));
+        assert_eq!(tiles.camera(), Point::zero());
+
+        assert_eq!(tiles.tile_at(Point::new(9, 0)), 1);
+        assert_eq!(tiles.tile_at(Point::new(17, 9)), EMPTY_TILE);
+        assert_eq!(tiles.tile_at(Point::new(-1, 0)), EMPTY_TILE);
+    }
+
+    #[test]
+    fn draws_with_sub_tile_offsets_inside_the_view() {
+        let mut tiles = tile_map();
+        let view = Rectangle::new(Point::new(4, 4), Size::new(14, 9));
+        tiles.set_view(view);
+        tiles.set_camera(Point::new(5, 3));
+
+        let mut grid = Grid::new();
+        tiles.draw(&mut grid);
+
+        for y in 0..64 {
+            for x in 0..64 {
+                let screen = Point::new(x, y);
+                let expected = if view.contains(screen) {
+                    let pixel = screen - view.top_left + tiles.camera();
+                    match tiles.tile_at(pixel) {
+                        0 => b'#',
+                        1 => b'.',
+                        _ => b' ',
+                    }
+                } else {
+                    b' '
+                };
+                assert_eq!(grid.pixels[y as usize][x as usize], expected, "{} {}", x, y);
+            }
+        }
+    }
+}
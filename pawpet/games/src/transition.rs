@@ -0,0 +1,455 @@
+/*!
+ * Screen transition between the outgoing and incoming state. The runner draws the outgoing state
+ * into outgoing() when the state changes, then loads the new state once needs_incoming() and
+ * draws it into incoming(). Every frame after that is composited from the two buffers until
+ * is_done(), only then does the new state get input.
+ *
+ * Time advances by a fixed step per frame, a frame stalled by asset loading doesn't skip part of
+ * the effect. Iris loads at the midpoint while the screen is black, the other effects load on the
+ * first frame which still shows the outgoing state.
+ *
+ * Overlapping the asset loading means exactly this: the load is still a single call that stalls
+ * one frame, but that frame already shows the right picture and the effect runs in full after
+ * it. Loading is not spread over several frames.
+ */
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use pawdevicetraits::DisplayDevice as Display;
+use pawdevicetraits::DisplayUpdateStats;
+
+use crate::gamestate::StateKind;
+
+const SIZE: usize = 64;
+const STEPS: u32 = 64; // progress, 0 is the outgoing frame and STEPS the incoming frame
+const IRIS_RADIUS: u32 = 46; // past the corners of the screen
+
+// ordered dither thresholds, a pixel shows the incoming frame once progress passes its threshold
+#[rustfmt::skip]
+static BAYER: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// direction the edge or the frames move in
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Effect {
+    Wipe(Direction),  // incoming frame uncovered behind a moving edge
+    DitherFade,       // ordered dither from one frame to the other
+    Iris,             // circle closes to black on the outgoing frame, opens on the incoming frame
+    Slide(Direction), // incoming frame pushes the outgoing frame off screen
+}
+
+impl Effect {
+    pub fn duration_ms(&self) -> u32 {
+        match self {
+            Effect::Wipe(_) => 300,
+            Effect::DitherFade => 600,
+            Effect::Iris => 700,
+            Effect::Slide(_) => 250,
+        }
+    }
+
+    // progress the incoming frame is first needed at
+    fn load_at(&self) -> u32 {
+        match self {
+            Effect::Iris => STEPS / 2,
+            _ => 0,
+        }
+    }
+}
+
+// effect used when the runner switches states, None is a hard cut
+pub(crate) fn effect_for(from: StateKind, to: StateKind, reload: bool) -> Option<Effect> {
+    let effect = match (from, to) {
+        (StateKind::Main, _) => return None,
+        (_, StateKind::Memorial) => Effect::DitherFade,
+        // evolved, same state with new sprites
+        _ if reload => Effect::Iris,
+        // new egg, hatched
+        (StateKind::Memorial, _) | (StateKind::Egg, _) => Effect::Iris,
+        (StateKind::Home, _) => Effect::Slide(Direction::Left),
+        (_, StateKind::Home) => Effect::Slide(Direction::Right),
+        _ => Effect::Wipe(Direction::Down),
+    };
+    return Some(effect);
+}
+
+// first n bits set
+fn low_bits(n: u32) -> u64 {
+    if n >= SIZE as u32 {
+        return !0;
+    }
+    return (1 << n) - 1;
+}
+
+// pixels of row y inside a circle around the screen center, doubled coordinates keep the center
+// between pixels
+fn circle_row(y: usize, radius: u32) -> u64 {
+    let dy = 2 * y as i32 - (SIZE as i32 - 1);
+    let limit = (2 * radius as i32).pow(2);
+
+    let mut mask = 0;
+    for x in 0..SIZE {
+        let dx = 2 * x as i32 - (SIZE as i32 - 1);
+        if dx * dx + dy * dy < limit {
+            mask |= 1 << x;
+        }
+    }
+    return mask;
+}
+
+/**
+ * 64x64 off screen frame, a row per u64 with bit x for column x. States draw into it the same
+ * way they draw to the display.
+ */
+pub struct FrameBuffer {
+    rows: [u64; SIZE],
+}
+
+impl FrameBuffer {
+    pub const fn new() -> Self {
+        Self { rows: [0; SIZE] }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows = [0; SIZE];
+    }
+
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        if x as usize >= SIZE || y as usize >= SIZE {
+            return false;
+        }
+        return (self.rows[y as usize] >> x) & 1 > 0;
+    }
+
+    fn row(&self, y: i32) -> u64 {
+        if y < 0 || y >= SIZE as i32 {
+            return 0;
+        }
+        return self.rows[y as usize];
+    }
+}
+
+impl Display for FrameBuffer {
+    fn draw_pixel(&mut self, x: u8, y: u8, color: bool) {
+        self.fill_rect(x, y, 1, 1, color);
+    }
+
+    fn update(&mut self) -> bool {
+        return true;
+    }
+
+    fn set_rotation(&mut self, _rotation: u8) {}
+
+    fn update_stats(&self) -> DisplayUpdateStats {
+        return DisplayUpdateStats::default();
+    }
+
+    fn fill_rect(&mut self, x: u8, y: u8, width: u8, height: u8, color: bool) {
+        if x as usize >= SIZE {
+            return;
+        }
+        let width = (width as u32).min(SIZE as u32 - x as u32);
+        let mask = low_bits(width) << x;
+
+        for row in self.rows.iter_mut().skip(y as usize).take(height as usize) {
+            if color {
+                *row |= mask;
+            } else {
+                *row &= !mask;
+            }
+        }
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.x < SIZE as i32 && point.y >= 0 && point.y < SIZE as i32 {
+                self.draw_pixel(point.x as u8, point.y as u8, color.is_on());
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        return Size::new(SIZE as u32, SIZE as u32);
+    }
+}
+
+pub struct Transition {
+    effect: Effect,
+    duration_ms: u32,
+    elapsed_ms: u32,
+    from: FrameBuffer,
+    to: FrameBuffer,
+    incoming: bool, // to holds the incoming frame
+    active: bool,
+}
+
+impl Transition {
+    pub const fn new() -> Self {
+        Self {
+            effect: Effect::DitherFade,
+            duration_ms: 0,
+            elapsed_ms: 0,
+            from: FrameBuffer::new(),
+            to: FrameBuffer::new(),
+            incoming: false,
+            active: false,
+        }
+    }
+
+    // both frames start blank, the outgoing frame is drawn right after
+    pub fn start(&mut self, effect: Effect, duration_ms: u32) {
+        self.effect = effect;
+        self.duration_ms = duration_ms.max(1);
+        self.elapsed_ms = 0;
+        self.from.clear();
+        self.to.clear();
+        self.incoming = false;
+        self.active = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        return self.active;
+    }
+
+    pub fn outgoing(&mut self) -> &mut FrameBuffer {
+        return &mut self.from;
+    }
+
+    // drawn into once, the effect shows a blank incoming frame until then
+    pub fn incoming(&mut self) -> &mut FrameBuffer {
+        self.incoming = true;
+        return &mut self.to;
+    }
+
+    // new state should be loaded and drawn into incoming()
+    pub fn needs_incoming(&self) -> bool {
+        return self.active && !self.incoming && self.progress() >= self.effect.load_at();
+    }
+
+    pub fn advance(&mut self, ms: u32) {
+        if self.active {
+            self.elapsed_ms = (self.elapsed_ms + ms).min(self.duration_ms);
+        }
+    }
+
+    // last frame has been reached, the runner stops the transition once it is drawn
+    pub fn is_done(&self) -> bool {
+        return self.active && self.incoming && self.elapsed_ms >= self.duration_ms;
+    }
+
+    fn progress(&self) -> u32 {
+        return self.elapsed_ms * STEPS / self.duration_ms.max(1);
+    }
+
+    // row y of the current frame
+    fn composite(&self, y: usize) -> u64 {
+        let level = self.progress();
+        let from = self.from.rows[y];
+        let to = self.to.rows[y];
+        let edge = level * SIZE as u32 / STEPS;
+
+        match self.effect {
+            Effect::Wipe(direction) => {
+                let mask = match direction {
+                    Direction::Right => low_bits(edge),
+                    Direction::Left => !low_bits(SIZE as u32 - edge),
+                    Direction::Down if (y as u32) < edge => !0,
+                    Direction::Up if y as u32 >= SIZE as u32 - edge => !0,
+                    _ => 0,
+                };
+                return (from & !mask) | (to & mask);
+            }
+            Effect::Slide(direction) => {
+                let y = y as i32;
+                let edge = edge as i32;
+                let back = SIZE as u32 - edge as u32;
+                match direction {
+                    Direction::Left => {
+                        return from.checked_shr(edge as u32).unwrap_or(0) | to.checked_shl(back).unwrap_or(0);
+                    }
+                    Direction::Right => {
+                        return from.checked_shl(edge as u32).unwrap_or(0) | to.checked_shr(back).unwrap_or(0);
+                    }
+                    Direction::Down => {
+                        return self.from.row(y - edge) | self.to.row(y + back as i32);
+                    }
+                    Direction::Up => {
+                        return self.from.row(y + edge) | self.to.row(y - back as i32);
+                    }
+                }
+            }
+            Effect::DitherFade => {
+                let mut pattern = 0u8;
+                for (x, threshold) in BAYER[y % 8].iter().enumerate() {
+                    if (*threshold as u32) < level {
+                        pattern |= 1 << x;
+                    }
+                }
+                let mask = pattern as u64 * 0x0101_0101_0101_0101;
+                return (from & !mask) | (to & mask);
+            }
+            Effect::Iris => {
+                let half = STEPS / 2;
+                if level < half {
+                    return from & circle_row(y, IRIS_RADIUS * (half - level) / half);
+                }
+                return to & circle_row(y, IRIS_RADIUS * (level - half) / half);
+            }
+        }
+    }
+
+    // whole screen, the overlays are drawn over it
+    pub fn draw(&self, disp: &mut impl Display) {
+        for y in 0..SIZE {
+            let row = self.composite(y);
+            for column in 0..SIZE / 8 {
+                let bits = (row >> (column * 8)) as u8;
+                disp.blit8((column * 8) as u8, y as u8, bits, true, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+    // outgoing frame is the left half, incoming frame the top half
+    fn halves(effect: Effect) -> Transition {
+        let mut transition = Transition::new();
+        transition.start(effect, 640);
+        transition.outgoing().fill_rect(0, 0, 32, 64, true);
+        transition.incoming().fill_rect(0, 0, 64, 32, true);
+        return transition;
+    }
+
+    fn frame(transition: &Transition) -> FrameBuffer {
+        let mut screen = FrameBuffer::new();
+        transition.draw(&mut screen);
+        return screen;
+    }
+
+    #[test]
+    fn effects_go_from_outgoing_to_incoming() {
+        use Direction::*;
+        let effects = [
+            Effect::Wipe(Left),
+            Effect::Wipe(Right),
+            Effect::Wipe(Up),
+            Effect::Wipe(Down),
+            Effect::DitherFade,
+            Effect::Iris,
+            Effect::Slide(Left),
+            Effect::Slide(Right),
+            Effect::Slide(Up),
+            Effect::Slide(Down),
+        ];
+
+        for effect in effects {
+            let mut transition = halves(effect);
+            assert_eq!(frame(&transition).rows, transition.from.rows, "{:?}", effect);
+            assert!(!transition.is_done());
+
+            transition.advance(10000);
+            assert!(transition.is_done());
+            assert_eq!(frame(&transition).rows, transition.to.rows, "{:?}", effect);
+        }
+    }
+
+    #[test]
+    fn halfway_frames() {
+        let mut slide = halves(Effect::Slide(Direction::Left));
+        slide.advance(320);
+        // outgoing left half is off screen, incoming frame fills the right half
+        let screen = frame(&slide);
+        assert!(!screen.pixel(0, 48));
+        assert!(screen.pixel(32, 0));
+        assert!(!screen.pixel(32, 48));
+
+        let mut wipe = halves(Effect::Wipe(Direction::Down));
+        wipe.advance(320);
+        let screen = frame(&wipe);
+        assert!(screen.pixel(40, 0));
+        assert!(!screen.pixel(40, 40));
+        assert!(screen.pixel(0, 40));
+
+        let mut fade = halves(Effect::DitherFade);
+        fade.advance(320);
+        let screen = frame(&fade);
+        let count = (0..8).filter(|x| screen.pixel(40 + x, 0)).count();
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn iris_loads_the_incoming_frame_while_black() {
+        let mut iris = Transition::new();
+        iris.start(Effect::Iris, 640);
+        iris.outgoing().fill_rect(0, 0, 64, 64, true);
+
+        iris.advance(160);
+        assert!(!iris.needs_incoming());
+        let screen = frame(&iris);
+        assert!(screen.pixel(32, 32));
+        assert!(!screen.pixel(0, 0));
+
+        iris.advance(160);
+        assert!(iris.needs_incoming());
+        assert!(frame(&iris).rows.iter().all(|row| *row == 0));
+
+        // waits for the incoming frame
+        iris.advance(10000);
+        assert!(!iris.is_done());
+        iris.incoming().fill_rect(0, 0, 64, 64, true);
+        assert!(iris.is_done());
+        assert!(!iris.needs_incoming());
+    }
+
+    #[test]
+    fn frame_buffer_is_a_draw_target() {
+        let mut buffer = FrameBuffer::new();
+        Rectangle::new(Point::new(60, 2), Size::new(10, 2))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(&mut buffer)
+            .ok();
+
+        assert!(buffer.pixel(63, 3));
+        assert!(!buffer.pixel(59, 3));
+        assert!(!buffer.pixel(63, 4));
+
+        buffer.fill_rect(62, 2, 8, 1, false);
+        assert!(!buffer.pixel(63, 2));
+        assert!(buffer.pixel(61, 2));
+    }
+}
@@ -275,6 +275,53 @@ impl HF2Monitor {
         send(&report);
     }
 
+    // response data larger than a single packet is continued in inner packets of up to 63 bytes
+    pub fn send_data_packets<F>(&mut self, mut send: F, data: &[u8])
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut response: &mut HF2Response;
+
+        unsafe {
+            response = &mut (*self.data.content.response);
+        }
+
+        response.status = HF2Responses::Success as u8;
+
+        let first_len = data.len().min(59);
+        unsafe {
+            response.content.data[0..first_len].copy_from_slice(&data[0..first_len]);
+        }
+
+        self.data.header = 4 + first_len as u8;
+        if first_len == data.len() {
+            self.data.header |= 0x40; // final packet
+        }
+
+        let report: &[u8] = unsafe {
+            core::slice::from_raw_parts(
+                (&self.data as *const HF2Packet) as *const u8,
+                core::mem::size_of::<HF2Packet>(),
+            )
+        };
+
+        send(&report);
+
+        let mut sent = first_len;
+        let mut report: [u8; 64] = [0; 64];
+        for chunk in data[first_len..].chunks(63) {
+            sent += chunk.len();
+
+            report[0] = chunk.len() as u8;
+            if sent == data.len() {
+                report[0] |= 0x40; // final packet
+            }
+            report[1..1 + chunk.len()].copy_from_slice(chunk);
+
+            send(&report);
+        }
+    }
+
     pub fn send_empty_error_packet<F>(&mut self, mut send: F)
     where
         F: FnMut(&[u8]),
@@ -0,0 +1,202 @@
+/*!
+ * Crash report kept in a RAM region the runtime does not initialize (see CRASH in memory.x),
+ * written by the panic and HardFault handlers right before a reset. The report survives the
+ * reset and the bootloader but not a power loss.
+ *
+ * The region sits between the app stack and the top of RAM used by the bootloader stack and the
+ * double tap flag, a stack overflow grows away from it.
+ */
+
+use core::arch::asm;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::ptr::addr_of_mut;
+
+use cortex_m::interrupt::free as disable_interrupts;
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::ExceptionFrame;
+use heapless::String;
+
+const CRASH_MAGIC: u32 = 0x9a3c_71e5;
+const STACK_WORDS: usize = 16;
+const MESSAGE_SIZE: usize = 96;
+const FILE_SIZE: usize = 48;
+
+// end of the app stack, the snapshot never reads past it
+const STACK_END: u32 = 0x2000_0000 + 31 * 1024;
+
+#[repr(u32)]
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum CrashKind {
+    Panic = 1,
+    HardFault = 2,
+}
+
+#[repr(C)]
+pub struct CrashReport {
+    magic: u32,
+    kind: u32,
+    shown: u32, // notice already shown to the player
+    pc: u32,
+    lr: u32,
+    sp: u32,
+    line: u32,
+    stack: [u32; STACK_WORDS],
+    file_len: u32,
+    file: [u8; FILE_SIZE],
+    message_len: u32,
+    message: [u8; MESSAGE_SIZE],
+}
+
+#[link_section = ".crash_report"]
+static mut CRASH_REPORT: core::mem::MaybeUninit<CrashReport> = core::mem::MaybeUninit::uninit();
+
+// truncates instead of failing, a cut off message is better than none
+struct FixedWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Write for FixedWriter<'a> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut count = s.len().min(self.buffer.len() - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        return Ok(());
+    }
+}
+
+fn report() -> &'static mut CrashReport {
+    // plain integers and arrays, any bit pattern is a valid value
+    return unsafe { (*addr_of_mut!(CRASH_REPORT)).assume_init_mut() };
+}
+
+fn capture(kind: CrashKind, pc: u32, lr: u32, sp: u32) -> &'static mut CrashReport {
+    let report = report();
+
+    report.magic = 0;
+    report.kind = kind as u32;
+    report.shown = 0;
+    report.pc = pc;
+    report.lr = lr;
+    report.sp = sp;
+    report.line = 0;
+    report.file_len = 0;
+    report.message_len = 0;
+
+    for i in 0..STACK_WORDS {
+        let address = sp + (i * 4) as u32;
+        report.stack[i] = if sp >= 0x2000_0000 && address < STACK_END {
+            unsafe { core::ptr::read_volatile(address as *const u32) }
+        } else {
+            0
+        };
+    }
+
+    return report;
+}
+
+fn reset() -> ! {
+    // let the write reach RAM before the reset request
+    cortex_m::asm::dsb();
+    SCB::sys_reset();
+}
+
+pub fn record_panic(info: &PanicInfo) -> ! {
+    disable_interrupts(|_| {
+        // no exception frame for a panic, registers are read in the handler itself
+        let (pc, lr, sp): (u32, u32, u32);
+        unsafe {
+            asm!("mov {}, pc", out(reg) pc);
+            asm!("mov {}, lr", out(reg) lr);
+            asm!("mov {}, sp", out(reg) sp);
+        }
+        let report = capture(CrashKind::Panic, pc, lr, sp);
+
+        if let Some(location) = info.location() {
+            // keep the end of long paths, the file name is the useful part
+            let file = location.file().as_bytes();
+            let start = file.len().saturating_sub(FILE_SIZE);
+            let file = &file[start..];
+            report.file[..file.len()].copy_from_slice(file);
+            report.file_len = file.len() as u32;
+            report.line = location.line();
+        }
+
+        let mut writer = FixedWriter {
+            buffer: &mut report.message,
+            len: 0,
+        };
+        write!(writer, "{}", info.message()).ok();
+        report.message_len = writer.len as u32;
+
+        report.magic = CRASH_MAGIC;
+    });
+
+    reset();
+}
+
+pub fn record_hard_fault(frame: &ExceptionFrame) -> ! {
+    // stacked registers are the state at the faulting instruction, the snapshot starts
+    // right above the exception frame
+    let sp = frame as *const ExceptionFrame as u32 + core::mem::size_of::<ExceptionFrame>() as u32;
+    let report = capture(CrashKind::HardFault, frame.pc(), frame.lr(), sp);
+    report.magic = CRASH_MAGIC;
+
+    reset();
+}
+
+fn valid_report() -> Option<&'static mut CrashReport> {
+    let report = report();
+    if report.magic != CRASH_MAGIC {
+        return None;
+    }
+    return Some(report);
+}
+
+// true once per crash, the report itself stays available to the host
+pub fn take_notice() -> bool {
+    match valid_report() {
+        Some(report) if report.shown == 0 => {
+            report.shown = 1;
+            return true;
+        }
+        _ => return false,
+    }
+}
+
+// plain text report for the host, empty if there was no crash since power on
+pub fn format(out: &mut String<512>) {
+    let report = match valid_report() {
+        Some(report) => report,
+        None => return,
+    };
+
+    match report.kind {
+        kind if kind == CrashKind::Panic as u32 => {
+            let file_len = (report.file_len as usize).min(FILE_SIZE);
+            let message_len = (report.message_len as usize).min(MESSAGE_SIZE);
+            let file = core::str::from_utf8(&report.file[..file_len]).unwrap_or("?");
+            let message = core::str::from_utf8(&report.message[..message_len]).unwrap_or("?");
+            write!(out, "panic at {}:{}\n{}\n", file, report.line, message).ok();
+        }
+        _ => {
+            write!(out, "hard fault\n").ok();
+        }
+    }
+
+    write!(
+        out,
+        "pc {:08x} lr {:08x} sp {:08x}\n",
+        report.pc, report.lr, report.sp
+    )
+    .ok();
+
+    for (i, word) in report.stack.iter().enumerate() {
+        let separator = if i % 4 == 3 { '\n' } else { ' ' };
+        write!(out, "{:08x}{}", word, separator).ok();
+    }
+}
@@ -1,47 +1,41 @@
+/*!
+ * TODO: spi variant for sharing, embedded hal has no generic dmac support
+ *
+ * Drawing goes to the frame buffer, update packs the lines that changed into the send buffer
+ * while the previous transfer is done with it. Nothing is sent when nothing changed.
+ */
+
 use atsamd_hal::dmac::*;
 
 use bsp::SharpCs;
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::Pixel;
 use pawbsp as bsp;
 
 use bsp::ehal::digital::v2::OutputPin;
 use bsp::pac::interrupt;
 use bsp::SharpSpi;
+use pawdevicetraits::sharp_lines::*;
 use pawdevicetraits::DisplayDevice;
-
-/**
- * TODO: spi variant for sharing, embedded hal has no generic dmac support
- */
-
-#[repr(C)]
-#[derive(Copy, Clone)]
-#[repr(packed)]
-pub struct Line128 {
-    id: u8,
-    col: [u8; 16],
-    eol: u8,
-}
-#[repr(C)]
-#[repr(packed)]
-pub struct Buffer128x128 {
-    command: u8,
-    row: [Line128; 128],
-    eoc: u16,
-}
+use pawdevicetraits::DisplayUpdateStats;
 
 const SET: [u8; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
 const CLR: [u8; 8] = [!1, !2, !4, !8, !16, !32, !64, !128];
 
-// const LENGTH: usize = 1 + 128 * (16 + 2) + 1;
-const LENGTH: usize = core::mem::size_of::<Buffer128x128>();
-
-static mut DISP_BUFFER_WRITE: [u8; LENGTH] = [0; LENGTH];
-static mut DISP_BUFFER_SEND: [u8; LENGTH] = [0; LENGTH];
+static mut DISP_FRAME: [SharpLine; SHARP_LINES] = [[0; SHARP_LINE_BYTES]; SHARP_LINES];
+// what the panel currently shows
+static mut DISP_PANEL: [SharpLine; SHARP_LINES] = [[0; SHARP_LINE_BYTES]; SHARP_LINES];
+static mut DISP_BUFFER_SEND: [u8; SHARP_PACKET_MAX] = [0; SHARP_PACKET_MAX];
 static mut DISP_CS: Option<bsp::SharpCs> = None;
 
-type TransferBuffer = &'static mut [u8; LENGTH];
+// microsecond clock for timing transfers, set once the system timer is running
+static mut DISP_CLOCK: Option<fn() -> u32> = None;
+static mut DISP_TRANSFER_START: u32 = 0;
+static mut DISP_TRANSFER_END: u32 = 0;
+
+type TransferBuffer = &'static mut [u8];
 
 static mut DISP_TRANSFER: Option<
     Transfer<Channel<Ch0, Busy>, BufferPair<TransferBuffer, bsp::SharpSpi>>,
@@ -49,6 +43,11 @@ static mut DISP_TRANSFER: Option<
 
 pub struct LS013B7DH03 {
     rotation: usize,
+    dirty: DirtyLines,
+    stats: DisplayUpdateStats,
+    // transfer in flight, stats are taken once it has finished
+    sending_lines: usize,
+    sending_length: usize,
 }
 
 impl DrawTarget for LS013B7DH03 {
@@ -64,29 +63,85 @@ impl DrawTarget for LS013B7DH03 {
             // pixels without returning an error or causing a panic.
             let x = coord.x;
             let y = coord.y;
+            if x < 0 || y < 0 || x >= 64 || y >= 64 {
+                continue;
+            }
             self.draw_pixel(x as u8, y as u8, color.is_on());
         }
 
         Ok(())
     }
 
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        // coalesce each row into spans, off screen pixels end a span
+        let mut colors = colors.into_iter();
+        for y in area.rows() {
+            let mut span: Option<(i32, u8, BinaryColor)> = None; // start x, length, color
+
+            for x in area.columns() {
+                let color = match colors.next() {
+                    Some(color) => color,
+                    None => break,
+                };
+
+                let visible = x >= 0 && x < 64 && y >= 0 && y < 64;
+                span = match span {
+                    Some((start, length, c)) if visible && c == color => Some((start, length + 1, c)),
+                    _ => {
+                        if let Some((start, length, c)) = span {
+                            self.draw_span(start as u8, y as u8, length, c.is_on());
+                        }
+                        if visible {
+                            Some((x, 1, color))
+                        } else {
+                            None
+                        }
+                    }
+                };
+            }
+
+            if let Some((start, length, c)) = span {
+                self.draw_span(start as u8, y as u8, length, c.is_on());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&Rectangle::new(Point::zero(), Size::new(64, 64)));
+        if area.size.width == 0 || area.size.height == 0 {
+            return Ok(());
+        }
+
+        self.fill_rect(
+            area.top_left.x as u8,
+            area.top_left.y as u8,
+            area.size.width as u8,
+            area.size.height as u8,
+            color.is_on(),
+        );
+        Ok(())
+    }
+
     fn clear(&mut self, color: Self::Color) -> core::result::Result<(), Self::Error> {
         // self.cs.set_high().unwrap();
         // self.spi.as_mut().unwrap().write(&[0x04, 0x00]).unwrap();
 
-        let b: &mut Buffer128x128;
-        unsafe {
-            b = core::mem::transmute(&mut DISP_BUFFER_WRITE);
-        }
+        let frame = unsafe { &mut DISP_FRAME };
 
         let mut c: u8 = 0xFF;
         if color == BinaryColor::On {
             c = 0;
         }
 
-        for y in b.row.iter_mut() {
-            for x in y.col.iter_mut() {
-                *x = c;
+        for (y, line) in frame.iter_mut().enumerate() {
+            if line.iter().any(|x| *x != c) {
+                *line = [c; SHARP_LINE_BYTES];
+                self.dirty.mark(y);
             }
         }
 
@@ -103,26 +158,65 @@ impl OriginDimensions for LS013B7DH03 {
 
 impl pawdevicetraits::DisplayDevice for LS013B7DH03 {
     fn draw_pixel(&mut self, x: u8, y: u8, color: bool) {
-        // assert!(x < 64 && y < 64);
+        self.fill_rect(x, y, 1, 1, color);
+    }
+
+    // logical pixels are 2x2 on the panel, any rotation of a rectangle is a rectangle of panel
+    // lines, each line is written with byte masks
+    fn fill_rect(&mut self, x: u8, y: u8, width: u8, height: u8, color: bool) {
+        if x >= 64 || y >= 64 || width == 0 || height == 0 {
+            return;
+        }
+
+        // panel sub pixel bounds, inclusive
+        let x0 = x as usize * 2;
+        let y0 = y as usize * 2;
+        let x1 = (x as usize + width as usize).min(64) * 2 - 1;
+        let y1 = (y as usize + height as usize).min(64) * 2 - 1;
+
+        // same mapping as draw_sub_pixel
+        let (px0, px1, py0, py1) = match self.rotation {
+            1 => (127 - y1, 127 - y0, x0, x1),
+            2 => (127 - x1, 127 - x0, 127 - y1, 127 - y0),
+            3 => (y0, y1, 127 - x1, 127 - x0),
+            _ => (x0, x1, y0, y1),
+        };
 
-        // TODO can be optimized with memset
-        self.draw_sub_pixel(x * 2, y * 2, color);
-        self.draw_sub_pixel(x * 2 + 1, y * 2, color);
-        self.draw_sub_pixel(x * 2, y * 2 + 1, color);
-        self.draw_sub_pixel(x * 2 + 1, y * 2 + 1, color);
+        let frame = unsafe { &mut DISP_FRAME };
+        for line in py0..=py1 {
+            if fill_line_bits(&mut frame[line], px0, px1, color) {
+                self.dirty.mark(line);
+            }
+        }
     }
 
     fn update(&mut self) -> bool {
         unsafe {
             if DISP_TRANSFER.as_mut().unwrap().complete() {
-                // swap send and write buffers
-                core::mem::swap(&mut DISP_BUFFER_WRITE, &mut DISP_BUFFER_SEND);
+                self.take_transfer_stats();
+
+                let (length, lines) = pack_dirty_lines(
+                    &DISP_FRAME,
+                    &mut DISP_PANEL,
+                    &mut self.dirty,
+                    &mut DISP_BUFFER_SEND,
+                    false,
+                );
+
+                self.sending_lines = lines;
+                self.sending_length = length;
 
+                // panel already shows this frame
+                if length == 0 {
+                    return true;
+                }
+
+                DISP_TRANSFER_START = now_us();
                 DISP_CS.as_mut().unwrap().set_high().unwrap();
                 DISP_TRANSFER
                     .as_mut()
                     .unwrap()
-                    .recycle_destination(&mut DISP_BUFFER_SEND)
+                    .recycle_destination(&mut DISP_BUFFER_SEND[..length])
                     .unwrap();
                 return true;
             } else {
@@ -131,27 +225,31 @@ impl pawdevicetraits::DisplayDevice for LS013B7DH03 {
             }
         };
     }
+
+    fn set_rotation(&mut self, rotation: u8) {
+        // panel rotation 1 is the normal orientation of the case
+        self.rotation = (rotation as usize + 1) % 4;
+    }
+
+    fn update_stats(&self) -> DisplayUpdateStats {
+        return self.stats;
+    }
 }
 
 impl LS013B7DH03 {
     pub fn new(spi: SharpSpi, mut dmac_channel: Channel<Ch0, Ready>, cs: SharpCs) -> Self {
-        let mut a: &mut Buffer128x128;
-        let mut b: &mut Buffer128x128;
-
-        unsafe {
-            a = core::mem::transmute(&mut DISP_BUFFER_SEND);
-            b = core::mem::transmute(&mut DISP_BUFFER_WRITE);
-        }
-
-        a.command = 0x01;
-        b.command = 0x01;
-        for i in 0..128 {
-            a.row[i].id = (i + 1) as u8;
-            b.row[i].id = (i + 1) as u8;
-
-            a.row[i].eol = 0x00;
-            b.row[i].eol = 0x00;
-        }
+        // panel contents are unknown at power on, the first transfer sends every line
+        let mut dirty = DirtyLines::new();
+        dirty.mark_all();
+        let (length, _) = unsafe {
+            pack_dirty_lines(
+                &DISP_FRAME,
+                &mut DISP_PANEL,
+                &mut dirty,
+                &mut DISP_BUFFER_SEND,
+                true,
+            )
+        };
 
         unsafe {
             DISP_CS = Some(cs);
@@ -168,14 +266,46 @@ impl LS013B7DH03 {
             .as_mut()
             .enable_interrupts(InterruptFlags::new().with_tcmpl(true));
 
-        let xfer =
-            unsafe { Transfer::new_unchecked(dmac_channel, &mut DISP_BUFFER_SEND, spi, false) };
+        let xfer = unsafe {
+            Transfer::new_unchecked(dmac_channel, &mut DISP_BUFFER_SEND[..length], spi, false)
+        };
         let xfer = Some(xfer.begin(TriggerSource::SERCOM4_TX, TriggerAction::BEAT));
 
         unsafe {
             DISP_TRANSFER = xfer;
         }
-        Self { rotation: 1 }
+        Self {
+            rotation: 1,
+            dirty,
+            stats: DisplayUpdateStats::default(),
+            // the clock is not set yet, the boot transfer is not timed
+            sending_lines: 0,
+            sending_length: 0,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: fn() -> u32) {
+        unsafe { DISP_CLOCK = Some(clock) };
+    }
+
+    // the previous transfer has finished, the dma interrupt took its end time
+    fn take_transfer_stats(&mut self) {
+        if self.sending_length == 0 {
+            self.stats.lines = 0;
+            self.stats.transfer_us = 0;
+            return;
+        }
+
+        let us = unsafe { DISP_TRANSFER_END.wrapping_sub(DISP_TRANSFER_START) };
+        self.stats.lines = self.sending_lines as u8;
+        self.stats.transfer_us = us;
+        self.stats.full_transfer_us = (us as u64 * SHARP_PACKET_MAX as u64 / self.sending_length as u64) as u32;
+        self.sending_length = 0;
+    }
+
+    // block until the pending frame has been sent, needed before entering sleep
+    pub fn wait_transfer(&mut self) {
+        unsafe { while !DISP_TRANSFER.as_mut().unwrap().complete() {} }
     }
 
     pub fn set_rotation(&mut self, rot: usize) {
@@ -212,15 +342,17 @@ impl LS013B7DH03 {
             _ => {} // 270
         }
 
-        let b: &mut Buffer128x128;
-        unsafe {
-            b = core::mem::transmute(&mut DISP_BUFFER_WRITE);
-        }
-        // let i: usize = (y * 128 + x) / 8;
-        if color {
-            b.row[y].col[x / 8] &= CLR[(x & 7) as usize];
+        let frame = unsafe { &mut DISP_FRAME };
+        let byte = &mut frame[y][x / 8];
+        let value = if color {
+            *byte & CLR[(x & 7) as usize]
         } else {
-            b.row[y].col[x / 8] |= SET[(x & 7) as usize];
+            *byte | SET[(x & 7) as usize]
+        };
+
+        if value != *byte {
+            *byte = value;
+            self.dirty.mark(y);
         }
     }
 }
@@ -235,7 +367,12 @@ fn DMAC() {
 
     if _channel > 1 {
         unsafe {
+            DISP_TRANSFER_END = now_us();
             DISP_CS.as_mut().unwrap().set_low().unwrap();
         }
     }
 }
+
+fn now_us() -> u32 {
+    return unsafe { DISP_CLOCK.map_or(0, |clock| clock()) };
+}
@@ -3,7 +3,11 @@
 
 pub mod battery_monitor;
 pub mod buttons;
+#[cfg(feature = "rt")]
+pub mod crash_report;
 pub mod display;
+pub mod reset_cause;
+pub mod rtc_clock;
 pub mod sleepy_dog;
 pub mod sys_timer;
 pub mod tone;
@@ -0,0 +1,63 @@
+/*!
+ * PM RCAUSE holds the source of the last reset until the next one, reading it does not clear it.
+ * Only one bit is set after a reset, checked from the most to the least specific.
+ *
+ * Resets are counted next to the crash report in the RAM region the runtime does not initialize
+ * (see CRASH in memory.x), so counting does not write flash on every boot. The counts survive
+ * resets but start over after a power loss.
+ */
+
+use core::ptr::addr_of_mut;
+
+use atsamd_hal::pac::PM;
+use pawdevicetraits::ResetCause;
+use pawdevicetraits::RESET_CAUSE_COUNT;
+
+const COUNTERS_MAGIC: u32 = 0x5e7c_0a11;
+
+#[repr(C)]
+struct ResetCounters {
+    magic: u32,
+    counts: [u16; RESET_CAUSE_COUNT],
+}
+
+#[link_section = ".crash_report"]
+static mut RESET_COUNTERS: core::mem::MaybeUninit<ResetCounters> = core::mem::MaybeUninit::uninit();
+
+pub fn read_reset_cause() -> ResetCause {
+    // read only access, PM is owned by the clock setup
+    let rcause = unsafe { &*PM::ptr() }.rcause.read();
+
+    if rcause.wdt().bit_is_set() {
+        return ResetCause::Watchdog;
+    }
+    if rcause.syst().bit_is_set() {
+        return ResetCause::System;
+    }
+    if rcause.bod12().bit_is_set() || rcause.bod33().bit_is_set() {
+        return ResetCause::Brownout;
+    }
+    if rcause.ext().bit_is_set() {
+        return ResetCause::External;
+    }
+    if rcause.por().bit_is_set() {
+        return ResetCause::PowerOn;
+    }
+    return ResetCause::Unknown;
+}
+
+// counts the reset that started this boot, returns the counts per cause since the last power loss
+pub fn count_reset(cause: ResetCause) -> [u16; RESET_CAUSE_COUNT] {
+    // plain integers, any bit pattern is a valid value
+    let counters = unsafe { (*addr_of_mut!(RESET_COUNTERS)).assume_init_mut() };
+
+    // garbage after a power loss
+    if counters.magic != COUNTERS_MAGIC {
+        counters.magic = COUNTERS_MAGIC;
+        counters.counts = [0; RESET_CAUSE_COUNT];
+    }
+
+    let count = &mut counters.counts[cause as usize];
+    *count = count.saturating_add(1);
+    return counters.counts;
+}
@@ -0,0 +1,46 @@
+/*!
+ * RTC calendar mode wrapper, converts to/from seconds since 2000-01-01 00:00:00
+ * keeps running in standby from the 1024 hz GCLK2 (XOSC32K)
+ */
+
+use atsamd_hal::rtc::{ClockMode, Datetime, Rtc};
+use pawdevicetraits::calendar::CalendarTime;
+
+pub struct RtcClock {
+    rtc: Rtc<ClockMode>,
+}
+
+impl pawdevicetraits::RtcDevice for RtcClock {
+    fn get_time(&self) -> u32 {
+        let time = self.rtc.current_time();
+
+        return CalendarTime {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            hours: time.hours,
+            minutes: time.minutes,
+            seconds: time.seconds,
+        }
+        .to_seconds();
+    }
+
+    fn set_time(&mut self, seconds: u32) {
+        let time = CalendarTime::from_seconds(seconds);
+
+        self.rtc.set_time(Datetime {
+            seconds: time.seconds,
+            minutes: time.minutes,
+            hours: time.hours,
+            day: time.day,
+            month: time.month,
+            year: time.year,
+        });
+    }
+}
+
+impl RtcClock {
+    pub fn new(rtc: Rtc<ClockMode>) -> Self {
+        Self { rtc }
+    }
+}
@@ -107,6 +107,15 @@ impl pawdevicetraits::WatchdogDevice for SleepyDog
         asm::dsb();
         asm::wfi();
     }
+
+    fn deep_sleep(&mut self) {
+        disable_interrupts(|_| {
+            self.disable();
+            self.wdt.intenclr.write(|w| w.ew().set_bit());
+        });
+        asm::dsb();
+        asm::wfi();
+    }
 }
 
 impl SleepyDog {
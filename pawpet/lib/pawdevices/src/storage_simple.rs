@@ -91,6 +91,7 @@ static mut FLASH_ACCESS: Option<Flash<bsp::FlashSpi, bsp::FlashCs>> = None;
 static mut IMAGE_CACHE_BUFFER: [u8; 8096] = [0; 8096];
 
 const EMPTY_FLAG: u32 = 0xFFFFFFFF; // all 1's
+const SAVE_MAGIC: u32 = 0x45564153; // "SAVE"
 const INVALID_FLAG: u32 = 0x0000AA99;
 const VALID_FLAG: u32 = 0x0000AA9F;
 
@@ -378,6 +379,117 @@ impl SimpleFilesystem {
         return Ok(());
     }
 
+    // save files use one block per save in the reserved blocks after the data blocks, written as a ring buffer
+    // [header page: magic, sequence, length, crc] [data pages]
+    // header is written last so a save interrupted by power loss is never picked as the latest
+    pub fn save_block_count(&self) -> u32 {
+        return 512 - self.total_blocks;
+    }
+
+    pub fn max_save_size(&self) -> usize {
+        return (self.block_size - self.write_size) as usize;
+    }
+
+    // returns (sequence, length, crc) of a committed save header
+    fn read_save_header(&self, block: u32) -> Option<(u32, usize, u32)> {
+        let mut header: [u8; 16] = [0; 16];
+        SimpleFilesystem::read(block * self.block_size, &mut header);
+
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let sequence = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let length = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[12..16].try_into().unwrap());
+
+        if magic != SAVE_MAGIC || length > self.max_save_size() {
+            return None;
+        }
+        return Some((sequence, length, crc));
+    }
+
+    // newest committed save header regardless of data integrity, returns (block, sequence)
+    pub fn find_latest_save_slot(&self) -> Option<(u32, u32)> {
+        let mut latest: Option<(u32, u32)> = None;
+
+        for b in self.total_blocks..self.total_blocks + self.save_block_count() {
+            if let Some((sequence, _, _)) = self.read_save_header(b) {
+                if latest.is_none() || latest.unwrap().1 < sequence {
+                    latest = Some((b, sequence));
+                }
+            }
+        }
+        return latest;
+    }
+
+    // finds the newest save with a valid crc, returns (block, sequence, length)
+    pub fn find_latest_save(&self, buffer: &mut [u8]) -> Option<(u32, u32, usize)> {
+        let mut latest: Option<(u32, u32, usize)> = None;
+
+        for b in self.total_blocks..self.total_blocks + self.save_block_count() {
+            let header = self.read_save_header(b);
+            if header.is_none() {
+                continue;
+            }
+
+            let (sequence, length, crc) = header.unwrap();
+            if length > buffer.len() || (latest.is_some() && latest.unwrap().1 >= sequence) {
+                continue;
+            }
+
+            SimpleFilesystem::read(b * self.block_size + self.write_size, &mut buffer[0..length]);
+            if self.crc_hasher.checksum(&buffer[0..length]) == crc {
+                latest = Some((b, sequence, length));
+            } else {
+                debug_rprintln!("	save block {} failed crc check", b);
+            }
+        }
+
+        return latest;
+    }
+
+    pub fn write_save(&self, block: u32, sequence: u32, data: &[u8]) -> Result<(), FileWriteError> {
+        if data.len() > self.max_save_size() {
+            return Err(FileWriteError::FilesystemFull);
+        }
+
+        debug_rprintln!("	writing save {} @block {} len {}", sequence, block, data.len());
+
+        let addr = block * self.block_size;
+        SimpleFilesystem::erase(block);
+
+        let mut i: usize = 0;
+        while i < data.len() {
+            let end = (i + self.write_size as usize).min(data.len());
+            SimpleFilesystem::write(addr + self.write_size + i as u32, &data[i..end], end - i);
+            i = end;
+        }
+
+        // read back before committing the header
+        let crc = self.crc_hasher.checksum(data);
+        let mut read_digest = self.crc_hasher.digest();
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut i: usize = 0;
+        while i < data.len() {
+            let end = (i + buffer.len()).min(data.len());
+            SimpleFilesystem::read(addr + self.write_size + i as u32, &mut buffer[0..end - i]);
+            read_digest.update(&buffer[0..end - i]);
+            i = end;
+        }
+
+        if read_digest.finalize() != crc {
+            debug_rprintln!("	BAD SAVE CHECKSUM");
+            return Err(FileWriteError::ChecksumFailed);
+        }
+
+        let mut header: [u8; 16] = [0; 16];
+        header[0..4].copy_from_slice(&SAVE_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        header[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&crc.to_le_bytes());
+        SimpleFilesystem::write(addr, &header, header.len());
+
+        return Ok(());
+    }
+
     pub fn read_file(&self, index_entry: &IndexBlockEntry, data: &mut [u8]) -> bool {
         SimpleFilesystem::read(index_entry.addr, data);
         let data_crc = self.crc_hasher.checksum(data);
@@ -519,6 +631,8 @@ pub struct SimpleFlashStorage {
     cache_entries: [Option<CacheEntry>; 32],
     cache_offset: usize,
     fs: SimpleFilesystem,
+    // block and sequence of the newest save
+    save_slot: Option<(u32, u32)>,
 }
 
 impl SimpleFlashStorage {
@@ -528,11 +642,13 @@ impl SimpleFlashStorage {
         }
         let mut fs = SimpleFilesystem::new();
         fs.mount();
+        let save_slot = fs.find_latest_save_slot();
 
         Self {
             cache_entries: Default::default(),
             cache_offset: 0,
             fs,
+            save_slot,
         }
     }
 }
@@ -652,6 +768,42 @@ impl StorageDevice for SimpleFlashStorage {
         return Ok(());
     }
 
+    fn load_save(&mut self, data: &mut [u8]) -> usize {
+        match self.fs.find_latest_save(data) {
+            Some((block, sequence, length)) => {
+                debug_rprintln!("\tloaded save {} @block {}", sequence, block);
+                self.save_slot = Some((block, sequence));
+                return length;
+            }
+            None => {
+                debug_rprintln!("no save found");
+                return 0;
+            }
+        }
+    }
+
+    fn write_save(&mut self, data: &[u8]) -> Result<(), FileWriteError> {
+        let first = self.fs.total_blocks;
+        let count = self.fs.save_block_count();
+
+        let (mut block, mut sequence) = match self.save_slot {
+            Some((b, seq)) => (first + (b - first + 1) % count, seq + 1),
+            None => (first, 0),
+        };
+
+        // skip over bad blocks, old save is kept until a new one has been committed
+        for _ in 0..2 {
+            if self.fs.write_save(block, sequence, data).is_ok() {
+                self.save_slot = Some((block, sequence));
+                return Ok(());
+            }
+            block = first + (block - first + 1) % count;
+            sequence += 1;
+        }
+
+        return Err(FileWriteError::ChecksumFailed);
+    }
+
     fn format_storage(&mut self) {
         self.fs.format();
         self.clear_cache();
@@ -17,6 +17,8 @@ pub struct SysTimer {
     _sysclock: Hertz,
     syst: SYST,
     total_ticks: AtomicU32,
+    uptime_ms: AtomicU32, // not reset by start, for measuring across frames
+    cycles_per_us: u32,
 
     tick_start: u32,
     tick_stop: u32,
@@ -85,6 +87,8 @@ impl pawdevicetraits::SysTimerDevice for SysTimer {
         // self.total_ticks += 1;
         let ticks = self.total_ticks.load(Ordering::Relaxed);
         self.total_ticks.store(ticks + 1, Ordering::Relaxed);
+        let uptime = self.uptime_ms.load(Ordering::Relaxed);
+        self.uptime_ms.store(uptime.wrapping_add(1), Ordering::Relaxed);
     }
 }
 
@@ -111,11 +115,26 @@ impl SysTimer {
             syst,
             _sysclock: sysclock,
             total_ticks: AtomicU32::new(0),
+            uptime_ms: AtomicU32::new(0),
+            cycles_per_us: sysclock.to_Hz() / 1_000_000,
             tick_start: 0,
             tick_stop: 0,
         }
     }
 
+    // microseconds while the timer has been enabled, wraps after ~71 minutes
+    // SysTick counts down from the reload value once per ms tick
+    pub fn now_us(&self) -> u32 {
+        loop {
+            let ms = self.uptime_ms.load(Ordering::Relaxed);
+            let elapsed = SYST::get_reload() - SYST::get_current();
+            // a tick landed in between, the counter already wrapped
+            if ms == self.uptime_ms.load(Ordering::Relaxed) {
+                return ms.wrapping_mul(1000).wrapping_add(elapsed / self.cycles_per_us);
+            }
+        }
+    }
+
     pub fn disable(&mut self) {
         unsafe { self.syst.csr.write(0) }
     }
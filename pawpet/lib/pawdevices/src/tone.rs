@@ -1,6 +1,9 @@
 
 
+use core::cell::Cell;
 use pawbsp as bsp;
+use pawdevicetraits::tone_pwm::TonePwm;
+use pawdevicetraits::VOLUME_MAX;
 
 pub static TONE_NOTES: [u32; 80] = [
     55, 58, 62, 65, 69, 73, 78, 82, 87, 93, 98, 104, 110, 117, 123, 131, 139, 147, 156, 165, 175,
@@ -12,53 +15,75 @@ pub static TONE_NOTES: [u32; 80] = [
 pub struct Tone {
     _tc: bsp::pac::TC4,
     _beeper: bsp::BeeperE,
-    _div: u32,
+    clock_hz: u32,
+    muted: Cell<bool>,
+    volume: Cell<u8>,
 }
 
 impl pawdevicetraits::ToneDevice for Tone
 {
     fn tone(&self, freq: u32) {
-        let tcc = self._tc.count16();
-        if freq == 0 {
-            self.no_tone();
-            return;
-        }
-        tcc.cc[0].write(|w| unsafe { w.cc().bits((self._div / freq) as u16) });
-        tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        let tc = self._tc.count8();
+        let pwm = match TonePwm::new(self.clock_hz, freq, self.volume.get()) {
+            Some(pwm) if !self.muted.get() => pwm,
+            _ => {
+                self.no_tone();
+                return;
+            }
+        };
+
+        // the prescaler can only be changed while the timer is disabled
+        tc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while tc.status.read().syncbusy().bit_is_set() {}
+        tc.ctrla.modify(|_, w| w.prescaler().bits(pwm.prescaler));
+        tc.per.write(|w| unsafe { w.per().bits(pwm.top) });
+        tc.cc[0].write(|w| unsafe { w.cc().bits(pwm.duty) });
+        tc.ctrla.modify(|_, w| w.enable().set_bit());
     }
 
     fn no_tone(&self) {
-        let tcc = self._tc.count16();
-        tcc.cc[0].write(|w| unsafe { w.cc().bits(0) });
+        let tc = self._tc.count8();
+        tc.cc[0].write(|w| unsafe { w.cc().bits(0) });
+
+        tc.ctrla.modify(|_, w| w.enable().clear_bit());
+    }
+
+    fn set_muted(&self, muted: bool) {
+        self.muted.set(muted);
+        if muted {
+            self.no_tone();
+        }
+    }
 
-        tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+    fn set_volume(&self, volume: u8) {
+        self.volume.set(volume);
     }
 }
 
 impl Tone {
+    // the clock needs to be slow enough for the 8 bit period of the lowest note, 6 MHz works
     pub fn new(
         beeper: bsp::BeeperE,
         tc4: bsp::pac::TC4,
         clock: &atsamd_hal::clock::Tc4Tc5Clock,
         pm: &mut bsp::pac::PM,
     ) -> Tone {
-        let div = clock.freq().to_Hz() / 64;
-        let tcc = tc4.count16();
+        let tc = tc4.count8();
 
         pm.apbcmask.modify(|_, w| w.tc4_().set_bit());
-        tcc.ctrla.write(|w| w.swrst().set_bit());
-        while tcc.ctrla.read().bits() & 1 != 0 {}
-        tcc.ctrla.modify(|_, w| w.enable().clear_bit());
-        tcc.ctrla.modify(|_, w| w.prescaler().div64());
-        tcc.ctrla.modify(|_, w| w.wavegen().mfrq());
-        tcc.cc[0].write(|w| unsafe { w.cc().bits(0) });
-        tcc.cc[1].write(|w| unsafe { w.cc().bits(0) });
-        tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        tc.ctrla.write(|w| w.swrst().set_bit());
+        while tc.ctrla.read().bits() & 1 != 0 {}
+        tc.ctrla.modify(|_, w| w.mode().count8());
+        tc.ctrla.modify(|_, w| w.wavegen().npwm());
+        tc.cc[0].write(|w| unsafe { w.cc().bits(0) });
+        tc.cc[1].write(|w| unsafe { w.cc().bits(0) });
 
         Self {
-            _div: div,
+            clock_hz: clock.freq().to_Hz(),
             _tc: tc4,
             _beeper: beeper,
+            muted: Cell::new(false),
+            volume: Cell::new(VOLUME_MAX),
         }
     }
 }
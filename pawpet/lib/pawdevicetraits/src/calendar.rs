@@ -0,0 +1,123 @@
+/*!
+ * Calendar date conversion for the RTC, times are seconds since 2000-01-01 00:00:00.
+ * The SAMD21 RTC year field counts from 2000 and only reaches 2063, so every fourth year is
+ * a leap year without the century rule.
+ */
+
+pub const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+const DAYS_PER_MONTH: [u32; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct CalendarTime {
+    pub year: u8, // since 2000
+    pub month: u8, // 1 - 12
+    pub day: u8, // 1 - 31
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    if month == 2 && is_leap_year(year) {
+        return 29;
+    }
+    DAYS_PER_MONTH[(month - 1) as usize]
+}
+
+impl CalendarTime {
+    pub fn to_seconds(&self) -> u32 {
+        let mut days: u32 = 0;
+        for y in 0..self.year as u32 {
+            days += if is_leap_year(y) { 366 } else { 365 };
+        }
+        for m in 1..self.month as u32 {
+            days += days_in_month(self.year as u32, m);
+        }
+        days += self.day as u32 - 1;
+
+        days * SECONDS_PER_DAY + self.hours as u32 * 3600 + self.minutes as u32 * 60 + self.seconds as u32
+    }
+
+    pub fn from_seconds(seconds: u32) -> Self {
+        let mut days = seconds / SECONDS_PER_DAY;
+        let day_seconds = seconds % SECONDS_PER_DAY;
+
+        let mut year: u32 = 0;
+        loop {
+            let year_days = if is_leap_year(year) { 366 } else { 365 };
+            if days < year_days {
+                break;
+            }
+            days -= year_days;
+            year += 1;
+        }
+
+        let mut month: u32 = 1;
+        while days >= days_in_month(year, month) {
+            days -= days_in_month(year, month);
+            month += 1;
+        }
+
+        Self {
+            year: year as u8,
+            month: month as u8,
+            day: (days + 1) as u8,
+            hours: (day_seconds / 3600) as u8,
+            minutes: ((day_seconds / 60) % 60) as u8,
+            seconds: (day_seconds % 60) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u8, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> CalendarTime {
+        CalendarTime {
+            year,
+            month,
+            day,
+            hours,
+            minutes,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn known_dates() {
+        let dates = [
+            (date(0, 1, 1, 0, 0, 0), 0),
+            (date(0, 1, 1, 23, 59, 59), SECONDS_PER_DAY - 1),
+            // 2000 is a leap year
+            (date(0, 2, 29, 0, 0, 0), 59 * SECONDS_PER_DAY),
+            (date(0, 3, 1, 0, 0, 0), 60 * SECONDS_PER_DAY),
+            (date(1, 1, 1, 0, 0, 0), 366 * SECONDS_PER_DAY),
+            // 2001 is not
+            (date(1, 3, 1, 0, 0, 0), (366 + 59) * SECONDS_PER_DAY),
+            (date(24, 10, 19, 12, 30, 5), 782_656_205),
+        ];
+
+        for (time, seconds) in dates {
+            assert_eq!(time.to_seconds(), seconds, "{:?}", time);
+            assert_eq!(CalendarTime::from_seconds(seconds), time);
+        }
+    }
+
+    #[test]
+    fn round_trip_across_the_rtc_range() {
+        // every 5 days and a bit until 2063
+        let mut seconds = 0;
+        while seconds < 63 * 365 * SECONDS_PER_DAY {
+            let time = CalendarTime::from_seconds(seconds);
+            assert!(time.month >= 1 && time.month <= 12);
+            assert!(time.day >= 1 && time.day as u32 <= days_in_month(time.year as u32, time.month as u32));
+            assert_eq!(time.to_seconds(), seconds);
+            seconds += 5 * SECONDS_PER_DAY + 3_607;
+        }
+    }
+}
@@ -1,5 +1,10 @@
 #![no_std]
 
+pub mod calendar;
+pub mod sharp_lines;
+pub mod tone_pwm;
+pub mod wake_scheduler;
+
 pub trait BatteryMonitorDevice {
     fn read(&mut self) -> u16;
 }
@@ -36,9 +41,51 @@ pub trait SysTimerDevice {
 #[derive(Debug)]
 pub struct CommError;
 
+// cost of the last update, only the lines that changed are sent
+#[derive(Copy, Clone, Default)]
+pub struct DisplayUpdateStats {
+    pub lines: u8,
+    pub transfer_us: u32, // measured from the start of the transfer to the dma interrupt
+    pub full_transfer_us: u32, // every line at the measured rate, what an update cost before line tracking
+}
+
 pub trait DisplayDevice {
     fn draw_pixel(&mut self, x: u8, y: u8, color: bool);
     fn update(&mut self) -> bool;
+    // quarter turns clockwise from the normal orientation, 0-3
+    fn set_rotation(&mut self, rotation: u8);
+    fn update_stats(&self) -> DisplayUpdateStats;
+
+    // fast paths, logical pixels clipped to the screen. Defaults go through draw_pixel,
+    // the display overrides them to write whole bytes
+    fn fill_rect(&mut self, x: u8, y: u8, width: u8, height: u8, color: bool) {
+        for py in y..y.saturating_add(height).min(64) {
+            for px in x..x.saturating_add(width).min(64) {
+                self.draw_pixel(px, py, color);
+            }
+        }
+    }
+
+    // horizontal run of pixels
+    fn draw_span(&mut self, x: u8, y: u8, length: u8, color: bool) {
+        self.fill_rect(x, y, length, 1, color);
+    }
+
+    // 8 pixels from a bitmap byte, bit 0 is the leftmost pixel
+    fn blit8(&mut self, x: u8, y: u8, bits: u8, on_color: bool, off_color: bool) {
+        let mut start = 0;
+        while start < 8 {
+            let bit = (bits >> start) & 1;
+            let mut end = start + 1;
+            while end < 8 && (bits >> end) & 1 == bit {
+                end += 1;
+            }
+
+            let color = if bit > 0 { on_color } else { off_color };
+            self.draw_span(x.saturating_add(start), y, end - start, color);
+            start = end;
+        }
+    }
 }
 
 pub static TONE_NOTES: [u32; 80] = [
@@ -49,9 +96,16 @@ pub static TONE_NOTES: [u32; 80] = [
     3729, 3951, 4186, 4435, 4699, 4978, 5274,
 ];
 
+// loudest volume level, 1 is the quietest
+pub const VOLUME_MAX: u8 = 3;
+
 pub trait ToneDevice {
     fn tone(&self, freq: u32);
     fn no_tone(&self);
+    // silences tones until unmuted
+    fn set_muted(&self, muted: bool);
+    // 1 to VOLUME_MAX, used from the next tone
+    fn set_volume(&self, volume: u8);
 }
 
 #[repr(u8)]
@@ -71,14 +125,109 @@ pub enum WatchdogTimeouts {
     Seconds128 = 0xB,
 }
 
+impl WatchdogTimeouts {
+    /// Approximate period length, watchdog is clocked at 128 hz (32k / 256)
+    pub fn as_ms(&self) -> u32 {
+        64 << (*self as u32)
+    }
+
+    /// Longest sleep period that does not exceed the given time
+    /// NOTE: sleep uses window mode with a 16k cycle (128s) timeout, window must close before the timeout
+    /// so Seconds64 is the longest safe sleep period
+    pub fn longest_within(ms: u32) -> WatchdogTimeouts {
+        const PERIODS: [WatchdogTimeouts; 11] = [
+            WatchdogTimeouts::Seconds64,
+            WatchdogTimeouts::Seconds32,
+            WatchdogTimeouts::Seconds16,
+            WatchdogTimeouts::Seconds8,
+            WatchdogTimeouts::Seconds4,
+            WatchdogTimeouts::Seconds2,
+            WatchdogTimeouts::Seconds1,
+            WatchdogTimeouts::Millis512,
+            WatchdogTimeouts::Millis256,
+            WatchdogTimeouts::Millis128,
+            WatchdogTimeouts::Millis64,
+        ];
+
+        for p in PERIODS {
+            if p.as_ms() <= ms {
+                return p;
+            }
+        }
+        WatchdogTimeouts::Millis64
+    }
+}
+
 pub trait WatchdogDevice {
     fn feed(&mut self);
     fn disable(&mut self);
     fn clear_disable_interrupt(&mut self);
     fn start_timeout(&mut self, period: WatchdogTimeouts);
     fn sleep(&mut self, period: WatchdogTimeouts);
+    // watchdog off, only an external interrupt (button press) wakes
+    fn deep_sleep(&mut self);
+}
+
+pub trait RtcDevice {
+    // seconds since 2000-01-01 00:00:00
+    fn get_time(&self) -> u32;
+    fn set_time(&mut self, seconds: u32);
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum WakeEvent {
+    ClockRedraw,
+    Alarm,
+    PetNeeds,
+}
+
+pub trait WakeSchedulerDevice {
+    // wake at time `at` (rtc seconds), repeating every `period` seconds, 0 for a one-shot event
+    // re-scheduling an already registered event replaces it
+    fn schedule(&mut self, event: WakeEvent, at: u32, period: u32);
+    fn cancel(&mut self, event: WakeEvent);
+}
+
+// cause of the last reset, read once at boot
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ResetCause {
+    PowerOn = 0,
+    Brownout = 1,
+    External = 2, // reset pin
+    Watchdog = 3,
+    System = 4, // software reset, crash handler or bootloader
+    Unknown = 5,
+}
+
+pub const RESET_CAUSE_COUNT: usize = 6;
+
+impl ResetCause {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ResetCause::PowerOn),
+            1 => Some(ResetCause::Brownout),
+            2 => Some(ResetCause::External),
+            3 => Some(ResetCause::Watchdog),
+            4 => Some(ResetCause::System),
+            5 => Some(ResetCause::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResetCause::PowerOn => "por",
+            ResetCause::Brownout => "bod",
+            ResetCause::External => "ext",
+            ResetCause::Watchdog => "wdt",
+            ResetCause::System => "sys",
+            ResetCause::Unknown => "unk",
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum FileWriteError {
     ChecksumFailed,
     FilesystemFull,
@@ -89,4 +238,7 @@ pub trait StorageDevice {
     fn write_image(&mut self, data: &[u8], key: &str) -> Result<(), FileWriteError>;
     fn clear_cache(&mut self);
     fn format_storage(&mut self);
+    // newest valid save file, returns the length read or 0 if there is no save
+    fn load_save(&mut self, data: &mut [u8]) -> usize;
+    fn write_save(&mut self, data: &[u8]) -> Result<(), FileWriteError>;
 }
@@ -0,0 +1,244 @@
+/*!
+ * Line bookkeeping for the Sharp memory LCD, the panel is written one addressed line at a time
+ * so only lines that changed since the last transfer have to be sent.
+ *
+ * Drawing marks lines dirty when a byte actually changes. Building the packet compares dirty
+ * lines against a shadow of what the panel shows, a line that was redrawn with the same content
+ * (clear then draw the same sprite) is skipped.
+ *
+ * No hardware dependencies, kept here so it can be tested on the host.
+ */
+
+pub const SHARP_LINES: usize = 128;
+pub const SHARP_LINE_BYTES: usize = 16;
+// command byte, address + data + trailer per line, final trailer
+pub const SHARP_PACKET_MAX: usize = 1 + SHARP_LINES * (SHARP_LINE_BYTES + 2) + 2;
+
+const SHARP_WRITE_LINES: u8 = 0x01;
+
+pub type SharpLine = [u8; SHARP_LINE_BYTES];
+
+pub struct DirtyLines {
+    bits: [u32; SHARP_LINES / 32],
+}
+
+impl Default for DirtyLines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirtyLines {
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; SHARP_LINES / 32],
+        }
+    }
+
+    pub fn mark(&mut self, line: usize) {
+        self.bits[line / 32] |= 1 << (line % 32);
+    }
+
+    pub fn mark_all(&mut self) {
+        self.bits = [u32::MAX; SHARP_LINES / 32];
+    }
+
+    pub fn is_dirty(&self, line: usize) -> bool {
+        self.bits[line / 32] & (1 << (line % 32)) != 0
+    }
+
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.bits = [0; SHARP_LINES / 32];
+    }
+}
+
+/**
+ * Sets pixels x0..=x1 of a line to `color` a byte at a time, returns true if the line changed.
+ * Pixels are active low, an on (black) pixel is a cleared bit. Bit 0 is the leftmost pixel of a byte.
+ */
+pub fn fill_line_bits(line: &mut SharpLine, x0: usize, x1: usize, color: bool) -> bool {
+    let first = x0 / 8;
+    let last = x1 / 8;
+    let mut changed = false;
+
+    for (i, byte) in line.iter_mut().enumerate().take(last + 1).skip(first) {
+        let lo = if i == first { x0 % 8 } else { 0 };
+        let hi = if i == last { x1 % 8 } else { 7 };
+        let mask = ((0xFF_u16 << lo) & (0xFF_u16 >> (7 - hi))) as u8;
+
+        let value = if color { *byte & !mask } else { *byte | mask };
+        if value != *byte {
+            *byte = value;
+            changed = true;
+        }
+    }
+    changed
+}
+
+// bytes of a packet carrying `lines` lines, 0 when there is nothing to send
+pub fn packet_length(lines: usize) -> usize {
+    if lines == 0 {
+        return 0;
+    }
+    1 + lines * (SHARP_LINE_BYTES + 2) + 2
+}
+
+/**
+ * Copies dirty lines that differ from the panel shadow into `packet` and the shadow, then clears
+ * the dirty marks. With `force` every dirty line is sent, used when the panel contents are unknown.
+ * Returns the packet length and the number of lines in it, (0, 0) when nothing changed.
+ */
+pub fn pack_dirty_lines(
+    frame: &[SharpLine; SHARP_LINES],
+    panel: &mut [SharpLine; SHARP_LINES],
+    dirty: &mut DirtyLines,
+    packet: &mut [u8; SHARP_PACKET_MAX],
+    force: bool,
+) -> (usize, usize) {
+    let mut lines = 0;
+    let mut offset = 1;
+
+    for line in 0..SHARP_LINES {
+        if !dirty.is_dirty(line) || (!force && frame[line] == panel[line]) {
+            continue;
+        }
+
+        panel[line] = frame[line];
+
+        // line addresses start at 1
+        packet[offset] = (line + 1) as u8;
+        packet[offset + 1..offset + 1 + SHARP_LINE_BYTES].copy_from_slice(&frame[line]);
+        packet[offset + 1 + SHARP_LINE_BYTES] = 0x00;
+        offset += SHARP_LINE_BYTES + 2;
+        lines += 1;
+    }
+    dirty.clear();
+
+    if lines == 0 {
+        return (0, 0);
+    }
+
+    packet[0] = SHARP_WRITE_LINES;
+    packet[offset] = 0x00;
+    packet[offset + 1] = 0x00;
+
+    (offset + 2, lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffers() -> (
+        [SharpLine; SHARP_LINES],
+        [SharpLine; SHARP_LINES],
+        [u8; SHARP_PACKET_MAX],
+    ) {
+        (
+            [[0xFF; SHARP_LINE_BYTES]; SHARP_LINES],
+            [[0xFF; SHARP_LINE_BYTES]; SHARP_LINES],
+            [0; SHARP_PACKET_MAX],
+        )
+    }
+
+    #[test]
+    fn nothing_dirty_sends_nothing() {
+        let (frame, mut panel, mut packet) = buffers();
+        let mut dirty = DirtyLines::new();
+
+        assert_eq!(pack_dirty_lines(&frame, &mut panel, &mut dirty, &mut packet, false), (0, 0));
+    }
+
+    #[test]
+    fn only_changed_lines_are_sent() {
+        let (mut frame, mut panel, mut packet) = buffers();
+        let mut dirty = DirtyLines::new();
+
+        frame[3][0] = 0x7F;
+        dirty.mark(3);
+        frame[100][15] = 0xFE;
+        dirty.mark(100);
+
+        let (length, lines) = pack_dirty_lines(&frame, &mut panel, &mut dirty, &mut packet, false);
+        assert_eq!(lines, 2);
+        assert_eq!(length, packet_length(2));
+
+        assert_eq!(packet[0], SHARP_WRITE_LINES);
+        assert_eq!(packet[1], 4);
+        assert_eq!(packet[2], 0x7F);
+        assert_eq!(packet[1 + SHARP_LINE_BYTES + 1], 0x00);
+        assert_eq!(packet[1 + SHARP_LINE_BYTES + 2], 101);
+        assert_eq!(packet[1 + 2 * (SHARP_LINE_BYTES + 2) - 2], 0xFE);
+        assert_eq!(&packet[length - 2..length], &[0x00, 0x00]);
+
+        assert_eq!(panel[3], frame[3]);
+        assert_eq!(dirty.count(), 0);
+    }
+
+    #[test]
+    fn redrawn_line_with_same_content_is_skipped() {
+        let (frame, mut panel, mut packet) = buffers();
+        let mut dirty = DirtyLines::new();
+
+        // clear and redraw of an unchanged screen
+        dirty.mark_all();
+        assert_eq!(pack_dirty_lines(&frame, &mut panel, &mut dirty, &mut packet, false), (0, 0));
+        assert_eq!(dirty.count(), 0);
+    }
+
+    #[test]
+    fn force_sends_every_dirty_line() {
+        let (frame, mut panel, mut packet) = buffers();
+        let mut dirty = DirtyLines::new();
+
+        dirty.mark_all();
+        let (length, lines) = pack_dirty_lines(&frame, &mut panel, &mut dirty, &mut packet, true);
+        assert_eq!(lines, SHARP_LINES);
+        assert_eq!(length, SHARP_PACKET_MAX);
+
+        // line addresses in order
+        for line in 0..SHARP_LINES {
+            assert_eq!(packet[1 + line * (SHARP_LINE_BYTES + 2)], (line + 1) as u8);
+        }
+    }
+
+    #[test]
+    fn fill_line_masks() {
+        let mut line: SharpLine = [0xFF; SHARP_LINE_BYTES];
+
+        // inside one byte
+        assert!(fill_line_bits(&mut line, 2, 4, true));
+        assert_eq!(line[0], 0b1110_0011);
+
+        // partial, whole and partial bytes
+        let mut line: SharpLine = [0xFF; SHARP_LINE_BYTES];
+        assert!(fill_line_bits(&mut line, 6, 17, true));
+        assert_eq!(&line[0..4], &[0b0011_1111, 0x00, 0b1111_1100, 0xFF]);
+
+        // filling with the same color is not a change
+        assert!(!fill_line_bits(&mut line, 8, 15, true));
+        assert!(fill_line_bits(&mut line, 0, 127, false));
+        assert_eq!(line, [0xFF; SHARP_LINE_BYTES]);
+    }
+
+    #[test]
+    fn dirty_marks() {
+        let mut dirty = DirtyLines::new();
+        dirty.mark(0);
+        dirty.mark(31);
+        dirty.mark(32);
+        dirty.mark(127);
+        dirty.mark(127);
+
+        assert_eq!(dirty.count(), 4);
+        assert!(dirty.is_dirty(31) && dirty.is_dirty(32) && dirty.is_dirty(127));
+        assert!(!dirty.is_dirty(1));
+
+        dirty.clear();
+        assert_eq!(dirty.count(), 0);
+    }
+}
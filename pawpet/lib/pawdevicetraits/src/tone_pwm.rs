@@ -0,0 +1,95 @@
+/*!
+ * Timer settings for a tone on an 8 bit PWM timer (SAMD21 TC in COUNT8 NPWM mode).
+ * The period register sets the frequency and the compare register the duty cycle, a piezo is
+ * loudest at 50% duty so lower volumes shorten the pulse.
+ */
+
+use crate::VOLUME_MAX;
+
+// dividers of the TC PRESCALER field, index is the field value
+const PRESCALERS: [u32; 8] = [1, 2, 4, 8, 16, 64, 256, 1024];
+
+// duty cycle as a shift of the period for volume 1..=VOLUME_MAX, 1/32 1/8 1/2
+const VOLUME_SHIFTS: [u8; VOLUME_MAX as usize] = [5, 3, 1];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TonePwm {
+    pub prescaler: u8, // PRESCALER field value
+    pub top: u8,       // PER, the period is top + 1 timer ticks
+    pub duty: u8,      // CC0
+}
+
+impl TonePwm {
+    // None if the frequency is out of range for the clock
+    pub fn new(clock_hz: u32, freq: u32, volume: u8) -> Option<Self> {
+        if freq == 0 {
+            return None;
+        }
+
+        // smallest prescaler that fits the period in 8 bits, for the best frequency resolution
+        let (prescaler, ticks) = PRESCALERS
+            .iter()
+            .enumerate()
+            .map(|(i, div)| (i as u8, (clock_hz / div + freq / 2) / freq))
+            .find(|(_, ticks)| *ticks <= 256)?;
+        if ticks < 2 {
+            return None;
+        }
+
+        let volume = volume.clamp(1, VOLUME_MAX);
+        let duty = (ticks >> VOLUME_SHIFTS[volume as usize - 1]).max(1);
+
+        Some(Self {
+            prescaler,
+            top: (ticks - 1) as u8,
+            duty: duty as u8,
+        })
+    }
+
+    // frequency actually played, rounded to the nearest hz
+    pub fn freq(&self, clock_hz: u32) -> u32 {
+        let period = PRESCALERS[self.prescaler as usize] * (self.top as u32 + 1);
+        (clock_hz + period / 2) / period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TONE_NOTES;
+
+    const CLOCK_HZ: u32 = 6_000_000;
+
+    #[test]
+    fn every_note_fits_within_a_percent() {
+        for freq in TONE_NOTES {
+            let pwm = TonePwm::new(CLOCK_HZ, freq, VOLUME_MAX).unwrap();
+            // compared as periods, the played frequency is not a whole number of hz
+            let period = PRESCALERS[pwm.prescaler as usize] * (pwm.top as u32 + 1);
+            let error = CLOCK_HZ.abs_diff(freq * period);
+            assert!(error * 100 <= CLOCK_HZ, "{} hz played at {}", freq, pwm.freq(CLOCK_HZ));
+        }
+    }
+
+    #[test]
+    fn volume_shortens_the_pulse() {
+        let loud = TonePwm::new(CLOCK_HZ, 440, VOLUME_MAX).unwrap();
+        assert_eq!(loud, TonePwm { prescaler: 5, top: 212, duty: 106 });
+
+        let mid = TonePwm::new(CLOCK_HZ, 440, 2).unwrap();
+        let quiet = TonePwm::new(CLOCK_HZ, 440, 1).unwrap();
+        assert_eq!(mid.top, loud.top);
+        assert!(quiet.duty >= 1 && quiet.duty < mid.duty && mid.duty < loud.duty);
+
+        // out of range volumes are clamped
+        assert_eq!(TonePwm::new(CLOCK_HZ, 440, 0), Some(quiet));
+        assert_eq!(TonePwm::new(CLOCK_HZ, 440, VOLUME_MAX + 1), Some(loud));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(TonePwm::new(CLOCK_HZ, 0, VOLUME_MAX), None);
+        assert_eq!(TonePwm::new(CLOCK_HZ, 10, VOLUME_MAX), None);
+        assert_eq!(TonePwm::new(CLOCK_HZ, CLOCK_HZ, VOLUME_MAX), None);
+    }
+}
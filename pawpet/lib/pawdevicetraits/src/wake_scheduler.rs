@@ -0,0 +1,171 @@
+/*!
+ * Timed wake events while sleeping, the main loop picks the longest watchdog sleep
+ * that does not overshoot the next event and services due events without starting
+ * the render loop.
+ */
+
+use crate::WakeEvent;
+use crate::WakeSchedulerDevice;
+use crate::WatchdogTimeouts;
+
+#[derive(Copy, Clone)]
+struct ScheduledWake {
+    event: WakeEvent,
+    at: u32,
+    period: u32,
+}
+
+pub struct WakeScheduler {
+    events: [Option<ScheduledWake>; 8],
+}
+
+impl WakeSchedulerDevice for WakeScheduler {
+    fn schedule(&mut self, event: WakeEvent, at: u32, period: u32) {
+        self.cancel(event);
+
+        for e in self.events.iter_mut() {
+            if e.is_none() {
+                *e = Some(ScheduledWake { event, at, period });
+                return;
+            }
+        }
+        // table full, event dropped
+        debug_assert!(false);
+    }
+
+    fn cancel(&mut self, event: WakeEvent) {
+        for e in self.events.iter_mut() {
+            if e.is_some() && e.unwrap().event == event {
+                *e = None;
+            }
+        }
+    }
+}
+
+impl Default for WakeScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakeScheduler {
+    pub const fn new() -> Self {
+        Self { events: [None; 8] }
+    }
+
+    // rtc time of the earliest registered event
+    pub fn next_due(&self) -> Option<u32> {
+        let mut next: Option<u32> = None;
+        for e in self.events.iter().flatten() {
+            if next.is_none() || e.at < next.unwrap() {
+                next = Some(e.at);
+            }
+        }
+        next
+    }
+
+    // longest sleep period up to `max` that wakes at or before the next event
+    pub fn sleep_period(&self, now: u32, max: WatchdogTimeouts) -> WatchdogTimeouts {
+        let mut ms = max.as_ms();
+
+        if let Some(at) = self.next_due() {
+            let remaining_ms = at.saturating_sub(now).saturating_mul(1000);
+            if remaining_ms < ms {
+                ms = remaining_ms;
+            }
+        }
+
+        WatchdogTimeouts::longest_within(ms)
+    }
+
+    // pops one due event, periodic events are re-armed for their next period
+    pub fn take_due(&mut self, now: u32) -> Option<WakeEvent> {
+        for e in self.events.iter_mut() {
+            if let Some(wake) = e {
+                if wake.at <= now {
+                    let event = wake.event;
+                    if wake.period > 0 {
+                        // skip missed periods instead of firing them back to back
+                        while wake.at <= now {
+                            wake.at += wake.period;
+                        }
+                    } else {
+                        *e = None;
+                    }
+                    return Some(event);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_within() {
+        assert_eq!(WatchdogTimeouts::longest_within(0), WatchdogTimeouts::Millis64);
+        assert_eq!(WatchdogTimeouts::longest_within(63), WatchdogTimeouts::Millis64);
+        assert_eq!(WatchdogTimeouts::longest_within(1023), WatchdogTimeouts::Millis512);
+        assert_eq!(WatchdogTimeouts::longest_within(1024), WatchdogTimeouts::Seconds1);
+        assert_eq!(WatchdogTimeouts::longest_within(5000), WatchdogTimeouts::Seconds4);
+        // never longer than the watchdog timeout allows
+        assert_eq!(WatchdogTimeouts::longest_within(u32::MAX), WatchdogTimeouts::Seconds64);
+    }
+
+    #[test]
+    fn earliest_event_is_next() {
+        let mut scheduler = WakeScheduler::new();
+        assert_eq!(scheduler.next_due(), None);
+
+        scheduler.schedule(WakeEvent::ClockRedraw, 120, 60);
+        scheduler.schedule(WakeEvent::Alarm, 100, 0);
+        scheduler.schedule(WakeEvent::PetNeeds, 300, 0);
+        assert_eq!(scheduler.next_due(), Some(100));
+
+        assert_eq!(scheduler.take_due(99), None);
+        assert_eq!(scheduler.take_due(100), Some(WakeEvent::Alarm));
+        assert_eq!(scheduler.take_due(100), None);
+        assert_eq!(scheduler.next_due(), Some(120));
+    }
+
+    #[test]
+    fn cancel_and_reschedule() {
+        let mut scheduler = WakeScheduler::new();
+        scheduler.schedule(WakeEvent::Alarm, 100, 0);
+        scheduler.schedule(WakeEvent::PetNeeds, 200, 0);
+
+        scheduler.cancel(WakeEvent::Alarm);
+        assert_eq!(scheduler.next_due(), Some(200));
+
+        // replaces the registered event instead of adding a second one
+        scheduler.schedule(WakeEvent::PetNeeds, 400, 0);
+        assert_eq!(scheduler.next_due(), Some(400));
+        assert_eq!(scheduler.take_due(400), Some(WakeEvent::PetNeeds));
+        assert_eq!(scheduler.next_due(), None);
+    }
+
+    #[test]
+    fn periodic_event_skips_missed_periods() {
+        let mut scheduler = WakeScheduler::new();
+        scheduler.schedule(WakeEvent::ClockRedraw, 60, 60);
+
+        assert_eq!(scheduler.take_due(250), Some(WakeEvent::ClockRedraw));
+        assert_eq!(scheduler.take_due(250), None);
+        assert_eq!(scheduler.next_due(), Some(300));
+    }
+
+    #[test]
+    fn sleep_wakes_before_next_event() {
+        let mut scheduler = WakeScheduler::new();
+        assert_eq!(scheduler.sleep_period(0, WatchdogTimeouts::Seconds16), WatchdogTimeouts::Seconds16);
+
+        scheduler.schedule(WakeEvent::Alarm, 10, 0);
+        assert_eq!(scheduler.sleep_period(0, WatchdogTimeouts::Seconds16), WatchdogTimeouts::Seconds8);
+        assert_eq!(scheduler.sleep_period(9, WatchdogTimeouts::Seconds16), WatchdogTimeouts::Millis512);
+        // overdue, shortest sleep
+        assert_eq!(scheduler.sleep_period(11, WatchdogTimeouts::Seconds16), WatchdogTimeouts::Millis64);
+    }
+}
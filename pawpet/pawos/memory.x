@@ -2,7 +2,17 @@ MEMORY
 {
   /* Leave 8k for the default bootloader on the Feather M0 */
   FLASH (rx) : ORIGIN = 0x00000000 + 8K, LENGTH = 256K - 8K
-  RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 32K
+  RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 32K - 1K
+  /* crash report and reset counters, not initialized at boot so they survive a reset.
+     the top 512 bytes are left for the bootloader stack and double tap flag */
+  CRASH (rw) : ORIGIN = 0x20000000 + 32K - 1K, LENGTH = 512
 }
 _stack_start = ORIGIN(RAM) + LENGTH(RAM);
 
+SECTIONS
+{
+  .crash_report (NOLOAD) : ALIGN(4)
+  {
+    KEEP(*(.crash_report .crash_report.*));
+  } > CRASH
+} INSERT AFTER .uninit;
@@ -10,7 +10,6 @@ use rtt_target::{debug_rprintln, rtt_init_print};
 // extern crate alloc;
 // use embedded_alloc::Heap;
 // use hal::watchdog::WatchdogTimeout;
-// use core::panic::PanicInfo;
 // use core::alloc::Layout;
 
 // #[global_allocator]
@@ -20,11 +19,13 @@ use hf2hid::HF2Monitor;
 
 use bsp::hal;
 use bsp::pac;
-// use core::fmt::Write;
+use core::fmt::Write;
 use pawbsp as bsp;
 
-use panic_halt as _;
+use core::panic::PanicInfo;
+use cortex_m_rt::ExceptionFrame;
 
+use core::sync::atomic::{AtomicBool, Ordering};
 use cortex_m::interrupt::free as disable_interrupts;
 use cortex_m::peripheral::NVIC;
 use cortex_m::peripheral::SCB;
@@ -46,6 +47,7 @@ use hal::usb::UsbBus;
 use pac::{interrupt, CorePeripherals, Peripherals};
 
 use crate::sleepy_dog::SleepyDog;
+use games::resets::ResetStats;
 use pawdevicetraits::*;
 
 static mut USB_ALLOCATOR: Option<UsbBusAllocator<UsbBus>> = None;
@@ -56,9 +58,20 @@ static mut USB_HID: Option<HIDClass<UsbBus>> = None;
 use pawdevices::*;
 static mut SYS_TIMER: Option<sys_timer::SysTimer> = None;
 
+// set by the EIC interrupt, used to tell button wakes apart from watchdog wakes
+static BUTTON_WAKE: AtomicBool = AtomicBool::new(false);
+
+// a host that stops reading mid report gets the rest of it dropped
+const SEND_TIMEOUT_US: u32 = 100_000;
+
 use hf2hid;
 
-use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::Text,
+};
 
 use spi_memory::series25::Flash;
 
@@ -260,8 +273,11 @@ fn init() -> (
     let _vcom_toggler =
         vcom_toggle::VCOMToggle::new(vcom_pin, peripherals.TCC1, &mut peripherals.PM);
 
-    let gclk0 = clocks.gclk0();
-    let tc_clock = &clocks.tc4_tc5(&gclk0).unwrap();
+    // tone timer at 48 MHz / 8 = 6 MHz, slow enough for the 8 bit period of the lowest note
+    let tone_gclock = clocks
+        .configure_gclk_divider_and_source(ClockGenId::GCLK5, 8, ClockSource::DFLL48M, false)
+        .unwrap();
+    let tc_clock = &clocks.tc4_tc5(&tone_gclock).unwrap();
     let tone_beeper: bsp::BeeperE = bsp::pin_alias!(pins.beeper_e).into();
 
     let tone = tone::Tone::new(tone_beeper, peripherals.TC4, tc_clock, &mut peripherals.PM);
@@ -321,6 +337,7 @@ fn init() -> (
     unsafe {
         SYS_TIMER = Some(timer);
     };
+    display.set_clock(sys_timer_us);
     // TODO disable unused peripherals according to what is enabled by default
     // TODO conditionally disable peripherals on sleep? both sercoms could probably be turned off between wakeups, dma, etc.
 
@@ -333,7 +350,7 @@ fn init() -> (
 
 #[entry]
 fn main() -> ! {
-    let (_rtc, mut flash, mut display, mut buttons, tone, mut battery) = init();
+    let (rtc, mut flash, mut display, mut buttons, tone, mut battery) = init();
 
     let timer = unsafe { SYS_TIMER.as_mut().unwrap() };
 
@@ -342,17 +359,17 @@ fn main() -> ! {
     debug_rprintln!("JDEC {:?} {:?}", id, rot);
 
     let mut storage = pawdevices::storage_simple::SimpleFlashStorage::new(flash);
+    let mut rtc = rtc_clock::RtcClock::new(rtc);
+    let mut scheduler = wake_scheduler::WakeScheduler::new();
     // storage.write_image(STORAGE_DATA, b"petsit");
 
     // storage.format_storage();
 
-    tone.tone(500);
-    timer.delay_ms(250);
-    tone.no_tone();
-    timer.delay_ms(250);
-    tone.tone(700);
-    timer.delay_ms(250);
-    tone.no_tone();
+    // startup beeps are played by the runner once the sound setting has been loaded
+
+    if crash_report::take_notice() {
+        show_crash_notice(&mut display, timer);
+    }
 
     let mut hid_mon: HF2Monitor = HF2Monitor::new();
 
@@ -361,6 +378,10 @@ fn main() -> ! {
         .start_timeout(sleepy_dog::SleepyDogTimeout::Seconds2);
 
     let mut paw_runner = games::PawRunner::new();
+    let reset_cause = reset_cause::read_reset_cause();
+    debug_rprintln!("reset cause {}", reset_cause.name());
+    let reset_counts = reset_cause::count_reset(reset_cause);
+    paw_runner.set_reset_stats(ResetStats::from_counts(reset_counts, reset_cause));
     let watchdog: &mut SleepyDog = &mut sleepy_dog::SleepyDog::get().unwrap();
 
     loop {
@@ -417,7 +438,19 @@ fn main() -> ! {
                     hf2hid::HF2Commands::Info => {
                         hid_mon.send_version_info_packet(send_packet);
                     }
-                    // hf2hid::HF2Commands::DMesg => {}
+                    hf2hid::HF2Commands::DMesg => {
+                        // reset counters and the last crash report since power on
+                        let mut report: heapless::String<512> = heapless::String::new();
+                        write_reset_stats(&mut report, paw_runner.reset_stats());
+                        crash_report::format(&mut report);
+                        let mut timed_out = false;
+                        let send = |packet: &[u8]| {
+                            if !timed_out {
+                                timed_out = !send_packet_wait(packet);
+                            }
+                        };
+                        hid_mon.send_data_packets(send, report.as_bytes());
+                    }
                     // hf2hid::HF2Commands::ListKeys => {}
                     hf2hid::HF2Commands::FormatFileSys => {
                         debug_rprintln!("ERASE START");
@@ -464,25 +497,81 @@ fn main() -> ! {
             timer,
             &mut battery,
             &mut storage,
+            &mut rtc,
+            &mut scheduler,
         );
 
+        // events that came due while awake (alarm)
+        while let Some(event) = scheduler.take_due(rtc.get_time()) {
+            paw_runner.wake(
+                event,
+                &mut display,
+                &tone,
+                timer,
+                &mut storage,
+                &rtc,
+                &mut scheduler,
+            );
+        }
+
         let sleep_request = paw_runner.sleep_request();
         if sleep_request.is_some() {
-            timer.disable();
-            sleep(&mut buttons, sleep_request.unwrap());
-            timer.enable();
+            // stay asleep between scheduled events, only a button press or an event requesting
+            // a full wake restarts the update loop
+            while let Some(max_period) = paw_runner.sleep_request() {
+                let period = scheduler.sleep_period(rtc.get_time(), max_period);
+
+                display.wait_transfer();
+                timer.disable();
+                let button_wake = if paw_runner.deep_sleep_request() {
+                    deep_sleep(&mut buttons)
+                } else {
+                    sleep(&mut buttons, period)
+                };
+                timer.enable();
+
+                if button_wake {
+                    break;
+                }
+
+                while let Some(event) = scheduler.take_due(rtc.get_time()) {
+                    paw_runner.wake(
+                        event,
+                        &mut display,
+                        &tone,
+                        timer,
+                        &mut storage,
+                        &rtc,
+                        &mut scheduler,
+                    );
+                }
+            }
         }
     }
 }
 
-pub fn sleep(buttons: &mut buttons::PawButtons, period: WatchdogTimeouts) {
+// returns true if woken by a button press, false for watchdog wake
+pub fn sleep(buttons: &mut buttons::PawButtons, period: WatchdogTimeouts) -> bool {
+    return sleep_until(buttons, Some(period));
+}
+
+// no watchdog wakes, only a button press
+pub fn deep_sleep(buttons: &mut buttons::PawButtons) -> bool {
+    return sleep_until(buttons, None);
+}
+
+fn sleep_until(buttons: &mut buttons::PawButtons, period: Option<WatchdogTimeouts>) -> bool {
+    BUTTON_WAKE.store(false, Ordering::Relaxed);
     buttons.enable_interrupt();
     NVIC::mask(interrupt::USB);
     NVIC::unpend(interrupt::USB);
     // NVIC::mask(-1);
     // NVIC::unpend(-1);
 
-    sleepy_dog::SleepyDog::get().unwrap().sleep(period);
+    match period {
+        Some(period) => sleepy_dog::SleepyDog::get().unwrap().sleep(period),
+        None => sleepy_dog::SleepyDog::get().unwrap().deep_sleep(),
+    }
 
     // ensure wakeup is handled without interrupts from other sources
     disable_interrupts(|_| {
@@ -497,6 +586,40 @@ pub fn sleep(buttons: &mut buttons::PawButtons, period: WatchdogTimeouts) {
             // NVIC::unmask(interrupt::SYSCTRL);
         }
     });
+
+    return BUTTON_WAKE.load(Ordering::Relaxed);
+}
+
+fn write_reset_stats(out: &mut heapless::String<512>, stats: &ResetStats) {
+    const CAUSES: [ResetCause; RESET_CAUSE_COUNT] = [
+        ResetCause::PowerOn,
+        ResetCause::Brownout,
+        ResetCause::External,
+        ResetCause::Watchdog,
+        ResetCause::System,
+        ResetCause::Unknown,
+    ];
+
+    write!(out, "resets").ok();
+    for cause in CAUSES {
+        write!(out, " {} {}", cause.name(), stats.count(cause)).ok();
+    }
+    if let Some(cause) = stats.last() {
+        write!(out, "\nlast reset {}", cause.name()).ok();
+    }
+    write!(out, "\n").ok();
+}
+
+fn show_crash_notice(display: &mut display::LS013B7DH03, timer: &mut sys_timer::SysTimer) {
+    let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+    display.clear(BinaryColor::Off).ok();
+    Text::new("Recovered\nfrom crash", Point::new(5, 28), style)
+        .draw(display)
+        .ok();
+    while !display.update() {}
+
+    timer.delay_ms(2000);
 }
 
 pub fn reset_to_boot() {
@@ -521,6 +644,26 @@ fn send_packet(report: &[u8]) {
     })
 }
 
+// waits for the previous report to go out, back to back packets would be dropped otherwise
+// returns false if the host did not take it within SEND_TIMEOUT_US
+fn send_packet_wait(report: &[u8]) -> bool {
+    let start = sys_timer_us();
+    loop {
+        let sent = disable_interrupts(|_| unsafe {
+            match USB_HID.as_mut() {
+                Some(hid) => !matches!(hid.push_raw_input(&report), Err(UsbError::WouldBlock)),
+                None => true,
+            }
+        });
+        if sent {
+            return true;
+        }
+        if sys_timer_us().wrapping_sub(start) >= SEND_TIMEOUT_US {
+            return false;
+        }
+    }
+}
+
 fn poll_packet(report: &mut [u8; 64]) -> usize {
     let mut packet_size = 0;
     disable_interrupts(|_| unsafe {
@@ -575,10 +718,26 @@ fn EIC() {
             // cleared by writing a one to the corresponding bit in the INTFLAG register.
             // read more: SAM-D21DA1-Family-Data-Sheet-DS40001882G.pdf # 16.6.5 Interrupts
             eic.intflag.modify(|_, w| w.extint0().set_bit());
+            BUTTON_WAKE.store(true, Ordering::Relaxed);
         }
     });
 }
 
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crash_report::record_panic(info);
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    crash_report::record_hard_fault(frame);
+}
+
+// display transfer timing and usb send timeouts, the dma interrupt has no access to the timer
+fn sys_timer_us() -> u32 {
+    return unsafe { SYS_TIMER.as_ref().map_or(0, |timer| timer.now_us()) };
+}
+
 #[exception]
 fn SysTick() {
     let timer = unsafe { SYS_TIMER.as_mut().unwrap() };
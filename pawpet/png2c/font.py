@@ -0,0 +1,212 @@
+import os
+import sys
+
+# compiles a BDF pixel font into the proportional font format loaded by games/src/font.rs
+# only the characters listed in the charset file are kept, one entry per line:
+#
+#   U+0020-U+007E       range of code points
+#   U+2665              single code point
+#   éèàç                literal characters
+#
+# empty columns on both sides of every glyph are trimmed, glyphs advance by their width plus
+# one pixel of spacing. Blank glyphs (space) keep half the BDF advance.
+
+MAGIC = b"PFNT"
+VERSION = 1
+HEADER_SIZE = 10
+GLYPH_SIZE = 6
+LETTER_SPACING = 1
+LINE_SPACING = 1
+MAX_CODE_POINT = 0xFFFF
+MAX_WIDTH = 32
+
+
+class FontError(Exception):
+    pass
+
+
+class Glyph:
+    def __init__(self, codePoint, rows, advance):
+        self.codePoint = codePoint
+        self.rows = rows  # list of rows, each a list of 0/1 pixels
+        self.advance = advance
+
+
+def readBdf(bdfFile):
+    ascent = None
+    descent = None
+    glyphs = dict()
+
+    with open(bdfFile, "r", encoding="latin-1") as f:
+        lines = [line.strip() for line in f]
+
+    i = 0
+    while i < len(lines):
+        fields = lines[i].split()
+        i += 1
+        if len(fields) == 0:
+            continue
+
+        if fields[0] == "FONT_ASCENT":
+            ascent = int(fields[1])
+        elif fields[0] == "FONT_DESCENT":
+            descent = int(fields[1])
+        elif fields[0] == "STARTCHAR":
+            codePoint = None
+            advance = 0
+            bbx = None
+            bitmap = list()
+
+            while i < len(lines) and lines[i] != "ENDCHAR":
+                fields = lines[i].split()
+                i += 1
+                if len(fields) == 0:
+                    continue
+                if fields[0] == "ENCODING":
+                    codePoint = int(fields[1])
+                elif fields[0] == "DWIDTH":
+                    advance = int(fields[1])
+                elif fields[0] == "BBX":
+                    bbx = [int(v) for v in fields[1:5]]
+                elif fields[0] == "BITMAP":
+                    while i < len(lines) and lines[i] != "ENDCHAR":
+                        bitmap.append(lines[i])
+                        i += 1
+            i += 1
+
+            if codePoint is None or codePoint < 0 or bbx is None:
+                continue
+            glyphs[codePoint] = (advance, bbx, bitmap)
+
+    if ascent is None or descent is None:
+        raise FontError("missing FONT_ASCENT/FONT_DESCENT")
+
+    height = ascent + descent
+    result = dict()
+    for codePoint, (advance, bbx, bitmap) in glyphs.items():
+        width, rowCount, xOffset, yOffset = bbx
+        cellWidth = max(advance, xOffset + width, 1)
+        rows = [[0] * cellWidth for _ in range(height)]
+
+        for r, hexRow in enumerate(bitmap[:rowCount]):
+            bits = int(hexRow, 16)
+            rowBits = len(hexRow) * 4
+            y = ascent - (yOffset + rowCount) + r
+            if y < 0 or y >= height:
+                continue
+            for c in range(width):
+                if (bits >> (rowBits - 1 - c)) & 1:
+                    x = xOffset + c
+                    if 0 <= x < cellWidth:
+                        rows[y][x] = 1
+
+        result[codePoint] = Glyph(codePoint, rows, advance)
+
+    return height, result
+
+
+def readCharset(charsetFile):
+    codePoints = set()
+    with open(charsetFile, "r", encoding="utf-8") as f:
+        for line in f:
+            line = line.strip()
+            if len(line) == 0:
+                continue
+            if line.upper().startswith("U+"):
+                bounds = line.split("-")
+                first = int(bounds[0][2:], 16)
+                last = int(bounds[-1].strip()[2:], 16)
+                codePoints.update(range(first, last + 1))
+            else:
+                codePoints.update(ord(c) for c in line if not c.isspace())
+
+    # always available for word wrapping
+    codePoints.add(ord(" "))
+    return codePoints
+
+
+def trim(glyph):
+    columns = [x for x in range(len(glyph.rows[0])) if any(row[x] for row in glyph.rows)]
+    if len(columns) == 0:
+        return list(), 0, max(glyph.advance // 2, 1)
+
+    first = columns[0]
+    last = columns[-1]
+    rows = [row[first:last + 1] for row in glyph.rows]
+    width = last - first + 1
+    return rows, width, width + LETTER_SPACING
+
+
+def toBytes(height, glyphs):
+    table = bytearray()
+    bitmaps = bytearray()
+
+    for glyph in glyphs:
+        rows, width, advance = trim(glyph)
+        if width > MAX_WIDTH:
+            raise FontError("U+{0:04X} is {1} pixels wide, at most {2}".format(glyph.codePoint, width, MAX_WIDTH))
+        if len(bitmaps) > 0xFFFF:
+            raise FontError("glyph bitmaps are larger than 64KB")
+
+        table.extend(glyph.codePoint.to_bytes(2, byteorder='little'))
+        table.append(width)
+        table.append(advance)
+        table.extend(len(bitmaps).to_bytes(2, byteorder='little'))
+
+        # rows padded to whole bytes, bit 0 is the leftmost pixel
+        for row in rows:
+            for start in range(0, width, 8):
+                value = 0
+                for bit, pixel in enumerate(row[start:start + 8]):
+                    value |= pixel << bit
+                bitmaps.append(value)
+
+    out = bytearray()
+    out.extend(MAGIC)
+    out.append(VERSION)
+    out.append(height)
+    out.append(height + LINE_SPACING)
+    out.append(0)
+    out.extend(len(glyphs).to_bytes(2, byteorder='little'))
+    out.extend(table)
+    out.extend(bitmaps)
+    return out
+
+
+def main():
+    if len(sys.argv) != 4:
+        print("usage: font [font.bdf] [charset.txt] [output.fnt]")
+        print("the default firmware font is the public domain misc-fixed 5x8.bdf built with font_charset.txt")
+        sys.exit(1)
+
+    bdfFile = sys.argv[1]
+    charsetFile = sys.argv[2]
+    outFile = sys.argv[3]
+
+    try:
+        height, available = readBdf(bdfFile)
+        if height > 32:
+            raise FontError("font is {0} pixels tall, at most 32".format(height))
+
+        codePoints = readCharset(charsetFile)
+        for codePoint in sorted(codePoints):
+            if codePoint > MAX_CODE_POINT:
+                raise FontError("U+{0:04X} is outside the basic multilingual plane".format(codePoint))
+            if codePoint not in available:
+                print("WARNING: U+{0:04X} is not in the font".format(codePoint))
+
+        glyphs = [available[c] for c in sorted(codePoints) if c in available]
+        serializedBytes = toBytes(height, glyphs)
+    except (FontError, OSError, ValueError) as e:
+        print("ERROR: {0}: {1}".format(bdfFile, e))
+        sys.exit(1)
+
+    with open(outFile, "wb") as f:
+        f.write(serializedBytes)
+
+    name = os.path.splitext(os.path.basename(outFile))[0]
+    print("{0} - {1} glyphs height:{2} bytes:{3}".format(name, len(glyphs), height, len(serializedBytes)))
+
+
+if __name__ == "__main__":
+    main()
@@ -1,5 +1,6 @@
 from PIL import Image
 from enum import IntEnum, unique
+import json
 import os
 import sys
 import re
@@ -99,6 +100,50 @@ def spanPixels(image, supportAlpha, outBytes):
         outBytes.append(currentByte)
 
 
+# small window LZ over packed pixels, see games/src/lz.rs
+# literal run: 0LLLLLLL + L+1 bytes, copy: 1LLLLLLL DDDDDDDD, L+3 bytes from D+1 back
+LZ_WINDOW = 256
+LZ_MIN_MATCH = 3
+LZ_MAX_MATCH = 127 + LZ_MIN_MATCH
+LZ_MAX_LITERALS = 128
+
+
+def lzCompress(data, outBytes):
+    literals = list()
+
+    def flushLiterals():
+        while len(literals) > 0:
+            chunk = literals[:LZ_MAX_LITERALS]
+            del literals[:LZ_MAX_LITERALS]
+            outBytes.append(len(chunk) - 1)
+            outBytes.extend(chunk)
+
+    i = 0
+    while i < len(data):
+        bestLength = 0
+        bestDistance = 0
+
+        # copies may overlap the bytes they produce, a distance of 1 is a run
+        for distance in range(1, min(i, LZ_WINDOW) + 1):
+            length = 0
+            while length < LZ_MAX_MATCH and i + length < len(data) and data[i + length - distance] == data[i + length]:
+                length += 1
+            if length > bestLength:
+                bestLength = length
+                bestDistance = distance
+
+        if bestLength >= LZ_MIN_MATCH:
+            flushLiterals()
+            outBytes.append(0x80 | (bestLength - LZ_MIN_MATCH))
+            outBytes.append(bestDistance - 1)
+            i += bestLength
+        else:
+            literals.append(data[i])
+            i += 1
+
+    flushLiterals()
+
+
 def packSpanByte(currentData, color, spanSize, index, alpha):
     if not alpha:
         currentData |= ((color << 7) | spanSize) << (index * 8)
@@ -136,6 +181,25 @@ def getColor(x):
         return Colors.WHITE
 
 
+# aseprite --data export next to the png, frames as an array or a hash, duration in ms
+def loadFrameDurations(imageFile, tileCount):
+    path = os.path.splitext(imageFile)[0] + ".json"
+    if not os.path.isfile(path):
+        return None
+
+    with open(path) as f:
+        frames = json.load(f)["frames"]
+    if isinstance(frames, dict):
+        frames = list(frames.values())
+
+    durations = [min(int(frame["duration"]), 0xFFFF) for frame in frames]
+    if len(durations) != tileCount:
+        print("WARN: {0} has {1} frame durations for {2} tiles, ignored".format(
+            path, len(durations), tileCount))
+        return None
+    return durations
+
+
 def toBytes(graphic):
     imageBytes = bytearray()
 
@@ -146,20 +210,31 @@ def toBytes(graphic):
     encoding = 0
     if graphic["encoding"] == "span":
         encoding = 1
+    elif graphic["encoding"] == "lz":
+        encoding = 2
     alpha = (1 if graphic["alpha"] else 0)
 
     encoding = encoding << 1
     encoding |= alpha
+
+    # frame duration table follows the frame offsets
+    if ("frame_durations" in graphic):
+        encoding |= 0x8000
     imageBytes.extend(encoding.to_bytes(2, byteorder='little'))
 
     imageBytes.extend(graphic["tile_count"].to_bytes(2, byteorder='little'))
 
-    if ("frame_offsets" in graphic):
+    # a single tile has no offset table, even from a sprite map, see PawImageHeader::offsets_length
+    if ("frame_offsets" in graphic) and graphic["tile_count"] > 1:
         for x in range(0, len(graphic["frame_offsets"])):
             x1 = graphic["frame_offsets"][x]
 
             imageBytes.extend(x1.to_bytes(2, byteorder='little'))
 
+    if ("frame_durations" in graphic):
+        for duration in graphic["frame_durations"]:
+            imageBytes.extend(duration.to_bytes(2, byteorder='little'))
+
     for x in graphic["bytes"]:
         assert x < 256
         imageBytes.extend(x.to_bytes(1, byteorder='little'))
@@ -182,6 +257,8 @@ def main():
         print()
         print("<filename>_<width>x<height> - rectangle or square tiles of size <width> and <height>, example 'spritemap_8_16', tile size of 8x16 sprite map")
         print()
+        print("<filename>_<width>x<height>.json - optional aseprite data export with per-frame durations for a sprite map")
+        print()
         print("order of tiles starts with top-left being 0, left->right incrementing per row")
 
     directory = sys.argv[1]
@@ -232,8 +309,11 @@ def main():
             packBytes = list()
             spanBytes = list()
 
+            lzBytes = list()
+
             packOffsets = list()
             spanOffsets = list()
+            lzOffsets = list()
 
             for y in range(0, mapHeight):
                 for x in range(0, mapWidth):
@@ -241,19 +321,26 @@ def main():
                         (x * tileWidth, y * tileHeight, x * tileWidth + tileWidth, y * tileHeight + tileHeight))
 
                     packOffsets.append(len(packBytes))
-                    packPixels(sprite, petGraphic["alpha"], packBytes)
+                    spritePack = list()
+                    packPixels(sprite, petGraphic["alpha"], spritePack)
+                    packBytes.extend(spritePack)
+
+                    # frames are compressed separately so each can be decoded on its own
+                    lzOffsets.append(len(lzBytes))
+                    lzCompress(spritePack, lzBytes)
 
                     spanOffsets.append(len(spanBytes))
                     spanPixels(sprite, petGraphic["alpha"], spanBytes)
 
-            if len(spanBytes) > len(packBytes):
-                petGraphic["encoding"] = "pack"
-                petGraphic["bytes"] = packBytes
-                petGraphic["frame_offsets"] = packOffsets
-            else:
-                petGraphic["encoding"] = "span"
-                petGraphic["bytes"] = spanBytes
-                petGraphic["frame_offsets"] = spanOffsets
+            durations = loadFrameDurations(imageFile, petGraphic["tile_count"])
+            if durations is not None:
+                petGraphic["frame_durations"] = durations
+
+            encodings = [("span", spanBytes, spanOffsets), ("pack", packBytes, packOffsets), ("lz", lzBytes, lzOffsets)]
+            encoding, encodedBytes, offsets = min(encodings, key=lambda e: len(e[1]))
+            petGraphic["encoding"] = encoding
+            petGraphic["bytes"] = encodedBytes
+            petGraphic["frame_offsets"] = offsets
 
         # not a sprite map
         else:
@@ -265,17 +352,18 @@ def main():
             packBytes = list()
             spanBytes = list()
 
+            lzBytes = list()
+
             packPixels(image, petGraphic["alpha"], packBytes)
             spanPixels(image, petGraphic["alpha"], spanBytes)
+            lzCompress(packBytes, lzBytes)
 
-            if len(spanBytes) > len(packBytes):
-                petGraphic["encoding"] = "pack"
-                petGraphic["bytes"] = packBytes
-            else:
-                petGraphic["encoding"] = "span"
-                petGraphic["bytes"] = spanBytes
+            encodings = [("span", spanBytes), ("pack", packBytes), ("lz", lzBytes)]
+            encoding, encodedBytes = min(encodings, key=lambda e: len(e[1]))
+            petGraphic["encoding"] = encoding
+            petGraphic["bytes"] = encodedBytes
 
-        print("{0} -> span:{1}, pack:{2}".format(petGraphic["name"], len(spanBytes), len(packBytes)))
+        print("{0} -> span:{1}, pack:{2}, lz:{3}".format(petGraphic["name"], len(spanBytes), len(packBytes), len(lzBytes)))
 
         serializedBytes = toBytes(petGraphic)
         totalBytes += len(serializedBytes)
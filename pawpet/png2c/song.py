@@ -0,0 +1,254 @@
+import os
+import sys
+
+# compiles an MML-like text song into the binary song format played by games/src/music.rs
+#
+#   ; comment to the end of the line
+#   t120        tempo in beats per minute (1-255), a beat is a quarter note
+#   o4          octave (1-8), o4 c is middle C
+#   < >         octave down, up
+#   l8          default note length, 1 whole, 2 half, 4 quarter, also 3 6 8 12 16 24 48, dots add
+#               half: l8.
+#   c d e f g a b
+#               note, + or # sharp, - flat, then an optional length and dots: c+8. e-
+#   r           rest, optional length and dots like a note
+#   q7          gate, eighths of a note that sound (1-8), the rest is silent
+#   @a4,7       arpeggio, semitones above the note cycled for fake chords, @a0 turns it off
+#   [ ... ]3    loop 3 times, [ ... ] without a count loops forever
+#
+#   t140 o4 l8 q6
+#   [ c e g >c< ]2 @a4,7 c2 @a0 r4
+
+MAGIC = b"PSNG"
+VERSION = 1
+TICKS_PER_WHOLE = 48
+DEFAULT_TEMPO = 120
+MAX_LOOP_DEPTH = 4
+
+FIRST_NOTE = 33  # midi note of TONE_NOTES[0], A1 55 Hz
+NOTE_COUNT = 80
+REST = 0x80
+TEMPO = 0x81
+LOOP = 0x82
+END_LOOP = 0x83
+ARPEGGIO = 0x84
+GATE = 0x85
+
+SEMITONES = {"c": 0, "d": 2, "e": 4, "f": 5, "g": 7, "a": 9, "b": 11}
+
+
+class SongError(Exception):
+    pass
+
+
+class Reader:
+    def __init__(self, text):
+        self.text = text
+        self.at = 0
+
+    def position(self):
+        line = self.text.count("\n", 0, self.at) + 1
+        column = self.at - (self.text.rfind("\n", 0, self.at) + 1) + 1
+        return "{0}:{1}".format(line, column)
+
+    def error(self, message):
+        return SongError("{0}: {1}".format(self.position(), message))
+
+    def peek(self):
+        if self.at < len(self.text):
+            return self.text[self.at]
+        return ""
+
+    def next(self):
+        c = self.peek()
+        self.at += 1
+        return c
+
+    def skip(self):
+        while self.at < len(self.text):
+            c = self.text[self.at]
+            if c == ";":
+                while self.at < len(self.text) and self.text[self.at] != "\n":
+                    self.at += 1
+            elif c.isspace():
+                self.at += 1
+            else:
+                break
+
+    def number(self):
+        start = self.at
+        while self.peek().isdigit():
+            self.at += 1
+        if start == self.at:
+            return None
+        return int(self.text[start:self.at])
+
+
+class Compiler:
+    def __init__(self):
+        self.tempo = DEFAULT_TEMPO
+        self.octave = 4
+        self.length = TICKS_PER_WHOLE // 4
+        self.events = bytearray()
+        self.loops = list()  # event offset of each open loop
+
+    # length number and dots, the default length if there is no number
+    def readLength(self, reader, default):
+        number = reader.number()
+        if number is None:
+            ticks = default
+        else:
+            if number == 0 or TICKS_PER_WHOLE % number != 0:
+                raise reader.error("length {0} is not a whole number of ticks".format(number))
+            ticks = TICKS_PER_WHOLE // number
+
+        added = ticks
+        while reader.peek() == ".":
+            reader.next()
+            if added % 2 != 0:
+                raise reader.error("dotted length is not a whole number of ticks")
+            added //= 2
+            ticks += added
+        return ticks
+
+    def timed(self, reader, event, ticks):
+        if ticks > 255:
+            raise reader.error("length of {0} ticks is longer than 255".format(ticks))
+        self.events.append(event)
+        self.events.append(ticks)
+
+    def command(self, reader):
+        c = reader.next().lower()
+
+        if c in SEMITONES:
+            semitone = SEMITONES[c]
+            while reader.peek() in ("+", "#", "-"):
+                semitone += -1 if reader.next() == "-" else 1
+
+            index = (self.octave + 1) * 12 + semitone - FIRST_NOTE
+            if index < 0 or index >= NOTE_COUNT:
+                raise reader.error("note is outside the beeper range (a1 - e8)")
+            self.timed(reader, index, self.readLength(reader, self.length))
+        elif c == "r":
+            self.timed(reader, REST, self.readLength(reader, self.length))
+        elif c == "l":
+            if not reader.peek().isdigit():
+                raise reader.error("l needs a length")
+            self.length = self.readLength(reader, self.length)
+        elif c == "o":
+            octave = reader.number()
+            if octave is None or not (1 <= octave <= 8):
+                raise reader.error("octave must be 1-8")
+            self.octave = octave
+        elif c == "<":
+            self.octave -= 1
+        elif c == ">":
+            self.octave += 1
+        elif c == "t":
+            tempo = reader.number()
+            if tempo is None or not (1 <= tempo <= 255):
+                raise reader.error("tempo must be 1-255")
+            # before the first event the header holds the tempo
+            if len(self.events) == 0:
+                self.tempo = tempo
+            else:
+                self.events.extend([TEMPO, tempo])
+        elif c == "q":
+            gate = reader.number()
+            if gate is None or not (1 <= gate <= 8):
+                raise reader.error("gate must be 1-8")
+            self.events.extend([GATE, gate])
+        elif c == "@":
+            if reader.next().lower() != "a":
+                raise reader.error("unknown command @, only @a (arpeggio)")
+            first = reader.number() or 0
+            second = 0
+            if reader.peek() == ",":
+                reader.next()
+                second = reader.number()
+                if second is None:
+                    raise reader.error("missing second arpeggio note")
+            if first > 15 or second > 15:
+                raise reader.error("arpeggio notes must be 0-15 semitones")
+            self.events.extend([ARPEGGIO, (first << 4) | second])
+        elif c == "[":
+            if len(self.loops) == MAX_LOOP_DEPTH:
+                raise reader.error("loops nest deeper than {0}".format(MAX_LOOP_DEPTH))
+            self.loops.append(len(self.events))
+            self.events.extend([LOOP, 0])
+        elif c == "]":
+            if len(self.loops) == 0:
+                raise reader.error("] without [")
+            start = self.loops.pop()
+            count = reader.number()
+            if count is None:
+                count = 0
+                if not any(self.events[i] < NOTE_COUNT or self.events[i] == REST for i in self.eventOffsets(start + 2)):
+                    raise reader.error("endless loop without notes or rests")
+            elif not (1 <= count <= 255):
+                raise reader.error("loop count must be 1-255")
+            self.events[start + 1] = count
+            self.events.append(END_LOOP)
+        else:
+            raise reader.error("unknown command '{0}'".format(c))
+
+        if self.octave < 1 or self.octave > 8:
+            raise reader.error("octave must be 1-8")
+
+    # offsets of the events from start, END_LOOP is the only single byte event
+    def eventOffsets(self, start):
+        at = start
+        while at < len(self.events):
+            yield at
+            at += 1 if self.events[at] == END_LOOP else 2
+
+    def compile(self, text):
+        reader = Reader(text)
+        reader.skip()
+        while reader.peek() != "":
+            self.command(reader)
+            reader.skip()
+
+        if len(self.loops) > 0:
+            raise reader.error("[ without ]")
+        if len(self.events) > 0xFFFF:
+            raise reader.error("song is larger than 64KB")
+
+    def toBytes(self):
+        out = bytearray()
+        out.extend(MAGIC)
+        out.append(VERSION)
+        out.append(self.tempo)
+        out.extend(len(self.events).to_bytes(2, byteorder='little'))
+        out.extend(self.events)
+        return out
+
+
+def main():
+    if len(sys.argv) != 3:
+        print("usage: song [song.mml] [output.sng]")
+        print("songs go in the sprites directory with the other files uploaded by pawcon, startup.sng replaces the startup beeps")
+        sys.exit(1)
+
+    songFile = sys.argv[1]
+    outFile = sys.argv[2]
+
+    try:
+        with open(songFile, "r", encoding="utf-8") as f:
+            text = f.read()
+        compiler = Compiler()
+        compiler.compile(text)
+        serializedBytes = compiler.toBytes()
+    except (SongError, OSError) as e:
+        print("ERROR: {0}: {1}".format(songFile, e))
+        sys.exit(1)
+
+    with open(outFile, "wb") as f:
+        f.write(serializedBytes)
+
+    name = os.path.splitext(os.path.basename(outFile))[0]
+    print("{0} - tempo:{1} bytes:{2}".format(name, compiler.tempo, len(serializedBytes)))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,186 @@
+import json
+import os
+import sys
+
+# compiles a species/evolution definition (json) into the binary format loaded by games/src/species.rs
+# sprites referenced by the stages are validated against the .paw files in the build directory
+#
+# {
+#     "stages": [
+#         {
+#             "name": "egg",
+#             "sprite": "egg_wobble",
+#             "duration_min": 60,
+#             "tags": { "idle": [0, 4, 8] },
+#             "branches": [ { "min_care": 0, "next": "baby" } ]
+#         },
+#         ...
+#     ]
+# }
+#
+# tags are [start frame, end frame (exclusive), ticks per frame (1/15 s)], stages without branches are final.
+# the duration of a final stage is the lifespan before dying of old age, 0 (or omitted) lives forever.
+# branches are checked in order, the first one with min_care at or below the average care is taken.
+
+MAGIC = b"PEVO"
+VERSION = 1
+KEY_SIZE = 16
+TAGS = ["idle", "walk", "sit", "sleep"]
+
+
+class SpeciesError(Exception):
+    pass
+
+
+def readTileCount(pawFile):
+    with open(pawFile, "rb") as f:
+        header = f.read(8)
+    if len(header) < 8:
+        raise SpeciesError("'{0}' is not a valid .paw file".format(pawFile))
+    return int.from_bytes(header[6:8], byteorder='little')
+
+
+def validate(definition, spritePath):
+    stages = definition.get("stages", [])
+
+    if len(stages) == 0:
+        raise SpeciesError("no stages defined")
+    if len(stages) > 255:
+        raise SpeciesError("too many stages")
+
+    names = [s["name"] for s in stages]
+    if len(set(names)) != len(names):
+        raise SpeciesError("duplicate stage names")
+
+    branchCount = 0
+    for stage in stages:
+        name = stage["name"]
+        sprite = stage["sprite"]
+
+        if len(sprite) > KEY_SIZE or len(sprite) == 0:
+            raise SpeciesError("{0}: sprite key '{1}' must be 1-16 characters".format(name, sprite))
+        if not all(" " <= c <= "~" for c in sprite):
+            raise SpeciesError("{0}: sprite key '{1}' must be printable ascii".format(name, sprite))
+
+        pawFile = os.path.join(spritePath, sprite + ".paw")
+        if not os.path.isfile(pawFile):
+            raise SpeciesError("{0}: sprite '{1}' not found in {2}".format(name, sprite, spritePath))
+
+        tileCount = readTileCount(pawFile)
+
+        tags = stage.get("tags", {})
+        if "idle" not in tags:
+            raise SpeciesError("{0}: missing idle animation tag".format(name))
+
+        for tag, value in tags.items():
+            if tag not in TAGS:
+                raise SpeciesError("{0}: unknown tag '{1}', expected one of {2}".format(name, tag, TAGS))
+            start, end, ticks = value
+            if not (0 <= start < end <= tileCount):
+                raise SpeciesError("{0}: tag '{1}' frames {2}-{3} outside of {4} tiles".format(
+                    name, tag, start, end, tileCount))
+            if not (0 < ticks < 256):
+                raise SpeciesError("{0}: tag '{1}' ticks per frame must be 1-255".format(name, tag))
+
+        duration = stage.get("duration_min", 0)
+        branches = stage.get("branches", [])
+
+        if not (0 <= duration < 65536):
+            raise SpeciesError("{0}: duration_min must fit in 16 bits".format(name))
+        if len(branches) > 0 and duration == 0:
+            raise SpeciesError("{0}: stage with branches needs a duration".format(name))
+        if len(branches) > 0 and branches[-1]["min_care"] != 0:
+            raise SpeciesError("{0}: last branch must have min_care 0 so evolution cannot stall".format(name))
+
+        for branch in branches:
+            if branch["next"] not in names:
+                raise SpeciesError("{0}: branch to unknown stage '{1}'".format(name, branch["next"]))
+            if not (0 <= branch["min_care"] <= 100):
+                raise SpeciesError("{0}: min_care must be 0-100".format(name))
+
+        branchCount += len(branches)
+
+    if branchCount > 255:
+        raise SpeciesError("too many branches")
+
+    # warn on stages that can never be reached from the first stage
+    reachable = {names[0]}
+    pending = [stages[0]]
+    while len(pending) > 0:
+        stage = pending.pop()
+        for branch in stage.get("branches", []):
+            if branch["next"] not in reachable:
+                reachable.add(branch["next"])
+                pending.append(stages[names.index(branch["next"])])
+
+    for name in names:
+        if name not in reachable:
+            print("WARN: stage '{0}' is unreachable".format(name))
+
+
+def toBytes(definition):
+    stages = definition["stages"]
+    names = [s["name"] for s in stages]
+
+    branchBytes = bytearray()
+    stageBytes = bytearray()
+    branchCount = 0
+
+    for stage in stages:
+        key = stage["sprite"].encode("utf-8")
+        stageBytes.extend(key + bytes(KEY_SIZE - len(key)))
+        stageBytes.extend(stage.get("duration_min", 0).to_bytes(2, byteorder='little'))
+
+        branches = stage.get("branches", [])
+        stageBytes.append(branchCount)
+        stageBytes.append(len(branches))
+
+        tags = stage.get("tags", {})
+        for tag in TAGS:
+            stageBytes.extend(bytes(tags.get(tag, [0, 0, 0])))
+
+        for branch in branches:
+            branchBytes.append(branch["min_care"])
+            branchBytes.append(names.index(branch["next"]))
+            branchBytes.extend(bytes(2))
+        branchCount += len(branches)
+
+    out = bytearray()
+    out.extend(MAGIC)
+    out.append(VERSION)
+    out.append(len(stages))
+    out.append(branchCount)
+    out.append(0)
+    out.extend(stageBytes)
+    out.extend(branchBytes)
+    return out
+
+
+def main():
+    if len(sys.argv) != 3:
+        print("usage: species [species.json] [sprite_build_directory]")
+        print("validates the species definition against the built .paw sprites and writes species.evo")
+        sys.exit(1)
+
+    definitionFile = sys.argv[1]
+    buildpath = sys.argv[2]
+
+    with open(definitionFile, "r") as f:
+        definition = json.load(f)
+
+    try:
+        validate(definition, buildpath)
+    except (SpeciesError, KeyError, ValueError, TypeError) as e:
+        print("ERROR: {0}: {1}".format(definitionFile, e))
+        sys.exit(1)
+
+    serializedBytes = toBytes(definition)
+
+    with open(os.path.join(buildpath, "species.evo"), "wb") as f:
+        f.write(serializedBytes)
+
+    print("species - stages:{0} bytes:{1}".format(len(definition["stages"]), len(serializedBytes)))
+
+
+if __name__ == "__main__":
+    main()
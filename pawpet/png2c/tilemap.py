@@ -0,0 +1,115 @@
+import csv
+import os
+import sys
+
+# compiles a tile map (csv, as exported by Tiled) into the binary format loaded by games/src/tilemap.rs
+# tile indices are frames of an 8x8 sprite map built by png2c, validated against the .paw in the build directory
+#
+#   0,0,1,1,2
+#   0,-1,1,3,2
+#
+# -1 or an empty cell is an empty tile, every row must have the same number of tiles (1-255).
+
+MAGIC = b"TMAP"
+VERSION = 1
+EMPTY_TILE = 0xFF
+TILE_SIZE = 8
+# pawcon uploads these by file name without the extension, cut to 16 characters
+STORAGE_EXTENSIONS = (".paw", ".evo", ".map", ".fnt", ".sng")
+STORAGE_KEY_LENGTH = 16
+
+
+class TileMapError(Exception):
+    pass
+
+
+def readTileSheet(pawFile):
+    with open(pawFile, "rb") as f:
+        header = f.read(8)
+    if len(header) < 8:
+        raise TileMapError("'{0}' is not a valid .paw file".format(pawFile))
+
+    width = int.from_bytes(header[0:2], byteorder='little')
+    height = int.from_bytes(header[2:4], byteorder='little')
+    if width != TILE_SIZE or height != TILE_SIZE:
+        raise TileMapError("'{0}' tiles are {1}x{2}, expected 8x8".format(pawFile, width, height))
+    return int.from_bytes(header[6:8], byteorder='little')
+
+
+def readRows(mapFile):
+    rows = list()
+    with open(mapFile, "r", newline="") as f:
+        for row in csv.reader(f):
+            if len(row) == 0:
+                continue
+            rows.append([EMPTY_TILE if cell.strip() in ("", "-1") else int(cell) for cell in row])
+    return rows
+
+
+def validate(rows, tileCount):
+    if len(rows) == 0:
+        raise TileMapError("empty map")
+    if len(rows) > 255 or len(rows[0]) > 255:
+        raise TileMapError("map is larger than 255x255 tiles")
+
+    for y, row in enumerate(rows):
+        if len(row) != len(rows[0]):
+            raise TileMapError("row {0} has {1} tiles, expected {2}".format(y, len(row), len(rows[0])))
+        for tile in row:
+            if tile != EMPTY_TILE and not (0 <= tile < min(tileCount, EMPTY_TILE)):
+                raise TileMapError("row {0}: tile {1} is not in the tile sheet ({2} tiles)".format(y, tile, tileCount))
+
+
+# another built file with the same storage key would replace the map on upload
+def checkKey(name, buildpath):
+    key = name[:STORAGE_KEY_LENGTH]
+    for entry in os.scandir(buildpath):
+        stem, extension = os.path.splitext(entry.name)
+        if extension in STORAGE_EXTENSIONS and entry.name != name + ".map" and stem[:STORAGE_KEY_LENGTH] == key:
+            raise TileMapError("storage key '{0}' is already used by '{1}', rename the map".format(key, entry.name))
+
+
+def toBytes(rows):
+    out = bytearray()
+    out.extend(MAGIC)
+    out.append(VERSION)
+    out.append(len(rows[0]))
+    out.append(len(rows))
+    out.append(0)
+    for row in rows:
+        out.extend(row)
+    return out
+
+
+def main():
+    if len(sys.argv) != 4:
+        print("usage: tilemap [map.csv] [tile_sheet_name] [sprite_build_directory]")
+        print("validates the map against the built tile sheet and writes <map>.map")
+        print("<map> must not share its name with another built file, they are stored without extensions")
+        sys.exit(1)
+
+    mapFile = sys.argv[1]
+    sheet = sys.argv[2]
+    buildpath = sys.argv[3]
+
+    name = os.path.splitext(os.path.basename(mapFile))[0]
+
+    try:
+        tileCount = readTileSheet(os.path.join(buildpath, sheet + ".paw"))
+        rows = readRows(mapFile)
+        validate(rows, tileCount)
+        checkKey(name, buildpath)
+    except (TileMapError, OSError, ValueError) as e:
+        print("ERROR: {0}: {1}".format(mapFile, e))
+        sys.exit(1)
+
+    serializedBytes = toBytes(rows)
+
+    with open(os.path.join(buildpath, name + ".map"), "wb") as f:
+        f.write(serializedBytes)
+
+    print("{0} - {1}x{2} tiles bytes:{3}".format(name, len(rows[0]), len(rows), len(serializedBytes)))
+
+
+if __name__ == "__main__":
+    main()
@@ -58,3 +58,8 @@ foreach ($file in $animations) {
 # invoke png -> .paw file conversion
 # TODO new rust export program for converting files from png to internal format
 Start-Process python -ArgumentList ("$projectRoot/png2c/png2c.py $projectRoot/../sprites/png $projectRoot/../sprites/") -NoNewWindow -Wait
+
+# compile and validate species/evolution definition against the converted sprites
+if (Test-Path "$projectRoot/../sprites/species.json") {
+    Start-Process python -ArgumentList ("$projectRoot/png2c/species.py $projectRoot/../sprites/species.json $projectRoot/../sprites/") -NoNewWindow -Wait
+}
@@ -154,10 +154,11 @@ impl pawdevicetraits::SysTimerDevice for SysTimerSim {
 pub struct DisplaySim {
     canvas: CanvasRenderingContext2d,
     scale: f64,
+    rotation: u8,
 }
 impl DisplaySim {
     pub fn new(canvas: CanvasRenderingContext2d) -> Self {
-        Self { canvas, scale: 2.0 }
+        Self { canvas, scale: 2.0, rotation: 0 }
     }
 }
 
@@ -165,6 +166,13 @@ impl DisplayDevice for DisplaySim {
     fn draw_pixel(&mut self, x: u8, y: u8, color: bool) {
         let scale = self.scale;
 
+        let (x, y) = match self.rotation {
+            1 => (63 - y, x),
+            2 => (63 - x, 63 - y),
+            3 => (y, 63 - x),
+            _ => (x, y),
+        };
+
         let x64: f64 = (x as f64) * scale;
         let y64: f64 = (y as f64) * scale;
 
@@ -180,6 +188,15 @@ impl DisplayDevice for DisplaySim {
     fn update(&mut self) -> bool {
         return true;
     }
+
+    fn set_rotation(&mut self, rotation: u8) {
+        self.rotation = rotation % 4;
+    }
+
+    // canvas is drawn directly, there is no transfer
+    fn update_stats(&self) -> DisplayUpdateStats {
+        return DisplayUpdateStats::default();
+    }
 }
 
 #[derive(Debug)]
@@ -236,6 +253,8 @@ impl ToneSim {
 impl ToneDevice for ToneSim {
     fn tone(&self, freq: u32) {}
     fn no_tone(&self) {}
+    fn set_muted(&self, muted: bool) {}
+    fn set_volume(&self, volume: u8) {}
 }
 
 ///////////////////////////////////////////////////////////////
@@ -251,6 +270,57 @@ impl WatchdogDevice for WatchdogSim {
     fn clear_disable_interrupt(&mut self) {}
     fn start_timeout(&mut self, period: WatchdogTimeouts) {}
     fn sleep(&mut self, period: WatchdogTimeouts) {}
+    fn deep_sleep(&mut self) {}
+}
+
+///////////////////////////////////////////////////////////////
+pub struct RtcSim {
+    offset: i64,
+}
+
+// seconds between the unix epoch and 2000-01-01
+const RTC_EPOCH_OFFSET: i64 = 946_684_800;
+
+impl RtcSim {
+    pub fn new() -> Self {
+        Self { offset: 0 }
+    }
+
+    fn host_seconds() -> i64 {
+        return (js_sys::Date::now() / 1000.0) as i64 - RTC_EPOCH_OFFSET;
+    }
+}
+
+impl RtcDevice for RtcSim {
+    fn get_time(&self) -> u32 {
+        return (RtcSim::host_seconds() + self.offset) as u32;
+    }
+    fn set_time(&mut self, seconds: u32) {
+        self.offset = seconds as i64 - RtcSim::host_seconds();
+    }
+}
+
+///////////////////////////////////////////////////////////////
+pub struct WakeSchedulerSim {
+    events: Vec<(WakeEvent, u32, u32)>,
+}
+
+impl WakeSchedulerSim {
+    pub fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+
+// simulator never sleeps, events are only recorded for logging
+impl WakeSchedulerDevice for WakeSchedulerSim {
+    fn schedule(&mut self, event: WakeEvent, at: u32, period: u32) {
+        self.cancel(event);
+        log!("schedule wake {:?} @{} every {}", event, at, period);
+        self.events.push((event, at, period));
+    }
+    fn cancel(&mut self, event: WakeEvent) {
+        self.events.retain(|e| e.0 != event);
+    }
 }
 
 ///////////////////////////////////////////////////////////////
@@ -272,7 +342,8 @@ impl CacheEntry {
 
 pub struct StorageSim {
     disk: Vec<CacheEntry>,
-    offset: usize
+    offset: usize,
+    save: Vec<u8>,
 }
 
 impl StorageSim {
@@ -280,6 +351,7 @@ impl StorageSim {
         Self {
             disk: Vec::new(),
             offset: 0,
+            save: Vec::new(),
         }
     }
 
@@ -336,4 +408,21 @@ impl StorageDevice for StorageSim {
     {
         // no cache for now, thoughs imulated 16kb cache would be ideal.
     }
+
+    fn load_save(&mut self, data: &mut [u8]) -> usize
+    {
+        if self.save.len() > data.len()
+        {
+            return 0;
+        }
+        data[0..self.save.len()].copy_from_slice(&self.save);
+        return self.save.len();
+    }
+
+    fn write_save(&mut self, data: &[u8]) -> Result<(), FileWriteError>
+    {
+        // kept in memory only, save is lost on page reload
+        self.save = data.to_vec();
+        return Ok(());
+    }
 }
\ No newline at end of file
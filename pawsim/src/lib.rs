@@ -2,9 +2,11 @@ use games::PawRunner;
 use hardware::BatteryMonitorSim;
 use hardware::ButtonsSim;
 use hardware::DisplaySim;
+use hardware::RtcSim;
 use hardware::StorageSim;
 use hardware::SysTimerSim;
 use hardware::ToneSim;
+use hardware::WakeSchedulerSim;
 use hardware::WatchdogSim;
 use std::f64;
 use wasm_bindgen::prelude::*;
@@ -24,7 +26,9 @@ pub struct PawPetSim {
     battery: BatteryMonitorSim,
     buttons: ButtonsSim,
     tone: ToneSim,
-    storage: StorageSim
+    storage: StorageSim,
+    rtc: RtcSim,
+    scheduler: WakeSchedulerSim,
 }
 
 #[wasm_bindgen]
@@ -55,7 +59,11 @@ impl PawPetSim {
             .arc(75.0, 75.0, 50.0, 0.0, f64::consts::PI * 2.0)
             .unwrap();
 
-        let m = PawRunner::new();
+        let mut m = PawRunner::new();
+        // every page load is a fresh power on
+        let mut power_on = [0; pawdevicetraits::RESET_CAUSE_COUNT];
+        power_on[pawdevicetraits::ResetCause::PowerOn as usize] = 1;
+        m.set_reset_stats(resets::ResetStats::from_counts(power_on, pawdevicetraits::ResetCause::PowerOn));
         let display = hardware::DisplaySim::new(context);
         let watchdog = hardware::WatchdogSim::new();
 
@@ -65,6 +73,8 @@ impl PawPetSim {
         let buttons = hardware::ButtonsSim::new();
         let tone = hardware::ToneSim::new();
         let storage = hardware::StorageSim::new();
+        let rtc = hardware::RtcSim::new();
+        let scheduler = hardware::WakeSchedulerSim::new();
 
         Self {
             state: m,
@@ -74,7 +84,9 @@ impl PawPetSim {
             battery,
             buttons,
             tone,
-            storage
+            storage,
+            rtc,
+            scheduler,
         }
     }
 
@@ -87,6 +99,8 @@ impl PawPetSim {
             &mut self.systimer,
             &mut self.battery,
             &mut self.storage,
+            &mut self.rtc,
+            &mut self.scheduler,
         );
 
         // TODO check suspend and other state info and update the simulator UX